@@ -83,7 +83,7 @@ fn node_view(node: NodeRef) -> Node<View> {
         <view style={NODE_STYLE}>
             <describe_node _=(name, node.style(), has_children) />
             <view style={CHILD_STYLE}>
-                {node.children().map(|child| match child {
+                {node.children().map(|child| match child.get_node() {
                     DynamicNode::Node(child) => mox! {
                         <view style={VIEW}>
                             <node_view _=(child) />
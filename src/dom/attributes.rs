@@ -13,3 +13,4 @@ macro_rules! attribute {
 
 attribute!(AttrStyle, Option<Style>);
 attribute!(AttrTitle, String);
+attribute!(AttrKey, String);
@@ -1,10 +1,15 @@
 use crate::dom::input::InputEvent;
-use crate::dom::node::{Node, NodeRef};
+use crate::dom::node::{Node, NodeDataChildrenIter, NodeRef};
+use crate::dom::LayoutEvent;
 use crate::style::{ComputedValues, Style};
 use crate::util::event_handler::EventHandler;
 use std::fmt::Debug;
+use std::rc::Rc;
 
 /// Represents the attributes and behavior of a single DOM element.
+///
+/// Custom elements implement this like the ones in `dom::elements` do.
+/// Layout only sees their computed values and their children.
 pub trait Element: Default + Clone + Debug + PartialEq + 'static {
     /// The type of children that can be parented to this element.
     type Child: NodeChild + Clone + Debug + PartialEq;
@@ -13,7 +18,8 @@ pub trait Element: Default + Clone + Debug + PartialEq + 'static {
 
     const ELEMENT_NAME: &'static str;
 
-    /// Creates default style values
+    /// Creates default style values, before any style is applied.
+    /// Inherited text properties are replaced by the parent's.
     fn create_computed_values(&self) -> ComputedValues {
         Default::default()
     }
@@ -27,8 +33,17 @@ pub trait Element: Default + Clone + Debug + PartialEq + 'static {
         (false, states)
     }
 
+    /// Called after layout when the element's box moved or changed size.
+    fn layout_changed(&self, _handlers: &mut Self::Handlers, _event: &LayoutEvent) {}
+
     /// Returns the list of styles attached to this element.
     fn style(&self) -> Option<Style>;
+
+    /// Identifies the element across renders, so that its layout can be
+    /// matched up between two trees, see `LayoutTreeNode::diff`.
+    fn key(&self) -> Option<&str> {
+        None
+    }
 }
 
 /// The trait representing all events that can be invoked on an element.
@@ -67,7 +82,8 @@ impl ElementStates for () {
 }
 
 pub enum DynamicNode<'a> {
-    Text(&'a str),
+    /// Text is shared with the layout engine instead of copied into it.
+    Text(&'a Rc<str>),
     Node(NodeRef<'a>),
 }
 
@@ -80,9 +96,9 @@ where
     }
 }
 
-impl<'a> From<&'a String> for DynamicNode<'a> {
-    fn from(value: &'a String) -> Self {
-        DynamicNode::Text(&value[..])
+impl<'a> From<&'a Rc<str>> for DynamicNode<'a> {
+    fn from(value: &'a Rc<str>) -> Self {
+        DynamicNode::Text(value)
     }
 }
 
@@ -94,8 +110,20 @@ impl<'a> From<&'a String> for DynamicNode<'a> {
 /// enums don't have to implement Element directly. This trait provides
 /// a sort of visitor pattern which lets the DOM be walked without
 /// having to know the types of each element at each step.
+///
+/// Any subtree can be traversed with `child_nodes()`: it yields each
+/// direct child exactly once, in DOM order (not the order set by the
+/// `order` style property), and yields nothing for text.
 pub trait NodeChild: 'static {
     fn get_node(&self) -> DynamicNode;
+
+    /// Iterates over the direct children of this node.
+    fn child_nodes(&self) -> NodeDataChildrenIter {
+        match self.get_node() {
+            DynamicNode::Node(node) => node.data().children(),
+            DynamicNode::Text(_) => Default::default(),
+        }
+    }
 }
 
 impl<Elt> NodeChild for Node<Elt>
@@ -107,9 +135,9 @@ where
     }
 }
 
-impl NodeChild for String {
+impl NodeChild for Rc<str> {
     fn get_node(&self) -> DynamicNode {
-        DynamicNode::Text(&self[..])
+        DynamicNode::Text(self)
     }
 }
 
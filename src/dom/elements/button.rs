@@ -1,6 +1,6 @@
 use crate::dom::element::{Element, ElementStates, HasEvent};
 use crate::dom::input::{InputEvent, State};
-use crate::dom::{AttrStyle, ClickEvent, Node, Span, View};
+use crate::dom::{AttrKey, AttrStyle, ClickEvent, LayoutEvent, Node, Spacer, Span, View};
 use crate::style::Style;
 use crate::util::event_handler::EventHandler;
 
@@ -9,6 +9,7 @@ use crate::util::event_handler::EventHandler;
 #[derive(Default, Clone, Debug, PartialEq)]
 pub struct Button {
     style: Option<Style>,
+    key: Option<String>,
 }
 
 multiple_children! {
@@ -16,18 +17,21 @@ multiple_children! {
         Button(Node<Button>),
         View(Node<View>),
         Span(Node<Span>),
+        Spacer(Node<Spacer>),
     }
 }
 
 element_attributes! {
     Button {
         style: AttrStyle,
+        key: AttrKey,
     }
 }
 
 element_handlers! {
     ButtonHandlers for Button {
         on_click: ClickEvent,
+        on_layout: LayoutEvent,
     }
 }
 
@@ -94,7 +98,15 @@ impl Element for Button {
         }
     }
 
+    fn layout_changed(&self, handlers: &mut ButtonHandlers, event: &LayoutEvent) {
+        handlers.on_layout.invoke(event);
+    }
+
     fn style(&self) -> Option<Style> {
         self.style
     }
+
+    fn key(&self) -> Option<&str> {
+        self.key.as_ref().map(String::as_str)
+    }
 }
@@ -68,6 +68,7 @@ macro_rules! element_handlers {
 
 pub mod app;
 pub mod button;
+pub mod spacer;
 pub mod span;
 pub mod view;
 pub mod window;
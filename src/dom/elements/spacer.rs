@@ -0,0 +1,39 @@
+use crate::dom::element::Element;
+use crate::dom::{AttrStyle, Node, View};
+use crate::style::{BlockValues, ComputedValues, DisplayType, Style};
+
+/// Corresponds to <spacer>. Takes up no space of its own, but grows to
+/// fill whatever is left along its parent's direction when the parent
+/// has a fixed size, e.g. to push a button to the end of a toolbar.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct Spacer {
+    style: Option<Style>,
+}
+
+element_attributes! {
+    Spacer {
+        style: AttrStyle,
+    }
+}
+
+impl Element for Spacer {
+    type Child = Node<View>;
+    type Handlers = ();
+    type States = ();
+
+    const ELEMENT_NAME: &'static str = "spacer";
+
+    fn create_computed_values(&self) -> ComputedValues {
+        ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                grow: true,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn style(&self) -> Option<Style> {
+        self.style
+    }
+}
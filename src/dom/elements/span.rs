@@ -1,23 +1,26 @@
 use crate::dom::element::Element;
-use crate::dom::{AttrStyle, Button, Node, View};
+use crate::dom::{AttrKey, AttrStyle, Button, Node, View};
 use crate::style::{ComputedValues, DisplayType, InlineValues, Style};
+use std::rc::Rc;
 
 /// Corresponds to <span>. This element is typically used for inline
 /// layout of text.
 #[derive(Default, Clone, Debug, PartialEq)]
 pub struct Span {
     style: Option<Style>,
+    key: Option<String>,
 }
 
 element_attributes! {
     Span {
         style: AttrStyle,
+        key: AttrKey,
     }
 }
 
 multiple_children! {
     enum SpanChild {
-        Text(String),
+        Text(Rc<str>),
         Button(Node<Button>),
         View(Node<View>),
         Span(Node<Span>),
@@ -33,7 +36,7 @@ impl Element for Span {
 
     fn create_computed_values(&self) -> ComputedValues {
         ComputedValues {
-            display: DisplayType::Inline(InlineValues {}),
+            display: DisplayType::Inline(InlineValues::default()),
             ..Default::default()
         }
     }
@@ -41,4 +44,8 @@ impl Element for Span {
     fn style(&self) -> Option<Style> {
         self.style
     }
+
+    fn key(&self) -> Option<&str> {
+        self.key.as_ref().map(String::as_str)
+    }
 }
@@ -1,12 +1,14 @@
-use crate::dom::element::Element;
-use crate::dom::{AttrStyle, Button, Node, Span};
+use crate::dom::element::{Element, HasEvent};
+use crate::dom::{AttrKey, AttrStyle, Button, LayoutEvent, Node, Spacer, Span};
 use crate::style::{ComputedValues, Style};
+use crate::util::event_handler::EventHandler;
 use crate::Color;
 
 /// Corresponds to <view>. Generic frame for layout purposes.
 #[derive(Default, Clone, Debug, PartialEq)]
 pub struct View {
     style: Option<Style>,
+    key: Option<String>,
 }
 
 multiple_children! {
@@ -14,18 +16,26 @@ multiple_children! {
         Button(Node<Button>),
         View(Node<View>),
         Span(Node<Span>),
+        Spacer(Node<Spacer>),
     }
 }
 
 element_attributes! {
     View {
         style: AttrStyle,
+        key: AttrKey,
+    }
+}
+
+element_handlers! {
+    ViewHandlers for View {
+        on_layout: LayoutEvent,
     }
 }
 
 impl Element for View {
     type Child = ViewChild;
-    type Handlers = ();
+    type Handlers = ViewHandlers;
     type States = ();
 
     const ELEMENT_NAME: &'static str = "view";
@@ -37,7 +47,15 @@ impl Element for View {
         }
     }
 
+    fn layout_changed(&self, handlers: &mut ViewHandlers, event: &LayoutEvent) {
+        handlers.on_layout.invoke(event);
+    }
+
     fn style(&self) -> Option<Style> {
         self.style
     }
+
+    fn key(&self) -> Option<&str> {
+        self.key.as_ref().map(String::as_str)
+    }
 }
@@ -1,6 +1,16 @@
 use super::element::Event;
+use crate::layout::LogicalRect;
 
 /// The element associated with this event was activated by the user.
 pub struct ClickEvent;
 
 impl Event for ClickEvent {}
+
+/// The element was laid out at a new position or size. The rect is
+/// relative to the window.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LayoutEvent {
+    pub rect: LogicalRect,
+}
+
+impl Event for LayoutEvent {}
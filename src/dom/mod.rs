@@ -11,6 +11,8 @@ pub mod input;
 pub mod node;
 
 pub use attributes::*;
-pub use elements::{app::App, button::Button, span::Span, view::View, window::Window};
+pub use elements::{
+    app::App, button::Button, spacer::Spacer, span::Span, view::View, window::Window,
+};
 pub use events::*;
 pub use node::Node;
@@ -1,5 +1,6 @@
-use crate::dom::element::{DynamicNode, Element, ElementStates, NodeChild};
+use crate::dom::element::{Element, ElementStates, NodeChild};
 use crate::dom::input::InputEvent;
+use crate::dom::LayoutEvent;
 use crate::style::{ComputedValues, Style};
 use std::any::{type_name, TypeId};
 use std::cell::{Cell, RefCell};
@@ -16,6 +17,9 @@ where
     handlers: RefCell<Elt::Handlers>,
     states: Cell<Elt::States>,
     computed_values: Cell<Option<ComputedValues>>,
+    /// Whether styling changed anything in this subtree last time, see
+    /// `AnyNodeData::style_changed`.
+    style_changed: Cell<bool>,
     children: Vec<Elt::Child>,
 }
 
@@ -45,6 +49,7 @@ where
             handlers: RefCell::new(Default::default()),
             states: Cell::new(Default::default()),
             computed_values: Cell::new(None),
+            style_changed: Cell::new(true),
             children: children,
         }
     }
@@ -72,16 +77,19 @@ where
     }
 }
 
+/// Iterates over the direct children of a node in DOM order. Created by
+/// `NodeChild::child_nodes()` or `AnyNodeData::children()`.
+#[derive(Default)]
 pub struct NodeDataChildrenIter<'a> {
-    node: &'a dyn AnyNodeData,
+    node: Option<&'a dyn AnyNodeData>,
     index: usize,
 }
 
 impl<'a> Iterator for NodeDataChildrenIter<'a> {
-    type Item = DynamicNode<'a>;
+    type Item = &'a dyn NodeChild;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let result = self.node.get_child(self.index);
+        let result = self.node?.get_child(self.index);
         self.index += 1;
         result
     }
@@ -89,11 +97,18 @@ impl<'a> Iterator for NodeDataChildrenIter<'a> {
 
 pub trait AnyNodeData: Debug {
     fn computed_values(&self) -> &Cell<Option<ComputedValues>>;
-    fn get_child(&self, index: usize) -> Option<DynamicNode>;
+    /// Cleared by the style engine when the last styling left the
+    /// computed values of this node and every node below it as they
+    /// were, which lets layout reuse the whole subtree. Nodes that were
+    /// never styled by it count as changed.
+    fn style_changed(&self) -> &Cell<bool>;
+    fn get_child(&self, index: usize) -> Option<&dyn NodeChild>;
     fn children(&self) -> NodeDataChildrenIter;
     fn process(&self, event: &InputEvent) -> bool;
+    fn layout_changed(&self, event: &LayoutEvent);
     fn create_computed_values(&self) -> ComputedValues;
     fn style(&self) -> Option<Style>;
+    fn key(&self) -> Option<&str>;
     fn has_state(&self, key: &str) -> bool;
     fn type_id(&self) -> TypeId;
     fn name(&self) -> &'static str;
@@ -107,13 +122,19 @@ where
         &self.computed_values
     }
 
-    fn get_child(&self, index: usize) -> Option<DynamicNode> {
-        self.children.get(index).map(|child| child.get_node())
+    fn style_changed(&self) -> &Cell<bool> {
+        &self.style_changed
+    }
+
+    fn get_child(&self, index: usize) -> Option<&dyn NodeChild> {
+        self.children
+            .get(index)
+            .map(|child| child as &dyn NodeChild)
     }
 
     fn children(&self) -> NodeDataChildrenIter {
         NodeDataChildrenIter {
-            node: self,
+            node: Some(self),
             index: 0,
         }
     }
@@ -127,6 +148,11 @@ where
         sink
     }
 
+    fn layout_changed(&self, event: &LayoutEvent) {
+        let mut handlers = self.handlers.borrow_mut();
+        self.element.layout_changed(&mut *handlers, event);
+    }
+
     fn create_computed_values(&self) -> ComputedValues {
         self.element.create_computed_values()
     }
@@ -135,6 +161,10 @@ where
         self.element.style()
     }
 
+    fn key(&self) -> Option<&str> {
+        self.element.key()
+    }
+
     fn has_state(&self, key: &str) -> bool {
         self.states.get().has_state(key)
     }
@@ -259,6 +289,12 @@ impl<'a> NodeRef<'a> {
     pub fn to_owned(&self) -> AnyNode {
         self.0.to_owned()
     }
+
+    /// Returns the node data for the full lifetime of the reference,
+    /// rather than for the lifetime of this handle.
+    pub fn data(self) -> &'a dyn AnyNodeData {
+        self.0.node_data()
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -287,9 +323,43 @@ impl PartialEq for AnyNode {
     }
 }
 
+impl Eq for AnyNode {}
+
 impl Hash for AnyNode {
     fn hash<H: Hasher>(&self, state: &mut H) {
         let raw: *const dyn AnyNodeData = &*self.0;
         raw.hash(state);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::Node;
+    use crate::dom::element::NodeChild;
+    use crate::dom::{Button, Span, View};
+    use std::rc::Rc;
+
+    fn count(child: &dyn NodeChild) -> usize {
+        1 + child.child_nodes().map(count).sum::<usize>()
+    }
+
+    #[test]
+    fn traverses_mixed_tree() {
+        let span = Node::new(
+            Span::default(),
+            vec![
+                Rc::<str>::from("hello").into(),
+                Node::new(Button::default(), vec![]).into(),
+                Rc::<str>::from("world").into(),
+            ],
+        );
+        let root = Node::new(
+            View::default(),
+            vec![span.into(), Node::new(View::default(), vec![]).into()],
+        );
+
+        assert_eq!(count(&root), 6);
+        assert_eq!(root.child_nodes().count(), 2);
+        assert_eq!(Rc::<str>::from("text").child_nodes().count(), 0);
+    }
+}
@@ -0,0 +1,202 @@
+use super::LogicalLength;
+use crate::style::ComputedValues;
+use crate::Color;
+use std::ops::Range;
+use std::rc::Rc;
+
+/// Changes a span of an `AttributedText` makes to the style of the
+/// element the text is laid out for. Unset fields keep its style.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RunStyle {
+    pub text_color: Option<Color>,
+    pub font_weight: Option<u32>,
+    pub text_size: Option<LogicalLength>,
+}
+
+impl RunStyle {
+    /// `self` on top of `under`, for spans that overlap.
+    fn over(self, under: RunStyle) -> RunStyle {
+        RunStyle {
+            text_color: self.text_color.or(under.text_color),
+            font_weight: self.font_weight.or(under.font_weight),
+            text_size: self.text_size.or(under.text_size),
+        }
+    }
+
+    /// The values of an element with its text restyled.
+    pub fn apply(&self, values: &ComputedValues) -> ComputedValues {
+        ComputedValues {
+            text_color: self.text_color.unwrap_or(values.text_color),
+            font_weight: self.font_weight.unwrap_or(values.font_weight),
+            text_size: self.text_size.unwrap_or(values.text_size),
+            ..*values
+        }
+    }
+}
+
+/// A line drawn along a run of text.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextDecoration {
+    Underline,
+    LineThrough,
+}
+
+/// Text with parts of it styled differently, e.g. highlighted source
+/// code, laid out as a single flow of lines by
+/// `LayoutEngine::layout_attributed`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AttributedText {
+    pub text: Rc<str>,
+    /// Byte ranges of the text and their styles. Later spans take
+    /// precedence where they overlap earlier ones.
+    pub spans: Vec<(Range<usize>, RunStyle)>,
+    /// Byte ranges of the text with lines drawn along them. Unlike
+    /// spans, these don't split the text into runs, so a decoration
+    /// starting or ending in the middle of a word leaves its shaping as
+    /// it is.
+    pub decorations: Vec<(Range<usize>, TextDecoration)>,
+}
+
+impl AttributedText {
+    pub fn new(text: impl Into<Rc<str>>) -> AttributedText {
+        AttributedText {
+            text: text.into(),
+            spans: vec![],
+            decorations: vec![],
+        }
+    }
+
+    pub fn with_span(mut self, range: Range<usize>, style: RunStyle) -> AttributedText {
+        self.spans.push((range, style));
+        self
+    }
+
+    pub fn with_decoration(
+        mut self,
+        range: Range<usize>,
+        decoration: TextDecoration,
+    ) -> AttributedText {
+        self.decorations.push((range, decoration));
+        self
+    }
+
+    /// The decorations that overlap the run at `run` of the text, with
+    /// their ranges cut to the run and made relative to its start. Ends
+    /// are moved to character boundaries like those of spans.
+    pub fn run_decorations(&self, run: &Range<usize>) -> Vec<(Range<usize>, TextDecoration)> {
+        self.decorations
+            .iter()
+            .filter_map(|(range, decoration)| {
+                let start = self.boundary(range.start).max(run.start);
+                let end = self.boundary(range.end).min(run.end);
+                if start < end {
+                    Some((start - run.start..end - run.start, *decoration))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Splits the text into runs of one style each, covering all of it,
+    /// with neighbouring runs of the same style merged so that each is
+    /// shaped in one go. Span ends that aren't on a character boundary
+    /// are moved forward to the next one.
+    pub fn runs(&self) -> Vec<(Range<usize>, RunStyle)> {
+        let len = self.text.len();
+        let boundary = |offset| self.boundary(offset);
+        let mut cuts = vec![0, len];
+        for (range, _) in &self.spans {
+            cuts.push(boundary(range.start));
+            cuts.push(boundary(range.end));
+        }
+        cuts.sort();
+        cuts.dedup();
+
+        let mut runs: Vec<(Range<usize>, RunStyle)> = vec![];
+        for cut in cuts.windows(2) {
+            let (start, end) = (cut[0], cut[1]);
+            let style = self
+                .spans
+                .iter()
+                .filter(|(range, _)| boundary(range.start) <= start && end <= boundary(range.end))
+                .fold(RunStyle::default(), |under, (_, style)| style.over(under));
+            match runs.last_mut() {
+                Some((range, last)) if *last == style => range.end = end,
+                _ => runs.push((start..end, style)),
+            }
+        }
+        runs
+    }
+
+    /// The first character boundary at or after `offset`.
+    fn boundary(&self, offset: usize) -> usize {
+        let mut offset = offset.min(self.text.len());
+        while !self.text.is_char_boundary(offset) {
+            offset += 1;
+        }
+        offset
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AttributedText, RunStyle, TextDecoration};
+    use crate::Color;
+
+    #[test]
+    fn runs_cover_the_text_once() {
+        let red = Color::new(255, 0, 0, 255);
+        let bold_red = RunStyle {
+            text_color: Some(red),
+            font_weight: Some(700),
+            ..Default::default()
+        };
+        let text = AttributedText::new("let x = 1;")
+            .with_span(0..3, bold_red)
+            .with_span(
+                0..2,
+                RunStyle {
+                    text_color: Some(red),
+                    ..Default::default()
+                },
+            );
+        // The second span changes nothing, so the first three characters
+        // stay one run.
+        assert_eq!(
+            text.runs(),
+            vec![(0..3, bold_red), (3..10, RunStyle::default())]
+        );
+
+        let adjacent = AttributedText::new("abcd")
+            .with_span(0..2, bold_red)
+            .with_span(2..4, bold_red);
+        assert_eq!(adjacent.runs(), vec![(0..4, bold_red)]);
+        assert!(AttributedText::new("").runs().is_empty());
+    }
+
+    #[test]
+    fn decorations_are_cut_to_each_run() {
+        let bold = RunStyle {
+            font_weight: Some(700),
+            ..Default::default()
+        };
+        let text = AttributedText::new("one two three")
+            .with_span(4..7, bold)
+            .with_decoration(2..9, TextDecoration::Underline);
+        // Decorations don't split runs of their own.
+        assert_eq!(text.runs().len(), 3);
+        assert_eq!(
+            text.run_decorations(&(0..4)),
+            vec![(2..4, TextDecoration::Underline)]
+        );
+        assert_eq!(
+            text.run_decorations(&(4..7)),
+            vec![(0..3, TextDecoration::Underline)]
+        );
+        assert_eq!(
+            text.run_decorations(&(7..13)),
+            vec![(0..2, TextDecoration::Underline)]
+        );
+    }
+}
@@ -1,24 +1,302 @@
-use super::{inline, LayoutChild, LayoutTreeNode, LogicalSize, RenderData};
+use super::{
+    bounded, finite_or, finite_size, inline, pixel_grid, shrink_constraint, stats,
+    text::{self, TextOptions},
+    CustomLayoutRef, FixedLayer, LayoutCache, LayoutChild, LayoutClip, LayoutKey, LayoutTreeNode,
+    LogicalLength, LogicalPoint, LogicalSideOffsets, LogicalSize, RenderData,
+};
 use crate::dom::{element::DynamicNode, node::AnyNode, node::NodeRef};
-use crate::style::{BlockValues, ComputedValues, Direction, DisplayType};
+use crate::style::{
+    Alignment, BlockValues, BorderRadius, BoxSizing, ComputedValues, Direction, DisplayType, Inset,
+    Overflow, Position,
+};
 use crate::util::equal_rc::EqualRc;
-use euclid::{point2, size2, vec2};
+use euclid::{point2, size2, vec2, Rect};
 use moxie::*;
+use std::rc::Rc;
 
 fn calc_max_size(values: &BlockValues, parent_size: LogicalSize) -> LogicalSize {
     let mut outer = parent_size;
     if let Some(width) = values.width {
-        outer.width = width.get();
+        outer.width = finite_or(width.get(), outer.width);
     }
     if let Some(height) = values.height {
-        outer.height = height.get();
+        outer.height = finite_or(height.get(), outer.height);
     }
-    outer - size2(values.padding.horizontal(), values.padding.vertical())
+    size2(
+        shrink_constraint(outer.width, values.padding.horizontal()),
+        shrink_constraint(outer.height, values.padding.vertical()),
+    )
+}
+
+/// Adds the percentage part of the padding, resolved against the width
+/// of the containing block. An unbounded width resolves to nothing.
+fn resolve_padding(values: &BlockValues, containing_width: f32) -> BlockValues {
+    let basis = bounded(containing_width).unwrap_or(0.0);
+    let percent = values.padding_percent;
+    let mut values = *values;
+    values.padding.left += percent.left * basis;
+    values.padding.right += percent.right * basis;
+    values.padding.top += percent.top * basis;
+    values.padding.bottom += percent.bottom * basis;
+    values
+}
+
+/// Converts the sizes of a `BoxSizing::ContentBox` block to include its
+/// padding and border, which is what the rest of layout works with.
+fn resolve_box_sizing(values: &BlockValues, border: LogicalSideOffsets) -> BlockValues {
+    let mut values = *values;
+    if values.box_sizing == BoxSizing::ContentBox {
+        let grow = |length: &mut Option<LogicalLength>, extra: f32| {
+            if let Some(length) = length {
+                *length = *length + LogicalLength::new(extra);
+            }
+        };
+        let extra_width = values.padding.horizontal() + border.horizontal();
+        let extra_height = values.padding.vertical() + border.vertical();
+        grow(&mut values.width, extra_width);
+        grow(&mut values.min_width, extra_width);
+        grow(&mut values.max_width, extra_width);
+        grow(&mut values.height, extra_height);
+        grow(&mut values.min_height, extra_height);
+        grow(&mut values.max_height, extra_height);
+        values.box_sizing = BoxSizing::BorderBox;
+    }
+    values
+}
+
+/// Turns `rows` into a fixed height, making room for the padding and
+/// border around the lines.
+fn resolve_rows(values: &BlockValues, border: f32, line_height: f32) -> BlockValues {
+    let mut values = *values;
+    if let Some(rows) = values.rows {
+        let content = rows as f32 * line_height;
+        values.height = Some(LogicalLength::new(
+            content + values.padding.vertical() + border,
+        ));
+    }
+    values
+}
+
+/// Fills in the side of a box with an `aspect_ratio` that isn't set
+/// from the other one, or both from the width available to the box when
+/// neither is, so that its size never depends on its content.
+fn resolve_aspect_ratio(values: &BlockValues, available_width: f32) -> BlockValues {
+    let mut values = *values;
+    let ratio = match values.aspect_ratio {
+        Some(ratio) if ratio > 0.0 && ratio.is_finite() => ratio,
+        _ => return values,
+    };
+    match (values.width, values.height) {
+        (Some(width), None) => {
+            values.height = Some(LogicalLength::new(width.get() / ratio));
+        }
+        (None, Some(height)) => {
+            values.width = Some(LogicalLength::new(height.get() * ratio));
+        }
+        (None, None) if available_width.is_finite() => {
+            values.width = Some(LogicalLength::new(available_width));
+            values.height = Some(LogicalLength::new(available_width / ratio));
+        }
+        _ => (),
+    }
+    values
+}
+
+fn has_size_limits(values: &BlockValues) -> bool {
+    values.min_width.is_some()
+        || values.min_height.is_some()
+        || values.max_width.is_some()
+        || values.max_height.is_some()
+}
+
+/// Clamps a box to its minimum and maximum size. Content that no longer
+/// fits overflows, which `Overflow::Scroll` turns into a scrollable
+/// area. As in CSS, the minimum wins over the maximum.
+fn apply_size_limits(values: &BlockValues, mut size: LogicalSize) -> LogicalSize {
+    if let Some(max_width) = values.max_width {
+        size.width = size.width.min(max_width.get());
+    }
+    if let Some(max_height) = values.max_height {
+        size.height = size.height.min(max_height.get());
+    }
+    if let Some(min_width) = values.min_width {
+        size.width = size.width.max(min_width.get());
+    }
+    if let Some(min_height) = values.min_height {
+        size.height = size.height.max(min_height.get());
+    }
+    size
+}
+
+/// How far to move content to align it within `extra` space.
+fn align_offset(align: Alignment, extra: f32) -> f32 {
+    let extra = extra.max(0.0);
+    match align {
+        Alignment::Start => 0.0,
+        Alignment::Center => extra / 2.0,
+        Alignment::End => extra,
+    }
+}
+
+/// The size of a block's content box along its direction, when it has a
+/// fixed one that children can fill, see `BlockValues::fill`.
+fn fixed_main_size(values: &ComputedValues, block_values: &BlockValues) -> Option<f32> {
+    if block_values.stacked {
+        return None;
+    }
+    let size = if block_values.direction == Direction::Vertical {
+        block_values.height?.get()
+            - block_values.padding.vertical()
+            - values.border_thickness.vertical()
+    } else {
+        block_values.width?.get()
+            - block_values.padding.horizontal()
+            - values.border_thickness.horizontal()
+    };
+    Some(size).filter(|size| size.is_finite())
+}
+
+/// The space a laid out child takes up along its parent's direction.
+fn main_extent(layout: &LayoutTreeNode, vertical: bool) -> f32 {
+    if vertical {
+        layout.size.height + layout.margin.vertical()
+    } else {
+        layout.size.width + layout.margin.horizontal()
+    }
+}
+
+/// Lays out a filling child with its size along its parent's direction
+/// set to `share`, margins included.
+fn layout_filling(
+    node: NodeRef,
+    values: &ComputedValues,
+    block_values: &BlockValues,
+    share: f32,
+    vertical: bool,
+    max_size: LogicalSize,
+) -> EqualRc<LayoutTreeNode> {
+    let mut block_values = *block_values;
+    if vertical {
+        let height = (share - block_values.margin.vertical()).max(0.0);
+        block_values.height = Some(LogicalLength::new(height));
+    } else {
+        let width = (share - block_values.margin.horizontal()).max(0.0);
+        block_values.width = Some(LogicalLength::new(width));
+    }
+    // The share already includes the padding and border.
+    block_values.box_sizing = BoxSizing::BorderBox;
+    layout_block(node, values, &block_values, max_size)
+}
+
+/// Whether a laid out child asked to share the leftover space of its
+/// parent, see `BlockValues::grow`.
+fn grows(layout: &LayoutTreeNode) -> bool {
+    if let RenderData::Node(ref node) = layout.render {
+        if let Some(values) = node.computed_values().get() {
+            if let DisplayType::Block(block) = values.display {
+                return block.grow;
+            }
+        }
+    }
+    false
+}
+
+/// Whether a laid out child is a paragraph, meaning text or inline
+/// content, and if so whether there's anything in it.
+fn paragraph(layout: &LayoutTreeNode) -> Option<bool> {
+    match layout.render {
+        RenderData::Text { .. } => Some(has_content(layout)),
+        RenderData::Node(_) if is_inline(layout) => Some(has_content(layout)),
+        RenderData::Node(_) => None,
+    }
+}
+
+fn is_inline(layout: &LayoutTreeNode) -> bool {
+    if let RenderData::Node(ref node) = layout.render {
+        if let Some(values) = node.computed_values().get() {
+            if let DisplayType::Inline(_) = values.display {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Whether inline content has any text or boxes in it, as opposed to
+/// only the blank lines reserved by empty text.
+fn has_content(layout: &LayoutTreeNode) -> bool {
+    match layout.render {
+        RenderData::Text { ref text, .. } => !text.range.is_empty(),
+        RenderData::Node(_) if is_inline(layout) => layout
+            .children
+            .iter()
+            .any(|child| has_content(&child.layout)),
+        RenderData::Node(_) => true,
+    }
+}
+
+/// Handles the common case of a wrapper around a single child, which
+/// sizes itself to the child, without the bookkeeping of the general
+/// case. Returns `None` when anything would make the general case
+/// behave differently.
+fn calc_single_child_layout(
+    values: &ComputedValues,
+    block_values: &BlockValues,
+    children: &[EqualRc<LayoutTreeNode>],
+    node: &AnyNode,
+) -> Option<EqualRc<LayoutTreeNode>> {
+    let child = match children {
+        [child] => child,
+        _ => return None,
+    };
+    if block_values.width.is_some()
+        || block_values.height.is_some()
+        || has_size_limits(block_values)
+        || block_values.overflow_x != Overflow::Visible
+        || block_values.overflow_y != Overflow::Visible
+        || grows(child)
+    {
+        return None;
+    }
+
+    let position = point2(
+        block_values.padding.left + values.border_thickness.left + child.margin.left,
+        block_values.padding.top + values.border_thickness.top + child.margin.top,
+    );
+    let content_size = size2(
+        (child.size.width + child.margin.horizontal()).max(0.0),
+        (child.size.height + child.margin.vertical()).max(0.0),
+    );
+    let size = content_size
+        + size2(
+            block_values.padding.horizontal() + values.border_thickness.horizontal(),
+            block_values.padding.vertical() + values.border_thickness.vertical(),
+        );
+    // Snapped like `block_box` does, so that the box lands on the same
+    // pixels it would with more children.
+    let size = pixel_grid::snap_size(finite_size(size));
+    let mut children = vec![LayoutChild {
+        position,
+        layout: child.clone(),
+    }];
+    pixel_grid::snap_positions(&mut children);
+
+    Some(EqualRc::new(LayoutTreeNode {
+        size,
+        margin: block_values.margin,
+        children,
+        render: RenderData::Node(node.clone()),
+        clip: None,
+        border_radius: values.border_radius.clamped(size),
+        truncated: false,
+        lines: 0,
+    }))
 }
 
 fn calc_block_layout(
     input: &(ComputedValues, Vec<EqualRc<LayoutTreeNode>>, AnyNode),
 ) -> EqualRc<LayoutTreeNode> {
+    stats::count(|stats| stats.boxes += 1);
     let (values, children, node) = input;
 
     let block_values = if let DisplayType::Block(block) = values.display {
@@ -27,35 +305,78 @@ fn calc_block_layout(
         panic!()
     };
 
+    if let Some(layout) = calc_single_child_layout(values, &block_values, children, node) {
+        return layout;
+    }
+
     let inset = point2(
         block_values.padding.left + values.border_thickness.left,
         block_values.padding.top + values.border_thickness.top,
     );
 
+    // Margins may be negative, which moves the cursor backwards and
+    // lets children overlap, so the extent is tracked separately from
+    // the cursor.
     let mut width = 0.0f32;
     let mut height = 0.0f32;
-    let mut child_positions = vec![];
-    for child in children {
+    let mut cursor = 0.0f32;
+    let mut after_paragraph = false;
+    // Where the last baseline of the previous child is, for
+    // `baseline_spacing`.
+    let mut last_baseline = None;
+    let mut child_positions = Vec::with_capacity(children.len());
+    for (index, child) in children.iter().enumerate() {
         let child = child.clone();
+        if index > 0 && !block_values.stacked {
+            cursor += block_values.gap.get();
+        }
         let size = child.size + size2(child.margin.horizontal(), child.margin.vertical());
-        if block_values.direction == Direction::Vertical {
+        let margin_offset = vec2(child.margin.left, child.margin.top);
+        if block_values.stacked {
+            width = width.max(size.width);
+            height = height.max(size.height);
+            child_positions.push(LayoutChild {
+                position: inset + margin_offset,
+                layout: child,
+            });
+        } else if block_values.direction == Direction::Vertical {
+            match paragraph(&child) {
+                Some(true) => {
+                    if after_paragraph {
+                        cursor += block_values.paragraph_spacing.get();
+                    }
+                    after_paragraph = true;
+                }
+                Some(false) => {}
+                None => after_paragraph = false,
+            }
+            let first_baseline = child.first_baseline();
+            if let (Some(spacing), Some(previous), Some(first)) =
+                (block_values.baseline_spacing, last_baseline, first_baseline)
+            {
+                cursor = previous + spacing.get() - first - child.margin.top;
+            }
+            last_baseline = child
+                .last_baseline()
+                .map(|baseline| cursor + child.margin.top + baseline);
             width = width.max(size.width);
             child_positions.push(LayoutChild {
-                position: inset + vec2(0.0, height),
+                position: inset + vec2(0.0, cursor) + margin_offset,
                 layout: child,
             });
-            height += size.height;
+            cursor += size.height;
+            height = height.max(cursor);
         } else {
             height = height.max(size.height);
             child_positions.push(LayoutChild {
-                position: inset + vec2(width, 0.0),
+                position: inset + vec2(cursor, 0.0) + margin_offset,
                 layout: child,
             });
-            width += size.width;
+            cursor += size.width;
+            width = width.max(cursor);
         }
     }
 
-    let size = size2(width, height);
     let padding = size2(
         block_values.padding.horizontal(),
         block_values.padding.vertical(),
@@ -65,59 +386,1408 @@ fn calc_block_layout(
         values.border_thickness.vertical(),
     );
 
-    let mut size = size + padding + border;
+    // Growing children split whatever space is left along a fixed main
+    // axis, pushing the children after them towards the far edge.
+    let growing = if block_values.stacked {
+        0
+    } else {
+        children.iter().filter(|child| grows(child)).count()
+    };
+    let vertical = block_values.direction == Direction::Vertical;
+    let main_size = if vertical {
+        block_values
+            .height
+            .map(|height| height.get() - padding.height - border.height)
+    } else {
+        block_values
+            .width
+            .map(|width| width.get() - padding.width - border.width)
+    };
+    if let Some(main_size) = main_size.filter(|_| growing > 0) {
+        let free = main_size - cursor;
+        if free.is_finite() && free > 0.0 {
+            let share = free / growing as f32;
+            let mut shift = 0.0;
+            for (position, child) in child_positions.iter_mut().zip(children) {
+                if vertical {
+                    position.position.y += shift;
+                } else {
+                    position.position.x += shift;
+                }
+                if grows(child) {
+                    shift += share;
+                }
+            }
+            if vertical {
+                height = height.max(main_size);
+            } else {
+                width = width.max(main_size);
+            }
+        }
+    }
+
+    let content_size = size2(width.max(0.0), height.max(0.0));
+    block_box(values, &block_values, node, content_size, child_positions)
+}
+
+/// Lays out a block with a `CustomLayout`, which places the children in
+/// place of the list or stack layout.
+fn calc_custom_layout(
+    input: &(
+        ComputedValues,
+        Vec<EqualRc<LayoutTreeNode>>,
+        AnyNode,
+        LogicalSize,
+    ),
+) -> EqualRc<LayoutTreeNode> {
+    stats::count(|stats| stats.boxes += 1);
+    let (values, children, node, max_size) = input;
+    let block_values = if let DisplayType::Block(block) = values.display {
+        block
+    } else {
+        panic!()
+    };
+    let custom = block_values.custom_layout.unwrap();
+    let (content_size, mut child_positions) = custom.0.layout(children, *max_size);
+    let inset = vec2(
+        block_values.padding.left + values.border_thickness.left,
+        block_values.padding.top + values.border_thickness.top,
+    );
+    for child in &mut child_positions {
+        child.position += inset;
+    }
+    block_box(values, &block_values, node, content_size, child_positions)
+}
+
+/// The box of a block whose children take up `content_size` at the given
+/// positions, sized and aligned by its style.
+fn block_box(
+    values: &ComputedValues,
+    block_values: &BlockValues,
+    node: &AnyNode,
+    content_size: LogicalSize,
+    mut child_positions: Vec<LayoutChild>,
+) -> EqualRc<LayoutTreeNode> {
+    let padding = size2(
+        block_values.padding.horizontal(),
+        block_values.padding.vertical(),
+    );
+    let border = size2(
+        values.border_thickness.horizontal(),
+        values.border_thickness.vertical(),
+    );
+    let mut size = content_size + padding + border;
 
+    let expand = block_values.overflow_expand;
     if let Some(width) = block_values.width {
-        size.width = width.get();
+        size.width = if expand {
+            size.width.max(width.get())
+        } else {
+            width.get()
+        };
     }
     if let Some(height) = block_values.height {
-        size.height = height.get();
+        size.height = if expand {
+            size.height.max(height.get())
+        } else {
+            height.get()
+        };
     }
+    let size = pixel_grid::snap_size(finite_size(apply_size_limits(block_values, size)));
+
+    let mut extra = size - content_size - padding - border;
+    let aligns_items = block_values.align_items != Alignment::Start
+        && !block_values.stacked
+        && block_values.custom_layout.is_none();
+    if aligns_items {
+        // Each child is aligned across the whole content box on its own,
+        // which leaves nothing for the content as a whole on that axis.
+        let inner = size - padding - border;
+        for child in &mut child_positions {
+            let layout = &child.layout;
+            if block_values.direction == Direction::Vertical {
+                let free = inner.width - layout.size.width - layout.margin.horizontal();
+                child.position.x += align_offset(block_values.align_items, free);
+            } else {
+                let free = inner.height - layout.size.height - layout.margin.vertical();
+                child.position.y += align_offset(block_values.align_items, free);
+            }
+        }
+        if block_values.direction == Direction::Vertical {
+            extra.width = 0.0;
+        } else {
+            extra.height = 0.0;
+        }
+    }
+    let offset = vec2(
+        align_offset(block_values.content_align_x, extra.width),
+        align_offset(block_values.content_align_y, extra.height),
+    );
+    if offset != vec2(0.0, 0.0) {
+        for child in &mut child_positions {
+            child.position += offset;
+        }
+    }
+    pixel_grid::snap_positions(&mut child_positions);
 
     let margin = block_values.margin;
 
+    let clip = overflow_clip(values, block_values, size - border);
+
     EqualRc::new(LayoutTreeNode {
         size,
         margin,
         children: child_positions,
         render: RenderData::Node(node.clone()),
+        clip,
+        border_radius: values.border_radius.clamped(size),
+        truncated: false,
+        lines: 0,
+    })
+}
+
+/// How far a clip reaches past the box along an axis with visible
+/// overflow, which is meant to cut nothing off but has to stay finite
+/// for the renderer.
+const UNCLIPPED: f32 = 1.0e6;
+
+/// The clip of a box whose padding box has the size `inner`, cutting
+/// children off along each axis that doesn't have visible overflow.
+/// The corners are only rounded when both axes are clipped, following
+/// the inner edge of the border.
+fn overflow_clip(
+    values: &ComputedValues,
+    block_values: &BlockValues,
+    inner: LogicalSize,
+) -> Option<LayoutClip> {
+    let axis = |overflow, start: f32, length: f32| {
+        if overflow == Overflow::Visible {
+            (-UNCLIPPED, 2.0 * UNCLIPPED)
+        } else {
+            (start, length)
+        }
+    };
+    let (x, width) = axis(
+        block_values.overflow_x,
+        values.border_thickness.left,
+        inner.width,
+    );
+    let (y, height) = axis(
+        block_values.overflow_y,
+        values.border_thickness.top,
+        inner.height,
+    );
+    let radius = match (block_values.overflow_x, block_values.overflow_y) {
+        (Overflow::Visible, Overflow::Visible) => return None,
+        (Overflow::Visible, _) | (_, Overflow::Visible) => BorderRadius::default(),
+        _ => {
+            let border = values.border_thickness;
+            let size = inner + size2(border.horizontal(), border.vertical());
+            values.border_radius.clamped(size).inner(border)
+        }
+    };
+    Some(LayoutClip {
+        rect: Rect::new(point2(x, y), size2(width, height)),
+        radius,
     })
 }
 
+/// Puts children in the order they should be placed in, based on the
+/// `order` style property. The sort is stable so that children with
+/// the same order keep their DOM order.
+fn sort_by_order<T>(mut children: Vec<(i32, T)>) -> Vec<T> {
+    children.sort_by_key(|&(order, _)| order);
+    children.into_iter().map(|(_, layout)| layout).collect()
+}
+
+/// Where a fixed box of the given size goes in the window. Only the
+/// window size matters, so the box stays put whatever its ancestors do.
+fn fixed_position(
+    inset: &Inset,
+    margin: &LogicalSideOffsets,
+    size: LogicalSize,
+    viewport: LogicalSize,
+) -> LogicalPoint {
+    let x = match (inset.left, inset.right) {
+        (Some(left), _) => left.get() + margin.left,
+        (None, Some(right)) => viewport.width - right.get() - margin.right - size.width,
+        (None, None) => margin.left,
+    };
+    let y = match (inset.top, inset.bottom) {
+        (Some(top), _) => top.get() + margin.top,
+        (None, Some(bottom)) => viewport.height - bottom.get() - margin.bottom - size.height,
+        (None, None) => margin.top,
+    };
+    point2(x, y)
+}
+
+/// Lays out a box with `position: fixed` against the window and hands
+/// it to the fixed layer, leaving no trace in its parent's layout.
+#[illicit::from_env(fixed: &FixedLayer)]
+pub(super) fn layout_fixed(node: NodeRef, values: &ComputedValues, block: &BlockValues) {
+    let layout = layout_block(node, values, block, fixed.viewport);
+    let position = fixed_position(&block.inset, &block.margin, layout.size, fixed.viewport);
+    fixed
+        .children
+        .borrow_mut()
+        .push(LayoutChild { position, layout });
+}
+
+/// What decides the layout of a subtree that styling left unchanged:
+/// the values of its root, the constraint it's laid out under, and the
+/// viewport that fixed boxes inside of it are placed against.
+type SubtreeArgs = (ComputedValues, BlockValues, LogicalSize, LogicalSize);
+type SubtreeLayout = (EqualRc<LayoutTreeNode>, Vec<LayoutChild>);
+
+/// Lays out a block and everything below it. When styling found nothing
+/// in the subtree changed since it was last laid out under the same
+/// constraint, the previous layout is taken without visiting the nodes
+/// below, e.g. every row of a form but the one whose label was updated,
+/// and the fixed boxes it found are handed to the fixed layer again.
+#[illicit::from_env(cache: &Rc<LayoutCache>, fixed: &FixedLayer)]
 pub fn layout_block(
     node: NodeRef,
     values: &ComputedValues,
     block_values: &BlockValues,
     parent_max_size: LogicalSize,
 ) -> EqualRc<LayoutTreeNode> {
+    let key = LayoutKey::Subtree(node.to_owned());
+    if node.style_changed().get() {
+        cache.forget::<SubtreeArgs, SubtreeLayout>(key);
+        return layout_block_children(node, values, block_values, parent_max_size);
+    }
+    let mut walked = false;
+    let (layout, fixed_children): SubtreeLayout = cache.memo(
+        key,
+        (*values, *block_values, parent_max_size, fixed.viewport),
+        |_| {
+            walked = true;
+            let start = fixed.children.borrow().len();
+            let layout = layout_block_children(node, values, block_values, parent_max_size);
+            (layout, fixed.children.borrow()[start..].to_vec())
+        },
+    );
+    if !walked {
+        fixed.children.borrow_mut().extend(fixed_children);
+    }
+    layout
+}
+
+/// Lays out a block by laying out each of its children, which reuses
+/// the layouts of those that didn't change.
+#[illicit::from_env(cache: &Rc<LayoutCache>)]
+fn layout_block_children(
+    node: NodeRef,
+    values: &ComputedValues,
+    block_values: &BlockValues,
+    parent_max_size: LogicalSize,
+) -> EqualRc<LayoutTreeNode> {
+    let block_values = resolve_padding(block_values, parent_max_size.width);
+    let mut block_values = resolve_box_sizing(&block_values, values.border_thickness);
+    if block_values.rows.is_some() {
+        let line_height = memo!(TextOptions::new(values), text::line_height);
+        block_values = resolve_rows(
+            &block_values,
+            values.border_thickness.vertical(),
+            line_height,
+        );
+    }
+    let block_values = &resolve_aspect_ratio(
+        &block_values,
+        shrink_constraint(parent_max_size.width, block_values.margin.horizontal()),
+    );
+    let values = &ComputedValues {
+        display: DisplayType::Block(*block_values),
+        ..*values
+    };
     let max_size = calc_max_size(block_values, parent_max_size);
+    let main_size = fixed_main_size(values, block_values);
+    // Only text running past the sides gets cut off, since text that
+    // doesn't wrap never runs past the bottom.
+    let clips = block_values.overflow_x != Overflow::Visible;
+    if let Some(custom) = block_values.custom_layout {
+        return layout_custom_children(node, values, custom, max_size, clips);
+    }
 
+    // Filling children are laid out after their siblings, once it's
+    // known how much space is left for them. Each remembers where it
+    // goes among the others.
     let mut children = vec![];
-    for child in node.children() {
+    let mut filling = vec![];
+    for (index, child) in node.children().enumerate() {
         topo::call! {
             {
-                match child {
-                    DynamicNode::Node(node) => {
-                        let values = node.computed_values().get().unwrap();
-                        match values.display {
-                            DisplayType::Block(ref block) => {
-                                children.push(layout_block(node, &values, block, max_size));
-                            }
-                            DisplayType::Inline(_) => {
-                                children.push(inline::layout_inline(node, &values, max_size));
+                match child.get_node() {
+                    DynamicNode::Node(child) => {
+                        let child_values = child.computed_values().get().unwrap();
+                        match child_values.display {
+                            DisplayType::Block(block) if block.fill && block.position != Position::Fixed && main_size.is_some() => {
+                                filling.push((children.len() + filling.len(), child, child_values, block));
                             }
+                            _ => children.extend(layout_child(node, index, DynamicNode::Node(child), values, max_size, clips)),
                         }
                     }
-                    DynamicNode::Text(text) => {
-                        children.push(inline::layout_text(node.to_owned(), text, max_size.width, values));
+                    text => children.extend(layout_child(node, index, text, values, max_size, clips)),
+                }
+            }
+        }
+    }
+    if let Some(main_size) = main_size {
+        let vertical = block_values.direction == Direction::Vertical;
+        let count = children.len() + filling.len();
+        let gaps = count.saturating_sub(1) as f32 * block_values.gap.get();
+        let used: f32 = children
+            .iter()
+            .map(|(_, (child, _))| main_extent(child, vertical))
+            .sum::<f32>()
+            + gaps;
+        let share = ((main_size - used) / filling.len() as f32).max(0.0);
+        for (slot, node, values, block) in filling {
+            topo::call! {
+                {
+                    let layout = layout_filling(node, &values, &block, share, vertical, max_size);
+                    children.insert(slot, (values.order, with_stand_in(node, &values, &block, layout)));
+                }
+            }
+        }
+    }
+    let (children, stand_ins): (Vec<_>, Vec<_>) = sort_by_order(children).into_iter().unzip();
+
+    let layout = cache.memo(
+        LayoutKey::Node(node.to_owned()),
+        (values.clone(), stand_ins.clone(), node.to_owned()),
+        calc_block_layout,
+    );
+    restore_boundaries(node, layout, stand_ins, children)
+}
+
+/// Whether a block's size is decided by its style alone, see
+/// `BlockValues::layout_boundary`.
+fn is_boundary(block_values: &BlockValues) -> bool {
+    block_values.layout_boundary
+        && block_values.width.is_some()
+        && block_values.height.is_some()
+        && !block_values.overflow_expand
+}
+
+/// A laid out child along with what it looks like to the layout of its
+/// parent, which is the child itself unless it's a layout boundary.
+type ChildLayout = (EqualRc<LayoutTreeNode>, EqualRc<LayoutTreeNode>);
+
+/// Pairs the layout of a block child with its stand-in, only looking
+/// one up when the child's style makes it a boundary.
+fn with_stand_in(
+    node: NodeRef,
+    values: &ComputedValues,
+    block_values: &BlockValues,
+    layout: EqualRc<LayoutTreeNode>,
+) -> ChildLayout {
+    if is_boundary(block_values) {
+        let stand_in = boundary_stand_in(node, values, &layout);
+        (layout, stand_in)
+    } else {
+        (layout.clone(), layout)
+    }
+}
+
+/// What a laid out boundary looks like to the layout of its parent: a
+/// box of the same size without children, which is the same as long as
+/// the boundary's size and style are, so that the parent's layout is
+/// taken from the cache.
+#[illicit::from_env(cache: &Rc<LayoutCache>)]
+fn boundary_stand_in(
+    node: NodeRef,
+    values: &ComputedValues,
+    layout: &EqualRc<LayoutTreeNode>,
+) -> EqualRc<LayoutTreeNode> {
+    cache.memo(
+        LayoutKey::Boundary(node.to_owned()),
+        (*values, layout.size, layout.margin),
+        |_| {
+            EqualRc::new(LayoutTreeNode {
+                size: layout.size,
+                margin: layout.margin,
+                render: RenderData::Node(node.to_owned()),
+                children: vec![],
+                clip: layout.clip.clone(),
+                border_radius: layout.border_radius,
+                truncated: false,
+                lines: 0,
+            })
+        },
+    )
+}
+
+/// Puts the layouts of boundaries back in place of their `stand_ins` in
+/// the layout of their parent `node`. The result is kept while neither
+/// the parent's layout nor its children change, so that the layouts
+/// above it are taken from the cache as well.
+#[illicit::from_env(cache: &Rc<LayoutCache>)]
+fn restore_boundaries(
+    node: NodeRef,
+    layout: EqualRc<LayoutTreeNode>,
+    stand_ins: Vec<EqualRc<LayoutTreeNode>>,
+    children: Vec<EqualRc<LayoutTreeNode>>,
+) -> EqualRc<LayoutTreeNode> {
+    if stand_ins == children {
+        return layout;
+    }
+    cache.memo(
+        LayoutKey::Node(node.to_owned()),
+        (layout, stand_ins, children),
+        |(layout, stand_ins, children)| {
+            let restored = layout
+                .children
+                .iter()
+                .map(|child| {
+                    let boundary = stand_ins
+                        .iter()
+                        .zip(children)
+                        .find(|(stand_in, real)| stand_in != real && **stand_in == child.layout);
+                    LayoutChild {
+                        position: child.position,
+                        layout: boundary
+                            .map_or_else(|| child.layout.clone(), |(_, real)| real.clone()),
                     }
+                })
+                .collect();
+            EqualRc::new(LayoutTreeNode {
+                size: layout.size,
+                margin: layout.margin,
+                render: RenderData::Node(node.to_owned()),
+                children: restored,
+                clip: layout.clip.clone(),
+                border_radius: layout.border_radius,
+                truncated: layout.truncated,
+                lines: layout.lines,
+            })
+        },
+    )
+}
+
+/// Lays out a child of `parent` under `max_size`, returning its layout
+/// with its `order`. Fixed children go on the fixed layer instead, and
+/// return nothing.
+fn layout_child(
+    parent: NodeRef,
+    index: usize,
+    child: DynamicNode,
+    parent_values: &ComputedValues,
+    max_size: LogicalSize,
+    clips: bool,
+) -> Option<(i32, ChildLayout)> {
+    match child {
+        DynamicNode::Node(node) => {
+            let values = node.computed_values().get().unwrap();
+            match values.display {
+                DisplayType::Block(ref block) if block.position == Position::Fixed => {
+                    layout_fixed(node, &values, block);
+                    None
+                }
+                DisplayType::Block(ref block) => {
+                    let layout = layout_block(node, &values, block, max_size);
+                    Some((values.order, with_stand_in(node, &values, block, layout)))
+                }
+                DisplayType::Inline(_) => {
+                    let layout = inline::layout_inline(node, &values, max_size, clips);
+                    Some((values.order, (layout.clone(), layout)))
+                }
+            }
+        }
+        DynamicNode::Text(text) => {
+            let layout = inline::layout_text(
+                parent.to_owned(),
+                index,
+                text,
+                max_size.width,
+                parent_values,
+                clips,
+            );
+            Some((0, (layout.clone(), layout)))
+        }
+    }
+}
+
+/// Finds the size of a child of `parent` under `max_size` without laying
+/// it out for good, returning it with its `order`. Sizes are kept under
+/// keys of their own, so that laying the child out again in a smaller
+/// cell doesn't push them out of the cache. Fixed children go on the
+/// fixed layer instead, and return nothing.
+#[illicit::from_env(cache: &Rc<LayoutCache>)]
+fn measure_child(
+    parent: NodeRef,
+    index: usize,
+    child: DynamicNode,
+    parent_values: &ComputedValues,
+    max_size: LogicalSize,
+    clips: bool,
+) -> Option<(i32, LogicalSize)> {
+    match child {
+        DynamicNode::Node(node) => {
+            let values = node.computed_values().get().unwrap();
+            match values.display {
+                DisplayType::Block(ref block) if block.position == Position::Fixed => {
+                    layout_fixed(node, &values, block);
+                    None
+                }
+                DisplayType::Block(ref block) => {
+                    Some((values.order, measure_block(node, &values, block, max_size)))
+                }
+                DisplayType::Inline(_) => {
+                    let mut subtree = vec![];
+                    collect_subtree_values(node, &mut subtree);
+                    let size = cache.memo(
+                        LayoutKey::measure(node.to_owned(), max_size),
+                        (values, subtree, clips),
+                        |_| inline::layout_inline(node, &values, max_size, clips).size,
+                    );
+                    Some((values.order, size))
                 }
             }
         }
+        DynamicNode::Text(text) => {
+            let size = cache.memo(
+                LayoutKey::measure_text(parent.to_owned(), text, max_size.width),
+                (text.clone(), *parent_values, clips),
+                |_| {
+                    inline::layout_text(
+                        parent.to_owned(),
+                        index,
+                        text,
+                        max_size.width,
+                        parent_values,
+                        clips,
+                    )
+                    .size
+                },
+            );
+            Some((0, size))
+        }
     }
+}
 
-    moxie::memo!(
-        (values.clone(), children, node.to_owned()),
-        calc_block_layout
+/// Lays out the children of a block with a `CustomLayout`. When the
+/// layout gives them cells of their own, like a grid does, they are
+/// measured under `max_size` first and then laid out once, each in its
+/// cell.
+#[illicit::from_env(cache: &Rc<LayoutCache>)]
+fn layout_custom_children(
+    node: NodeRef,
+    values: &ComputedValues,
+    custom: CustomLayoutRef,
+    max_size: LogicalSize,
+    clips: bool,
+) -> EqualRc<LayoutTreeNode> {
+    let mut measured = vec![];
+    for (index, child) in node.children().enumerate() {
+        topo::call! {
+            {
+                if let Some((order, size)) = measure_child(node, index, child.get_node(), values, max_size, clips) {
+                    measured.push((order, index, child, size));
+                }
+            }
+        }
+    }
+    measured.sort_by_key(|&(order, ..)| order);
+
+    let sizes: Vec<_> = measured.iter().map(|&(.., size)| size).collect();
+    let cells = custom
+        .0
+        .cells(&sizes, max_size)
+        .unwrap_or_else(|| vec![max_size; sizes.len()]);
+    let mut children = vec![];
+    for ((_, index, child, _), cell) in measured.into_iter().zip(cells) {
+        topo::call! {
+            {
+                if let Some((_, layout)) = layout_child(node, index, child.get_node(), values, cell, clips) {
+                    children.push(layout);
+                }
+            }
+        }
+    }
+    let (children, stand_ins): (Vec<_>, Vec<_>) = children.into_iter().unzip();
+
+    let layout = cache.memo(
+        LayoutKey::Node(node.to_owned()),
+        (values.clone(), stand_ins.clone(), node.to_owned(), max_size),
+        calc_custom_layout,
+    );
+    restore_boundaries(node, layout, stand_ins, children)
+}
+
+/// Collects the computed values of every element below `node`, which
+/// together with `node` itself decide its layout, since the children
+/// of a DOM node never change.
+fn collect_subtree_values(node: NodeRef, values: &mut Vec<Option<ComputedValues>>) {
+    for child in node.children() {
+        if let DynamicNode::Node(child) = child.get_node() {
+            values.push(child.computed_values().get());
+            collect_subtree_values(child, values);
+        }
+    }
+}
+
+/// Finds the size a block takes up under `parent_max_size` without
+/// placing it, for layouts that need to try out a child before deciding
+/// how much space to give it. The size is kept for each constraint the
+/// block is measured under, so probing it again is only a walk over the
+/// styles below it until one of them changes. A layout boundary is
+/// only measured again when its own style changes.
+#[illicit::from_env(cache: &Rc<LayoutCache>)]
+pub fn measure_block(
+    node: NodeRef,
+    values: &ComputedValues,
+    block_values: &BlockValues,
+    parent_max_size: LogicalSize,
+) -> LogicalSize {
+    let mut subtree = vec![];
+    if !is_boundary(block_values) {
+        collect_subtree_values(node, &mut subtree);
+    }
+    cache.memo(
+        LayoutKey::measure(node.to_owned(), parent_max_size),
+        (*values, *block_values, subtree),
+        |_| layout_block(node, values, block_values, parent_max_size).size,
     )
 }
+
+#[cfg(test)]
+mod test {
+    use super::{
+        calc_block_layout, calc_max_size, fixed_position, resolve_aspect_ratio, resolve_box_sizing,
+        resolve_padding, resolve_rows, sort_by_order,
+    };
+    use crate::dom::element::Element;
+    use crate::dom::node::AnyNode;
+    use crate::dom::{Node, Spacer, Span, View};
+    use crate::layout::{
+        LayoutChild, LayoutTreeNode, LogicalLength, LogicalRect, RenderData, UNBOUNDED,
+    };
+    use crate::style::{
+        Alignment, BlockValues, BorderRadius, BoxSizing, ComputedValues, Direction, DisplayType,
+        Inset, Overflow, Style,
+    };
+    use crate::util::equal_rc::EqualRc;
+    use euclid::{point2, size2, vec2, SideOffsets2D};
+    use std::rc::Rc;
+
+    /// A custom element which always lays out as a fixed-size box.
+    #[derive(Default, Clone, Debug, PartialEq)]
+    struct FixedBox;
+
+    impl Element for FixedBox {
+        type Child = Rc<str>;
+        type Handlers = ();
+        type States = ();
+
+        const ELEMENT_NAME: &'static str = "fixed_box";
+
+        fn create_computed_values(&self) -> ComputedValues {
+            ComputedValues {
+                display: DisplayType::Block(BlockValues {
+                    width: Some(LogicalLength::new(24.0)),
+                    height: Some(LogicalLength::new(12.0)),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }
+        }
+
+        fn style(&self) -> Option<Style> {
+            None
+        }
+    }
+
+    #[test]
+    fn custom_element_lays_out() {
+        let values = FixedBox.create_computed_values();
+        let node = Node::new(FixedBox, vec![]);
+        let layout = calc_block_layout(&(values, vec![], node.into()));
+        assert_eq!(layout.size, size2(24.0, 12.0));
+    }
+
+    #[test]
+    fn hidden_overflow_clips_to_radius() {
+        let values = ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                overflow_x: Overflow::Hidden,
+                overflow_y: Overflow::Hidden,
+                width: Some(LogicalLength::new(100.0)),
+                height: Some(LogicalLength::new(50.0)),
+                ..Default::default()
+            }),
+            border_radius: BorderRadius::uniform(8.0),
+            ..Default::default()
+        };
+        let node = Node::new(View::default(), vec![]);
+        let layout = calc_block_layout(&(values, vec![], node.into()));
+        let clip = layout.clip.as_ref().unwrap();
+        assert_eq!(clip.rect.size, layout.size);
+        assert_eq!(clip.radius, BorderRadius::uniform(8.0));
+        assert_eq!(layout.border_radius, BorderRadius::uniform(8.0));
+    }
+
+    #[test]
+    fn clip_follows_the_inner_edge_of_the_border() {
+        let values = ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                overflow_x: Overflow::Hidden,
+                overflow_y: Overflow::Hidden,
+                width: Some(LogicalLength::new(100.0)),
+                height: Some(LogicalLength::new(50.0)),
+                ..Default::default()
+            }),
+            border_radius: BorderRadius::uniform(8.0),
+            border_thickness: SideOffsets2D::new(10.0, 3.0, 3.0, 3.0),
+            ..Default::default()
+        };
+        let node = Node::new(View::default(), vec![]);
+        let layout = calc_block_layout(&(values, vec![], node.into()));
+        let clip = layout.clip.as_ref().unwrap();
+        assert_eq!(layout.border_radius, BorderRadius::uniform(8.0));
+        assert_eq!(
+            clip.radius,
+            BorderRadius {
+                top_left: size2(5.0, 0.0),
+                top_right: size2(5.0, 0.0),
+                bottom_right: size2(5.0, 5.0),
+                bottom_left: size2(5.0, 5.0),
+            }
+        );
+    }
+
+    #[test]
+    fn radii_too_large_for_the_box_are_scaled_down() {
+        let values = ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                width: Some(LogicalLength::new(20.0)),
+                height: Some(LogicalLength::new(10.0)),
+                ..Default::default()
+            }),
+            border_radius: BorderRadius::elliptical(20.0, 5.0),
+            ..Default::default()
+        };
+        let node = Node::new(View::default(), vec![]);
+        let layout = calc_block_layout(&(values, vec![], node.into()));
+        assert_eq!(layout.border_radius, BorderRadius::elliptical(10.0, 2.5));
+    }
+
+    fn fixed_box(width: f32, height: f32) -> EqualRc<LayoutTreeNode> {
+        let values = ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                width: Some(LogicalLength::new(width)),
+                height: Some(LogicalLength::new(height)),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let node = Node::new(View::default(), vec![]);
+        calc_block_layout(&(values, vec![], node.into()))
+    }
+
+    #[test]
+    fn stack_sizes_to_largest_child() {
+        let values = ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                stacked: true,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let children = vec![
+            fixed_box(10.0, 40.0),
+            fixed_box(30.0, 20.0),
+            fixed_box(20.0, 10.0),
+        ];
+        let node = Node::new(View::default(), vec![]);
+        let layout = calc_block_layout(&(values, children, node.into()));
+        assert_eq!(layout.size, size2(30.0, 40.0));
+        for child in &layout.children {
+            assert_eq!(child.position, point2(0.0, 0.0));
+        }
+    }
+
+    /// Stands in for a paragraph of text 20 high, with a box for its
+    /// content unless it's empty.
+    fn paragraph(content: bool) -> EqualRc<LayoutTreeNode> {
+        let span = Node::new(Span::default(), vec![]);
+        span.computed_values()
+            .set(Some(Span::default().create_computed_values()));
+        let children = if content {
+            vec![LayoutChild {
+                position: point2(0.0, 0.0),
+                layout: fixed_box(80.0, 20.0),
+            }]
+        } else {
+            vec![]
+        };
+        EqualRc::new(LayoutTreeNode {
+            size: size2(80.0, 20.0),
+            margin: SideOffsets2D::new_all_same(0.0),
+            render: RenderData::Node(span.into()),
+            children,
+            clip: None,
+            border_radius: BorderRadius::default(),
+            truncated: false,
+            lines: 1,
+        })
+    }
+
+    #[test]
+    fn paragraph_spacing_skips_empty_paragraphs() {
+        let values = ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                paragraph_spacing: LogicalLength::new(12.0),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let node: AnyNode = Node::new(View::default(), vec![]).into();
+        let children = vec![paragraph(true), paragraph(true), paragraph(true)];
+        let layout = calc_block_layout(&(values, children, node.clone()));
+        assert_eq!(layout.size.height, 84.0);
+
+        let children = vec![
+            paragraph(true),
+            paragraph(false),
+            paragraph(true),
+            fixed_box(80.0, 10.0),
+            paragraph(true),
+        ];
+        let layout = calc_block_layout(&(values, children, node));
+        assert_eq!(layout.children[2].position.y, 52.0);
+        // No spacing between a box and the paragraph after it.
+        assert_eq!(layout.children[4].position.y, 82.0);
+    }
+
+    #[test]
+    fn baseline_spacing_passes_over_children_without_text() {
+        let values = |baseline_spacing| ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                gap: LogicalLength::new(4.0),
+                baseline_spacing,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let node: AnyNode = Node::new(View::default(), vec![]).into();
+        let children = vec![paragraph(true), fixed_box(80.0, 10.0)];
+        let spaced = calc_block_layout(&(
+            values(Some(LogicalLength::new(28.0))),
+            children.clone(),
+            node.clone(),
+        ));
+        let plain = calc_block_layout(&(values(None), children, node));
+        assert_eq!(spaced.children[1].position.y, 24.0);
+        assert_eq!(spaced.size, plain.size);
+    }
+
+    #[test]
+    fn non_finite_sizes_stay_finite() {
+        let layout = fixed_box(std::f32::NAN, std::f32::INFINITY);
+        assert!(layout.size.width.is_finite());
+        assert!(layout.size.height.is_finite());
+    }
+
+    #[test]
+    fn nan_constraint_is_unbounded() {
+        let block = BlockValues {
+            width: Some(LogicalLength::new(std::f32::NAN)),
+            ..Default::default()
+        };
+        let max_size = calc_max_size(&block, size2(std::f32::NAN, 100.0));
+        assert_eq!(max_size.width, std::f32::INFINITY);
+        assert_eq!(max_size.height, 100.0);
+    }
+
+    #[test]
+    fn padding_keeps_constraints_in_range() {
+        let block = BlockValues {
+            padding: SideOffsets2D::new_all_same(30.0),
+            ..Default::default()
+        };
+        let max_size = calc_max_size(&block, size2(UNBOUNDED, 40.0));
+        assert_eq!(max_size.width, UNBOUNDED);
+        assert_eq!(max_size.height, 0.0);
+    }
+
+    #[test]
+    fn content_centered_in_fixed_box() {
+        let values = ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                width: Some(LogicalLength::new(200.0)),
+                height: Some(LogicalLength::new(200.0)),
+                content_align_x: Alignment::Center,
+                content_align_y: Alignment::Center,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let node = Node::new(View::default(), vec![]);
+        let layout = calc_block_layout(&(values, vec![fixed_box(50.0, 20.0)], node.into()));
+        assert_eq!(layout.children[0].position, point2(75.0, 90.0));
+    }
+
+    #[test]
+    fn stack_centers_small_child_in_both_axes() {
+        let values = ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                stacked: true,
+                width: Some(LogicalLength::new(400.0)),
+                height: Some(LogicalLength::new(400.0)),
+                content_align_x: Alignment::Center,
+                content_align_y: Alignment::Center,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let node = Node::new(View::default(), vec![]);
+        let layout = calc_block_layout(&(values, vec![fixed_box(100.0, 100.0)], node.into()));
+        assert_eq!(layout.children[0].position, point2(150.0, 150.0));
+    }
+
+    #[test]
+    fn gap_follows_visual_order() {
+        let values = ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                direction: Direction::Horizontal,
+                gap: LogicalLength::new(10.0),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        // Ordered back to front, as a reversed row would be.
+        let children = sort_by_order(vec![
+            (2, fixed_box(20.0, 10.0)),
+            (1, fixed_box(30.0, 10.0)),
+            (0, fixed_box(40.0, 10.0)),
+        ]);
+        let node = Node::new(View::default(), vec![]);
+        let layout = calc_block_layout(&(values, children, node.into()));
+        let xs: Vec<f32> = layout
+            .children
+            .iter()
+            .map(|child| child.position.x)
+            .collect();
+        assert_eq!(xs, vec![0.0, 50.0, 90.0]);
+        assert_eq!(layout.size.width, 110.0);
+    }
+
+    #[test]
+    fn negative_margin_overlaps_previous_child() {
+        let values = ComputedValues::default();
+        let second = {
+            let values = ComputedValues {
+                display: DisplayType::Block(BlockValues {
+                    width: Some(LogicalLength::new(100.0)),
+                    height: Some(LogicalLength::new(50.0)),
+                    margin: SideOffsets2D::new(-10.0, 0.0, 0.0, 0.0),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            let node = Node::new(View::default(), vec![]);
+            calc_block_layout(&(values, vec![], node.into()))
+        };
+        let children = vec![fixed_box(100.0, 50.0), second];
+        let node = Node::new(View::default(), vec![]);
+        let layout = calc_block_layout(&(values, children, node.into()));
+        assert_eq!(layout.children[1].position, point2(0.0, 40.0));
+        assert_eq!(layout.size, size2(100.0, 90.0));
+    }
+
+    #[test]
+    fn order_sorts_children_stably() {
+        let children = vec![
+            fixed_box(10.0, 10.0),
+            fixed_box(20.0, 10.0),
+            fixed_box(30.0, 10.0),
+        ];
+        let sorted = sort_by_order(vec![
+            (2, children[0].clone()),
+            (0, children[1].clone()),
+            (1, children[2].clone()),
+        ]);
+        assert!(
+            sorted
+                == vec![
+                    children[1].clone(),
+                    children[2].clone(),
+                    children[0].clone()
+                ]
+        );
+    }
+
+    #[test]
+    fn spacer_pushes_to_far_edge() {
+        let spacer = Node::new(Spacer::default(), vec![]);
+        let spacer_values = Spacer::default().create_computed_values();
+        spacer.computed_values().set(Some(spacer_values));
+        let spacer = calc_block_layout(&(spacer_values, vec![], spacer.into()));
+
+        let values = ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                direction: Direction::Horizontal,
+                width: Some(LogicalLength::new(100.0)),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let children = vec![fixed_box(10.0, 10.0), spacer, fixed_box(20.0, 10.0)];
+        let node = Node::new(View::default(), vec![]);
+        let layout = calc_block_layout(&(values, children, node.into()));
+        assert_eq!(layout.size, size2(100.0, 10.0));
+        assert_eq!(layout.children[0].position, point2(0.0, 0.0));
+        assert_eq!(layout.children[2].position, point2(80.0, 0.0));
+    }
+
+    #[test]
+    fn fixed_box_anchors_to_window() {
+        let inset = Inset {
+            right: Some(LogicalLength::new(10.0)),
+            bottom: Some(LogicalLength::new(10.0)),
+            ..Default::default()
+        };
+        let margin = SideOffsets2D::new_all_same(0.0);
+        let viewport = size2(400.0, 300.0);
+        let position = fixed_position(&inset, &margin, size2(50.0, 20.0), viewport);
+        assert_eq!(position, point2(340.0, 270.0));
+
+        let inset = Inset {
+            left: Some(LogicalLength::new(5.0)),
+            top: Some(LogicalLength::new(8.0)),
+            ..Default::default()
+        };
+        let position = fixed_position(&inset, &margin, size2(50.0, 20.0), viewport);
+        assert_eq!(position, point2(5.0, 8.0));
+    }
+
+    #[test]
+    fn percentage_padding_uses_containing_width() {
+        let mut block = BlockValues::default();
+        block.padding.left = 4.0;
+        block.padding_percent.left = 0.1;
+        block.padding_percent.top = 0.1;
+        let resolved = resolve_padding(&block, 400.0);
+        assert_eq!(resolved.padding.left, 44.0);
+        assert_eq!(resolved.padding.top, 40.0);
+
+        let max_size = calc_max_size(&resolved, size2(400.0, 400.0));
+        assert_eq!(max_size, size2(356.0, 360.0));
+
+        let unbounded = resolve_padding(&block, std::f32::INFINITY);
+        assert_eq!(unbounded.padding.left, 4.0);
+    }
+
+    fn wrapper(child: EqualRc<LayoutTreeNode>, padding: f32) -> EqualRc<LayoutTreeNode> {
+        let values = ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                padding: SideOffsets2D::new_all_same(padding),
+                margin: SideOffsets2D::new(1.0, 0.0, 0.0, 2.0),
+                ..Default::default()
+            }),
+            border_thickness: SideOffsets2D::new_all_same(1.0),
+            ..Default::default()
+        };
+        let node = Node::new(View::default(), vec![]);
+        calc_block_layout(&(values, vec![child], node.into()))
+    }
+
+    #[test]
+    fn single_child_wraps_tightly() {
+        let layout = wrapper(fixed_box(30.0, 20.0), 5.0);
+        assert_eq!(layout.size, size2(42.0, 32.0));
+        assert_eq!(layout.children[0].position, point2(6.0, 6.0));
+
+        // The child's margin is included, like in the general case.
+        let outer = wrapper(layout, 0.0);
+        assert_eq!(outer.size, size2(46.0, 35.0));
+        assert_eq!(outer.children[0].position, point2(3.0, 2.0));
+    }
+
+    #[test]
+    fn wrapper_chain_adds_up() {
+        let mut layout = fixed_box(10.0, 10.0);
+        for _ in 0..50 {
+            layout = wrapper(layout, 1.0);
+        }
+        assert_eq!(layout.size.width, 10.0 + 4.0 + 49.0 * 6.0);
+    }
+
+    #[test]
+    fn placeholder_keeps_its_size_once_loaded() {
+        let block = BlockValues {
+            width: Some(LogicalLength::new(320.0)),
+            aspect_ratio: Some(16.0 / 9.0),
+            ..Default::default()
+        };
+        let block = resolve_aspect_ratio(&block, 1000.0);
+        assert_eq!(block.height, Some(LogicalLength::new(180.0)));
+        let values = ComputedValues {
+            display: DisplayType::Block(block),
+            ..Default::default()
+        };
+        let node = Node::new(View::default(), vec![]);
+        let placeholder = calc_block_layout(&(values, vec![], node.clone().into()));
+        let image = fixed_box(640.0, 360.0);
+        let loaded = calc_block_layout(&(values, vec![image], node.into()));
+        assert_eq!(placeholder.size, size2(320.0, 180.0));
+        assert_eq!(loaded.size, placeholder.size);
+
+        let auto_sized = BlockValues {
+            aspect_ratio: Some(2.0),
+            ..Default::default()
+        };
+        let block = resolve_aspect_ratio(&auto_sized, 300.0);
+        assert_eq!(block.width, Some(LogicalLength::new(300.0)));
+        assert_eq!(block.height, Some(LogicalLength::new(150.0)));
+        assert_eq!(
+            resolve_aspect_ratio(&auto_sized, std::f32::INFINITY),
+            auto_sized
+        );
+    }
+
+    #[test]
+    fn rows_size_box_independent_of_content() {
+        let block = BlockValues {
+            rows: Some(3),
+            padding: SideOffsets2D::new_all_same(5.0),
+            ..Default::default()
+        };
+        let block = resolve_rows(&block, 0.0, 20.0);
+        assert_eq!(block.height, Some(LogicalLength::new(70.0)));
+
+        let values = ComputedValues {
+            display: DisplayType::Block(block),
+            ..Default::default()
+        };
+        let node = Node::new(View::default(), vec![]);
+        let one_line = fixed_box(80.0, 20.0);
+        let layout = calc_block_layout(&(values, vec![one_line], node.into()));
+        assert_eq!(layout.size.height, 70.0);
+    }
+
+    #[test]
+    fn overflow_expand_grows_fixed_size_to_content() {
+        let values = |overflow_expand| ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                width: Some(LogicalLength::new(50.0)),
+                overflow_expand,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let node = Node::new(View::default(), vec![]);
+
+        let layout = calc_block_layout(&(
+            values(true),
+            vec![fixed_box(80.0, 10.0)],
+            node.clone().into(),
+        ));
+        assert_eq!(layout.size.width, 80.0);
+
+        let layout = calc_block_layout(&(values(false), vec![fixed_box(80.0, 10.0)], node.into()));
+        assert_eq!(layout.size.width, 50.0);
+
+        let layout = calc_block_layout(&(
+            values(true),
+            vec![],
+            Node::new(View::default(), vec![]).into(),
+        ));
+        assert_eq!(layout.size.width, 50.0);
+    }
+
+    #[test]
+    fn scrollable_extent_of_overflowing_box() {
+        let values = ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                height: Some(LogicalLength::new(300.0)),
+                overflow_x: Overflow::Hidden,
+                overflow_y: Overflow::Hidden,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let node = Node::new(View::default(), vec![]);
+        node.computed_values().set(Some(values));
+        let layout = calc_block_layout(&(values, vec![fixed_box(100.0, 800.0)], node.into()));
+        assert_eq!(layout.size.height, 300.0);
+        assert_eq!(layout.content_size(), size2(100.0, 800.0));
+        assert_eq!(layout.scrollable_extent(), size2(0.0, 500.0));
+    }
+
+    #[test]
+    fn rows_aligned_to_the_end_share_a_right_edge() {
+        let values = ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                width: Some(LogicalLength::new(100.0)),
+                padding: SideOffsets2D::new_all_same(5.0),
+                align_items: Alignment::End,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let children = vec![
+            fixed_box(40.0, 10.0),
+            fixed_box(70.0, 10.0),
+            fixed_box(20.0, 10.0),
+        ];
+        let node: AnyNode = Node::new(View::default(), vec![]).into();
+        let layout = calc_block_layout(&(values, children, node.clone()));
+        for child in &layout.children {
+            assert_eq!(child.position.x + child.layout.size.width, 95.0);
+        }
+        assert_eq!(layout.children[2].position.y, 25.0);
+
+        // A row wider than the list starts at its left edge.
+        let layout = calc_block_layout(&(values, vec![fixed_box(150.0, 10.0)], node));
+        assert_eq!(layout.children[0].position.x, 5.0);
+    }
+
+    #[test]
+    fn overflow_is_reported_per_edge() {
+        let values = ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                width: Some(LogicalLength::new(100.0)),
+                height: Some(LogicalLength::new(100.0)),
+                overflow_y: Overflow::Hidden,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let node: AnyNode = Node::new(View::default(), vec![]).into();
+        let layout = calc_block_layout(&(values, vec![fixed_box(100.0, 150.0)], node.clone()));
+        assert_eq!(layout.overflow(), SideOffsets2D::new(0.0, 0.0, 50.0, 0.0));
+
+        // Negative margins pull a child out at the top and left.
+        let child_values = ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                width: Some(LogicalLength::new(50.0)),
+                height: Some(LogicalLength::new(50.0)),
+                margin: SideOffsets2D::new(-20.0, 0.0, 0.0, -10.0),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let child = Node::new(View::default(), vec![]);
+        let child = calc_block_layout(&(child_values, vec![], child.into()));
+        let layout = calc_block_layout(&(values, vec![child], node));
+        assert_eq!(layout.overflow(), SideOffsets2D::new(20.0, 0.0, 0.0, 10.0));
+    }
+
+    #[test]
+    fn scrollbar_thumb_follows_offset() {
+        let values = ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                width: Some(LogicalLength::new(200.0)),
+                height: Some(LogicalLength::new(100.0)),
+                overflow_x: Overflow::Scroll,
+                overflow_y: Overflow::Scroll,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let node = Node::new(View::default(), vec![]);
+        node.computed_values().set(Some(values));
+        let layout = calc_block_layout(&(values, vec![fixed_box(100.0, 200.0)], node.into()));
+
+        let bars = layout.scrollbars(vec2(0.0, 50.0), 10.0);
+        assert_eq!(bars.horizontal, None);
+        let vertical = bars.vertical.unwrap();
+        assert_eq!(
+            vertical.track,
+            LogicalRect::new(point2(190.0, 0.0), size2(10.0, 100.0))
+        );
+        // Half of the content is visible, scrolled halfway down.
+        assert_eq!(
+            vertical.thumb,
+            LogicalRect::new(point2(190.0, 25.0), size2(10.0, 50.0))
+        );
+    }
+
+    #[test]
+    fn overflow_axes_clip_and_scroll_separately() {
+        let container = |overflow_x, overflow_y| {
+            let values = ComputedValues {
+                display: DisplayType::Block(BlockValues {
+                    width: Some(LogicalLength::new(100.0)),
+                    height: Some(LogicalLength::new(100.0)),
+                    overflow_x,
+                    overflow_y,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            let node = Node::new(View::default(), vec![]);
+            node.computed_values().set(Some(values));
+            calc_block_layout(&(values, vec![fixed_box(300.0, 200.0)], node.into()))
+        };
+
+        let layout = container(Overflow::Hidden, Overflow::Scroll);
+        let clip = layout.clip.as_ref().unwrap();
+        assert_eq!(clip.rect.size, size2(100.0, 100.0));
+        assert_eq!(layout.scrollable_extent(), size2(200.0, 100.0));
+        // Only the axis that scrolls gets a scrollbar.
+        let bars = layout.scrollbars(vec2(0.0, 0.0), 10.0);
+        assert!(bars.horizontal.is_none());
+        assert_eq!(bars.vertical.unwrap().track.size.height, 100.0);
+
+        let layout = container(Overflow::Visible, Overflow::Hidden);
+        let clip = layout.clip.as_ref().unwrap();
+        assert!(clip.rect.min_x() < 0.0 && clip.rect.max_x() > 300.0);
+        assert_eq!((clip.rect.min_y(), clip.rect.max_y()), (0.0, 100.0));
+        assert_eq!(clip.radius, BorderRadius::default());
+    }
+
+    #[test]
+    fn max_height_caps_scrolling_menu() {
+        let menu = |items: usize| {
+            let values = ComputedValues {
+                display: DisplayType::Block(BlockValues {
+                    max_height: Some(LogicalLength::new(200.0)),
+                    overflow_x: Overflow::Scroll,
+                    overflow_y: Overflow::Scroll,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            let children = (0..items).map(|_| fixed_box(100.0, 20.0)).collect();
+            let node = Node::new(View::default(), vec![]);
+            calc_block_layout(&(values, children, node.into()))
+        };
+
+        let short = menu(2);
+        assert_eq!(short.size.height, 40.0);
+        assert!(short.clip.is_some());
+        assert_eq!(short.scrollable_extent(), size2(0.0, 0.0));
+
+        let long = menu(50);
+        assert_eq!(long.size.height, 200.0);
+        assert_eq!(long.scrollable_extent(), size2(0.0, 800.0));
+    }
+
+    #[test]
+    fn box_sizing_decides_what_width_measures() {
+        let content_width = |box_sizing| {
+            let block = BlockValues {
+                width: Some(LogicalLength::new(200.0)),
+                padding: SideOffsets2D::new_all_same(10.0),
+                box_sizing,
+                ..Default::default()
+            };
+            let block = resolve_box_sizing(&block, SideOffsets2D::new_all_same(0.0));
+            let values = ComputedValues {
+                display: DisplayType::Block(block),
+                ..Default::default()
+            };
+            let node = Node::new(View::default(), vec![]);
+            let layout = calc_block_layout(&(values, vec![], node.into()));
+            (
+                calc_max_size(&block, size2(1000.0, 1000.0)).width,
+                layout.size.width,
+            )
+        };
+        assert_eq!(content_width(BoxSizing::BorderBox), (180.0, 200.0));
+        assert_eq!(content_width(BoxSizing::ContentBox), (200.0, 220.0));
+    }
+}
@@ -0,0 +1,50 @@
+use super::{LayoutChild, LayoutTreeNode, LogicalSize};
+use crate::util::equal_rc::EqualRc;
+use std::any::Any;
+use std::fmt;
+
+/// Places the children of a block whose style sets `custom_layout`, for
+/// arrangements the built in layouts don't cover, like a circular menu
+/// or a masonry grid. Padding, borders, and the size properties of the
+/// block itself are still applied around whatever it returns. Its
+/// children don't `fill`.
+pub trait CustomLayout: Any {
+    /// The space each child gets, given the sizes of the children when
+    /// laid out under `max_size`, for layouts that divide their box into
+    /// cells. Children are then laid out in their cells, so that e.g.
+    /// text wraps at the width of its column rather than that of the
+    /// whole grid. `None`, the default, lays them out under `max_size`.
+    fn cells(&self, _sizes: &[LogicalSize], _max_size: LogicalSize) -> Option<Vec<LogicalSize>> {
+        None
+    }
+
+    /// Places `children`, in DOM order as rearranged by `order`, each
+    /// laid out in its cell or else under `max_size`. Returns the size
+    /// of the content box and the children with their positions in it.
+    /// Child margins are left to the layout to honor or not.
+    fn layout(
+        &self,
+        children: &[EqualRc<LayoutTreeNode>],
+        max_size: LogicalSize,
+    ) -> (LogicalSize, Vec<LayoutChild>);
+}
+
+/// A custom layout as set on a style. Layouts are told apart by their
+/// address and type, so that they can take part in memoized layout like
+/// any other style value: one that should place children differently
+/// has to be a different value.
+#[derive(Clone, Copy)]
+pub struct CustomLayoutRef(pub &'static dyn CustomLayout);
+
+impl PartialEq for CustomLayoutRef {
+    fn eq(&self, other: &CustomLayoutRef) -> bool {
+        let address = |layout: &'static dyn CustomLayout| layout as *const _ as *const u8;
+        address(self.0) == address(other.0) && Any::type_id(self.0) == Any::type_id(other.0)
+    }
+}
+
+impl fmt::Debug for CustomLayoutRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CustomLayoutRef({:p})", self.0 as *const _ as *const u8)
+    }
+}
@@ -0,0 +1,110 @@
+use super::{LayoutTreeNode, LogicalPoint, LogicalRect, LogicalSideOffsets, RenderData};
+use crate::style::DisplayType;
+use euclid::{point2, size2, vec2};
+
+/// Geometry for a layout inspector overlay, in window coordinates.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DebugShape {
+    /// The border box of a node or a line of text.
+    Box(LogicalRect),
+    /// The area inside of a block's border and padding, only produced
+    /// when the block has either.
+    Content(LogicalRect),
+    /// The baseline of a line of text.
+    Baseline { start: LogicalPoint, width: f32 },
+}
+
+pub(super) fn collect_shapes(
+    node: &LayoutTreeNode,
+    position: LogicalPoint,
+    shapes: &mut Vec<DebugShape>,
+) {
+    shapes.push(DebugShape::Box(LogicalRect::new(position, node.size)));
+    match node.render {
+        RenderData::Node(ref dom_node) => {
+            let values = dom_node.computed_values().get();
+            if let Some(DisplayType::Block(block)) = values.map(|values| values.display) {
+                let inset = block.padding + values.unwrap().border_thickness;
+                if inset != LogicalSideOffsets::zero() {
+                    shapes.push(DebugShape::Content(LogicalRect::new(
+                        position + vec2(inset.left, inset.top),
+                        size2(
+                            (node.size.width - inset.horizontal()).max(0.0),
+                            (node.size.height - inset.vertical()).max(0.0),
+                        ),
+                    )));
+                }
+            }
+        }
+        RenderData::Text { ref text, .. } => {
+            shapes.push(DebugShape::Baseline {
+                start: point2(position.x, position.y + text.baseline),
+                width: node.size.width,
+            });
+        }
+    }
+    for child in &node.children {
+        collect_shapes(&child.layout, position + child.position.to_vector(), shapes);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{collect_shapes, DebugShape};
+    use crate::dom::{Node, View};
+    use crate::layout::{LayoutChild, LayoutTreeNode, LogicalRect, RenderData};
+    use crate::style::{BlockValues, BorderRadius, ComputedValues, DisplayType};
+    use crate::util::equal_rc::EqualRc;
+    use euclid::{point2, size2, SideOffsets2D};
+
+    fn node(padding: f32, children: Vec<LayoutChild>) -> EqualRc<LayoutTreeNode> {
+        let dom_node = Node::new(View::default(), vec![]);
+        dom_node.computed_values().set(Some(ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                padding: SideOffsets2D::new_all_same(padding),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }));
+        EqualRc::new(LayoutTreeNode {
+            size: size2(40.0, 40.0),
+            margin: SideOffsets2D::new_all_same(0.0),
+            render: RenderData::Node(dom_node.into()),
+            children,
+            clip: None,
+            border_radius: BorderRadius::default(),
+            truncated: false,
+            lines: 0,
+        })
+    }
+
+    #[test]
+    fn one_box_per_node() {
+        let leaf = LayoutChild {
+            position: point2(5.0, 5.0),
+            layout: node(0.0, vec![]),
+        };
+        let middle = LayoutChild {
+            position: point2(10.0, 0.0),
+            layout: node(2.0, vec![leaf.clone()]),
+        };
+        let root = node(0.0, vec![middle, leaf]);
+
+        let mut shapes = vec![];
+        collect_shapes(&root, point2(0.0, 0.0), &mut shapes);
+        let boxes: Vec<_> = shapes
+            .iter()
+            .filter_map(|shape| match shape {
+                DebugShape::Box(rect) => Some(*rect),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(boxes.len(), 4);
+        // The nested leaf is offset by both of its ancestors.
+        assert_eq!(boxes[2].origin, point2(15.0, 5.0));
+        assert!(shapes.contains(&DebugShape::Content(LogicalRect::new(
+            point2(12.0, 2.0),
+            size2(36.0, 36.0)
+        ))));
+    }
+}
@@ -1,13 +1,19 @@
 use super::{
-    block,
-    text::{TextLayoutInfo, TextState},
-    LayoutChild, LayoutText, LayoutTreeNode, LogicalSideOffsets, LogicalSize, RenderData,
+    attributed::{AttributedText, RunStyle, TextDecoration},
+    block, bounded, finite_size, stats,
+    text::{self, TextLayoutInfo, TextOptions, TextState},
+    DecorationSegment, LayoutCache, LayoutChild, LayoutClip, LayoutKey, LayoutText, LayoutTreeNode,
+    LogicalLength, LogicalSideOffsets, LogicalSize, RenderData, TextFragment,
 };
 use crate::dom::{element::DynamicNode, node::AnyNode, node::NodeRef};
-use crate::style::{ComputedValues, DisplayType};
+use crate::style::{
+    BlockValues, BorderRadius, BoxSizing, ChildOverflow, ComputedValues, DisplayType, Position,
+    TextAlign, WhiteSpace,
+};
 use crate::util::equal_rc::EqualRc;
-use euclid::{point2, size2};
-use moxie::*;
+use euclid::{point2, size2, Rect};
+use std::ops::Range;
+use std::rc::Rc;
 
 #[derive(PartialEq)]
 enum InlineLayoutItem {
@@ -15,23 +21,233 @@ enum InlineLayoutItem {
     Text {
         text: EqualRc<TextLayoutInfo>,
         parent: AnyNode,
+        index: usize,
+        /// Where `text` starts in the source text, for runs of an
+        /// `AttributedText`.
+        offset: usize,
+        style: RunStyle,
+        /// Byte ranges of `text` to decorate.
+        decorations: Vec<(Range<usize>, TextDecoration)>,
     },
 }
 
+/// How the lines of an inline box are aligned, with the last line
+/// aligned separately for `text_align_last`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct LineAlign {
+    lines: TextAlign,
+    last: TextAlign,
+}
+
+impl LineAlign {
+    fn new(values: &ComputedValues) -> LineAlign {
+        let last = values.text_align_last.unwrap_or(match values.text_align {
+            TextAlign::Justify => TextAlign::Left,
+            align => align,
+        });
+        LineAlign {
+            lines: values.text_align,
+            last,
+        }
+    }
+
+    fn is_left(&self) -> bool {
+        self.lines == TextAlign::Left && self.last == TextAlign::Left
+    }
+}
+
+impl Default for LineAlign {
+    fn default() -> Self {
+        LineAlign {
+            lines: TextAlign::Left,
+            last: TextAlign::Left,
+        }
+    }
+}
+
+/// Where a line `extra` narrower than its box starts, and how much
+/// wider each of its `gaps` between words gets.
+fn line_alignment(align: TextAlign, extra: f32, gaps: usize) -> (f32, f32) {
+    let extra = extra.max(0.0);
+    match align {
+        TextAlign::Left => (0.0, 0.0),
+        TextAlign::Center => (extra / 2.0, 0.0),
+        TextAlign::Right => (extra, 0.0),
+        TextAlign::Justify if gaps > 0 => (0.0, extra / gaps as f32),
+        TextAlign::Justify => (0.0, 0.0),
+    }
+}
+
+fn justify_gaps(layout: &LayoutTreeNode) -> usize {
+    match layout.render {
+        RenderData::Text { ref text, .. } => text.info.justify_gaps(text.run_range()),
+        RenderData::Node(_) => 0,
+    }
+}
+
+fn trailing_space_width(layout: &LayoutTreeNode) -> f32 {
+    match layout.render {
+        RenderData::Text { ref text, .. } => text.info.trailing_space_width(text.run_range()),
+        RenderData::Node(_) => 0.0,
+    }
+}
+
+/// Lays out a line of text again with its spaces widened by `extra`.
+fn justify(layout: &LayoutTreeNode, extra: f32) -> Option<EqualRc<LayoutTreeNode>> {
+    if let RenderData::Text {
+        ref text,
+        ref parent,
+    } = layout.render
+    {
+        let (fragments, width) = text.info.justified_fragments(text.run_range(), extra);
+        let decorations =
+            text.info
+                .decoration_segments(text.run_range(), &run_decorations(text), extra);
+        Some(with_fragments(
+            layout,
+            text,
+            parent,
+            fragments,
+            decorations,
+            width,
+            layout.truncated,
+        ))
+    } else {
+        None
+    }
+}
+
+/// Ends a line of text with its ellipsis, cut down to fit in `width`.
+fn ellipsize(layout: &LayoutTreeNode, width: f32) -> Option<EqualRc<LayoutTreeNode>> {
+    if let RenderData::Text {
+        ref text,
+        ref parent,
+    } = layout.render
+    {
+        let (fragments, width, decorations) =
+            text.info
+                .ellipsized_fragments(text.run_range(), &run_decorations(text), width)?;
+        Some(with_fragments(
+            layout,
+            text,
+            parent,
+            fragments,
+            decorations,
+            width,
+            true,
+        ))
+    } else {
+        None
+    }
+}
+
+/// The decorations of a line of text, as byte ranges of the text its
+/// `info` was shaped from.
+fn run_decorations(text: &LayoutText) -> Vec<(Range<usize>, TextDecoration)> {
+    text.decorations
+        .iter()
+        .map(|segment| {
+            let range = segment.range.start - text.offset..segment.range.end - text.offset;
+            (range, segment.kind)
+        })
+        .collect()
+}
+
+/// Moves decorations measured on the text `info` was shaped from to
+/// where that text starts in the source text.
+fn to_source_decorations(
+    decorations: Vec<DecorationSegment>,
+    offset: usize,
+) -> Vec<DecorationSegment> {
+    decorations
+        .into_iter()
+        .map(|segment| DecorationSegment {
+            range: segment.range.start + offset..segment.range.end + offset,
+            ..segment
+        })
+        .collect()
+}
+
+/// A copy of a line of text with its glyphs and decorations replaced.
+/// The decorations are given like `decoration_segments` returns them.
+fn with_fragments(
+    layout: &LayoutTreeNode,
+    text: &LayoutText,
+    parent: &AnyNode,
+    fragments: Vec<TextFragment>,
+    decorations: Vec<DecorationSegment>,
+    width: f32,
+    truncated: bool,
+) -> EqualRc<LayoutTreeNode> {
+    EqualRc::new(LayoutTreeNode {
+        render: RenderData::Text {
+            text: LayoutText {
+                fragments,
+                size: text.size,
+                index: text.index,
+                range: text.range.clone(),
+                baseline: text.baseline,
+                style: text.style,
+                whitespace: vec![],
+                decorations: to_source_decorations(decorations, text.offset),
+                info: text.info.clone(),
+                offset: text.offset,
+            },
+            parent: parent.clone(),
+        },
+        size: size2(width, layout.size.height),
+        margin: layout.margin,
+        children: vec![],
+        clip: None,
+        border_radius: BorderRadius::default(),
+        truncated,
+        lines: layout.lines,
+    })
+}
+
 struct LayoutState {
     children: Vec<LayoutChild>,
+    /// The index of the first child and the width of each line.
+    line_starts: Vec<(usize, f32)>,
     longest_line: f32,
     height: f32,
+    lines: u32,
+    max_lines: Option<u32>,
+    max_width: f32,
+    baseline_grid: Option<f32>,
+    truncated: bool,
 }
 
 impl LayoutState {
     fn add_line(&mut self, line: LineState) {
+        if line.line_items.is_empty() {
+            return;
+        }
+        if self
+            .max_lines
+            .map_or(false, |max_lines| self.lines >= max_lines)
+        {
+            if !self.truncated {
+                self.ellipsize_last_line();
+            }
+            self.truncated = true;
+            return;
+        }
+        self.lines += 1;
+
+        if let Some(grid) = self.baseline_grid.filter(|grid| *grid > 0.0) {
+            let baseline = self.height + line.ascender;
+            self.height += (baseline / grid).ceil() * grid - baseline;
+        }
+
+        self.line_starts.push((self.children.len(), line.x));
         for item in line.line_items {
             let LineItem {
                 ascender,
                 x,
                 layout,
             } = item;
+            self.truncated |= layout.truncated;
             self.children.push(LayoutChild {
                 position: point2(x, self.height + line.ascender - ascender),
                 layout,
@@ -41,6 +257,57 @@ impl LayoutState {
         self.height += line.height;
         self.longest_line = self.longest_line.max(line.x);
     }
+
+    /// Ends the last line shown with an ellipsis once `max_lines` leaves
+    /// out the ones after it, if its last item is text with one set.
+    fn ellipsize_last_line(&mut self) {
+        let (first, line_width) = match self.line_starts.last_mut() {
+            Some(line) => line,
+            None => return,
+        };
+        if let Some(child) = self.children[*first..].last_mut() {
+            if let Some(layout) = ellipsize(&child.layout, self.max_width - child.position.x) {
+                *line_width += layout.size.width - child.layout.size.width;
+                self.longest_line = self.longest_line.max(*line_width);
+                child.layout = layout;
+            }
+        }
+    }
+
+    /// Moves the lines into place within `width`. When lines were left
+    /// out by `max_lines`, the last one shown isn't the end of the text,
+    /// so it's aligned like the others.
+    fn align_lines(&mut self, width: f32, align: LineAlign) {
+        let count = self.line_starts.len();
+        for line in 0..count {
+            let (first, line_width) = self.line_starts[line];
+            let end = self
+                .line_starts
+                .get(line + 1)
+                .map_or(self.children.len(), |&(next, _)| next);
+            let items = &mut self.children[first..end];
+            let text_align = if line + 1 == count && !self.truncated {
+                align.last
+            } else {
+                align.lines
+            };
+            let line_width = line_width
+                - items
+                    .last()
+                    .map_or(0.0, |item| trailing_space_width(&item.layout));
+            let gaps = items.iter().map(|item| justify_gaps(&item.layout)).sum();
+            let (mut shift, extra) = line_alignment(text_align, width - line_width, gaps);
+            for item in items {
+                item.position.x += shift;
+                if extra > 0.0 {
+                    if let Some(justified) = justify(&item.layout, extra) {
+                        shift += justified.size.width - item.layout.size.width;
+                        item.layout = justified;
+                    }
+                }
+            }
+        }
+    }
 }
 
 // Turns into LayoutChild
@@ -71,7 +338,8 @@ impl LineState {
 
     fn insert_block_item(&mut self, layout: EqualRc<LayoutTreeNode>) -> bool {
         let size = layout.size;
-        if self.x + size.width > self.max_width {
+        // A box wider than a whole line still gets a line to itself.
+        if self.x + size.width > self.max_width && !self.line_items.is_empty() {
             return false;
         }
         self.line_items.push(LineItem {
@@ -84,8 +352,40 @@ impl LineState {
         true
     }
 
-    fn insert_text_item(&mut self, parent: AnyNode, state: &mut TextState) -> bool {
-        if let Some(line) = state.fill_line(self.max_width - self.x, self.line_items.is_empty()) {
+    #[allow(clippy::too_many_arguments)]
+    fn insert_text_item(
+        &mut self,
+        parent: AnyNode,
+        index: usize,
+        offset: usize,
+        style: RunStyle,
+        decorations: &[(Range<usize>, TextDecoration)],
+        info: &EqualRc<TextLayoutInfo>,
+        state: &mut TextState,
+    ) -> bool {
+        let available = self.max_width - self.x;
+        if let Some(mut line) = state.fill_line(available, self.line_items.is_empty()) {
+            let overflowing = line.width > available;
+            let whitespace = if overflowing {
+                vec![]
+            } else {
+                info.whitespace_markers(line.range.clone())
+            };
+            let range = info.source_range(line.range.clone());
+            let mut segments = info.decoration_segments(range.clone(), decorations, 0.0);
+            // A line that overflows without an ellipsis keeps all of its
+            // text, even if some of it ends up clipped.
+            let mut truncated = false;
+            if overflowing {
+                if let Some((fragments, width, ellipsized)) =
+                    info.ellipsized_fragments(range.clone(), decorations, available)
+                {
+                    line.fragments = fragments;
+                    line.width = width;
+                    segments = ellipsized;
+                    truncated = true;
+                }
+            }
             self.line_items.push(LineItem {
                 ascender: line.ascender,
                 x: self.x,
@@ -94,12 +394,24 @@ impl LineState {
                         text: LayoutText {
                             fragments: line.fragments,
                             size: line.text_size,
+                            index,
+                            range: range.start + offset..range.end + offset,
+                            baseline: line.ascender,
+                            style,
+                            whitespace,
+                            decorations: to_source_decorations(segments, offset),
+                            info: info.clone(),
+                            offset,
                         },
                         parent,
                     },
                     size: size2(line.width, line.height),
                     margin: LogicalSideOffsets::default(),
                     children: vec![],
+                    clip: None,
+                    border_radius: BorderRadius::default(),
+                    truncated,
+                    lines: 1,
                 }),
             });
 
@@ -114,52 +426,110 @@ impl LineState {
     }
 }
 
+#[illicit::from_env(cache: &Rc<LayoutCache>)]
 fn collect_inline_items(
     node: NodeRef,
     parent_values: &ComputedValues,
     max_size: LogicalSize,
+    after_space: &mut bool,
     items: &mut Vec<InlineLayoutItem>,
 ) {
-    for child in node.children() {
+    for (index, child) in node.children().enumerate() {
         topo::call! {
             {
-                match child {
+                match child.get_node() {
                     DynamicNode::Node(node) => {
                         let values = node.computed_values().get().unwrap();
                         match values.display {
+                            DisplayType::Block(ref block) if block.position == Position::Fixed => {
+                                block::layout_fixed(node, &values, block);
+                            }
                             DisplayType::Block(ref block) => {
-                                let layout = block::layout_block(node, &values, block, max_size).into();
+                                let layout = layout_inline_block(node, &values, block, max_size, parent_values);
                                 items.push(InlineLayoutItem::Block(layout));
+                                *after_space = false;
                             }
                             DisplayType::Inline(_) => {
-                                collect_inline_items(node, &values, max_size, items);
+                                collect_inline_items(node, &values, max_size, after_space, items);
                             }
                         }
                     }
-                    DynamicNode::Text(text) => items.push(InlineLayoutItem::Text {
-                        text: memo!((text.to_owned(), parent_values.text_size.get()), move |(text, size)| {
-                            EqualRc::new(TextLayoutInfo::new(
-                                (*text).to_owned(),
-                                *size,
-                            ))
-                        }).into(),
-                        parent: node.to_owned(),
-                    })
+                    DynamicNode::Text(text) => {
+                        let options = TextOptions::new(parent_values);
+                        let starts_after_space = *after_space;
+                        *after_space = text::ends_in_space(text, &options, starts_after_space);
+                        items.push(InlineLayoutItem::Text {
+                            text: cache.memo(LayoutKey::text(node.to_owned(), text), ((*text).clone(), options, starts_after_space), move |(text, options, after_space)| {
+                                text::shape_text(text.clone(), *options, *after_space)
+                            }),
+                            parent: node.to_owned(),
+                            index,
+                            offset: 0,
+                            style: RunStyle::default(),
+                            decorations: vec![],
+                        })
+                    }
                 }
             }
         }
     }
 }
 
+fn child_overflow(values: &ComputedValues) -> ChildOverflow {
+    match values.display {
+        DisplayType::Inline(inline) => inline.child_overflow,
+        DisplayType::Block(_) => ChildOverflow::Visible,
+    }
+}
+
+/// Lays out a block inside of inline content. When the block doesn't
+/// fit on a line of its own and its parent shrinks such children, it's
+/// laid out again as wide as the line instead.
+fn layout_inline_block(
+    node: NodeRef,
+    values: &ComputedValues,
+    block_values: &BlockValues,
+    max_size: LogicalSize,
+    parent_values: &ComputedValues,
+) -> EqualRc<LayoutTreeNode> {
+    if child_overflow(parent_values) == ChildOverflow::Shrink {
+        if let Some(available) = bounded(max_size.width) {
+            let available = (available - block_values.margin.horizontal()).max(0.0);
+            let size = block::measure_block(node, values, block_values, max_size);
+            if size.width > available {
+                let shrunk = BlockValues {
+                    width: Some(LogicalLength::new(available)),
+                    min_width: None,
+                    box_sizing: BoxSizing::BorderBox,
+                    ..*block_values
+                };
+                return block::layout_block(node, values, &shrunk, max_size);
+            }
+        }
+    }
+    block::layout_block(node, values, block_values, max_size)
+}
+
 fn calc_inline_layout(
     node: AnyNode,
     max_width: f32,
+    max_lines: Option<u32>,
+    baseline_grid: Option<f32>,
+    align: LineAlign,
+    clip_overflow: bool,
     items: &[InlineLayoutItem],
 ) -> EqualRc<LayoutTreeNode> {
+    stats::count(|stats| stats.boxes += 1);
     let mut state = LayoutState {
         height: 0.0f32,
         longest_line: 0.0f32,
         children: vec![],
+        line_starts: vec![],
+        lines: 0,
+        max_lines,
+        max_width,
+        baseline_grid,
+        truncated: false,
     };
 
     let mut line = LineState::new(max_width);
@@ -173,10 +543,25 @@ fn calc_inline_layout(
                     line.insert_block_item(layout.clone().into());
                 }
             }
-            InlineLayoutItem::Text { text, parent } => {
+            InlineLayoutItem::Text {
+                text,
+                parent,
+                index,
+                offset,
+                style,
+                decorations,
+            } => {
                 let mut text_state = TextState::new(&**text);
                 loop {
-                    line.insert_text_item(parent.clone(), &mut text_state);
+                    line.insert_text_item(
+                        parent.clone(),
+                        *index,
+                        *offset,
+                        *style,
+                        decorations,
+                        text,
+                        &mut text_state,
+                    );
                     if text_state.finished() {
                         break;
                     }
@@ -187,52 +572,418 @@ fn calc_inline_layout(
         }
     }
     state.add_line(line);
-    let size = size2(state.longest_line, state.height);
+    // Aligned lines take up the whole width, when there is a limit.
+    let mut width = match bounded(max_width) {
+        Some(max_width) if !align.is_left() => max_width,
+        _ => state.longest_line,
+    };
+    state.align_lines(width, align);
+    // Lines that run past a limited width are cut off at it, rather than
+    // making the parent wider.
+    let clip = if clip_overflow && width > max_width {
+        width = max_width;
+        Some(LayoutClip {
+            rect: Rect::new(point2(0.0, 0.0), size2(width, state.height)),
+            radius: BorderRadius::default(),
+        })
+    } else {
+        None
+    };
+    let size = finite_size(size2(width, state.height));
     let children = state.children;
+    let border_radius = node
+        .computed_values()
+        .get()
+        .map_or_else(BorderRadius::default, |values| {
+            values.border_radius.clamped(size)
+        });
 
     EqualRc::new(LayoutTreeNode {
         render: RenderData::Node(node),
         margin: LogicalSideOffsets::default(),
         size,
         children,
+        clip,
+        border_radius,
+        truncated: state.truncated,
+        lines: state.lines,
     })
 }
 
+/// Text that doesn't wrap is cut off where its parent hides overflow,
+/// instead of widening the parent.
+fn clips_overflow(values: &ComputedValues, parent_clips: bool) -> bool {
+    parent_clips && values.white_space == WhiteSpace::NoWrap
+}
+
+#[illicit::from_env(cache: &Rc<LayoutCache>)]
 pub fn layout_inline(
     node: NodeRef,
     values: &ComputedValues,
     max_size: LogicalSize,
+    parent_clips: bool,
 ) -> EqualRc<LayoutTreeNode> {
     let mut items = vec![];
 
-    collect_inline_items(node, values, max_size, &mut items);
+    // Whitespace at the start of the content collapses away like that
+    // at the start of any other line.
+    collect_inline_items(node, values, max_size, &mut true, &mut items);
 
-    memo!((node.to_owned(), max_size.width, items), |(
-        node,
-        max_width,
-        items,
-    )| {
-        calc_inline_layout(node.clone(), *max_width, &items[..])
-    })
+    let max_lines = if let DisplayType::Inline(inline) = values.display {
+        inline.max_lines
+    } else {
+        None
+    };
+
+    let baseline_grid = values.baseline_grid.map(|grid| grid.get());
+
+    cache.memo(
+        LayoutKey::Node(node.to_owned()),
+        (
+            node.to_owned(),
+            max_size.width,
+            max_lines,
+            baseline_grid,
+            LineAlign::new(values),
+            clips_overflow(values, parent_clips) || child_overflow(values) == ChildOverflow::Clip,
+            items,
+        ),
+        |(node, max_width, max_lines, baseline_grid, align, clip_overflow, items)| {
+            calc_inline_layout(
+                node.clone(),
+                *max_width,
+                *max_lines,
+                *baseline_grid,
+                *align,
+                *clip_overflow,
+                &items[..],
+            )
+        },
+    )
 }
 
+#[illicit::from_env(cache: &Rc<LayoutCache>)]
 pub fn layout_text(
     node: AnyNode,
-    text: &str,
+    index: usize,
+    text: &Rc<str>,
     max_width: f32,
     values: &ComputedValues,
+    parent_clips: bool,
 ) -> EqualRc<LayoutTreeNode> {
-    let size = values.text_size;
-    memo!((max_width, text.to_owned(), node, size), |(
-        max_width,
-        text,
+    let options = TextOptions::new(values);
+    let baseline_grid = values.baseline_grid.map(|grid| grid.get());
+    let align = LineAlign::new(values);
+    let clip_overflow = clips_overflow(values, parent_clips);
+    cache.memo(
+        LayoutKey::text(node.clone(), text),
+        (
+            max_width,
+            text.clone(),
+            node,
+            index,
+            options,
+            baseline_grid,
+            align,
+            clip_overflow,
+        ),
+        |(max_width, text, node, index, options, baseline_grid, align, clip_overflow)| {
+            let item = InlineLayoutItem::Text {
+                text: text::shape_text(text.clone(), *options, true),
+                parent: node.clone(),
+                index: *index,
+                offset: 0,
+                style: RunStyle::default(),
+                decorations: vec![],
+            };
+            calc_inline_layout(
+                node.clone(),
+                *max_width,
+                None,
+                *baseline_grid,
+                *align,
+                *clip_overflow,
+                &[item],
+            )
+        },
+    )
+}
+
+/// Lays out `attributed` as the content of `node`, restyling each run.
+/// Every run is shaped on its own, but they flow into the same lines
+/// as if they were the text of neighbouring spans.
+pub fn layout_attributed(
+    node: AnyNode,
+    values: &ComputedValues,
+    attributed: &AttributedText,
+    max_width: f32,
+) -> EqualRc<LayoutTreeNode> {
+    let mut after_space = true;
+    let items: Vec<_> = attributed
+        .runs()
+        .into_iter()
+        .map(|(range, style)| {
+            let options = TextOptions::new(&style.apply(values));
+            let decorations = attributed.run_decorations(&range);
+            let offset = range.start;
+            let run: Rc<str> = attributed.text[range].into();
+            let starts_after_space = after_space;
+            after_space = text::ends_in_space(&run, &options, starts_after_space);
+            InlineLayoutItem::Text {
+                text: text::shape_text(run, options, starts_after_space),
+                parent: node.clone(),
+                index: 0,
+                offset,
+                style,
+                decorations,
+            }
+        })
+        .collect();
+    calc_inline_layout(
         node,
-        size,
-    )| {
-        let item = InlineLayoutItem::Text {
-            text: EqualRc::new(TextLayoutInfo::new(text.to_owned(), size.get())),
-            parent: node.clone(),
+        max_width,
+        None,
+        values.baseline_grid.map(|grid| grid.get()),
+        LineAlign::new(values),
+        false,
+        &items,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{calc_inline_layout, line_alignment, InlineLayoutItem, LineAlign};
+    use crate::dom::{Node, Span, View};
+    use crate::layout::{LayoutTreeNode, LogicalSideOffsets, RenderData, UNBOUNDED};
+    use crate::style::{BorderRadius, ComputedValues, TextAlign};
+    use crate::util::equal_rc::EqualRc;
+    use euclid::size2;
+
+    fn fixed_item(width: f32, height: f32) -> InlineLayoutItem {
+        InlineLayoutItem::Block(EqualRc::new(LayoutTreeNode {
+            size: size2(width, height),
+            margin: LogicalSideOffsets::default(),
+            render: RenderData::Node(Node::new(View::default(), vec![]).into()),
+            children: vec![],
+            clip: None,
+            border_radius: BorderRadius::default(),
+            truncated: false,
+            lines: 0,
+        }))
+    }
+
+    #[test]
+    fn clipped_lines_keep_to_max_width() {
+        let node = Node::new(Span::default(), vec![]);
+        let items = [fixed_item(100.0, 10.0)];
+        let layout = calc_inline_layout(
+            node.clone().into(),
+            60.0,
+            None,
+            None,
+            LineAlign::default(),
+            true,
+            &items,
+        );
+        assert_eq!(layout.size.width, 60.0);
+        let clip = layout.clip.as_ref().unwrap();
+        assert_eq!(clip.rect.size, size2(60.0, 10.0));
+
+        let layout = calc_inline_layout(
+            node.into(),
+            60.0,
+            None,
+            None,
+            LineAlign::default(),
+            false,
+            &items,
+        );
+        assert_eq!(layout.size.width, 100.0);
+        assert!(layout.clip.is_none());
+    }
+
+    #[test]
+    fn unbounded_width_keeps_one_line() {
+        let node = Node::new(Span::default(), vec![]);
+        let items = [fixed_item(60.0, 10.0), fixed_item(80.0, 12.0)];
+        let align = LineAlign::new(&ComputedValues {
+            text_align: TextAlign::Center,
+            ..Default::default()
+        });
+        let layout = calc_inline_layout(node.into(), UNBOUNDED, None, None, align, true, &items);
+        assert_eq!(layout.lines, 1);
+        assert_eq!(layout.size, size2(140.0, 12.0));
+        assert!(layout.clip.is_none());
+    }
+
+    #[test]
+    fn infinite_width_never_wraps() {
+        // Together these are wider than the large width that used to stand
+        // in for an unbounded line, which would have wrapped them.
+        let items = vec![
+            fixed_item(6e7, 10.0),
+            fixed_item(6e7, 10.0),
+            fixed_item(6e7, 10.0),
+        ];
+        let node = Node::new(Span::default(), vec![]);
+        let layout = calc_inline_layout(
+            node.into(),
+            std::f32::INFINITY,
+            None,
+            None,
+            LineAlign::default(),
+            false,
+            &items,
+        );
+        assert_eq!(layout.lines, 1);
+        assert_eq!(layout.children.len(), 3);
+        assert!(layout.children.iter().all(|child| child.position.y == 0.0));
+        assert_eq!(layout.size.height, 10.0);
+    }
+
+    #[test]
+    fn max_lines_truncates() {
+        let items = vec![
+            fixed_item(60.0, 10.0),
+            fixed_item(60.0, 10.0),
+            fixed_item(60.0, 10.0),
+        ];
+        let node = Node::new(Span::default(), vec![]);
+        let layout = calc_inline_layout(
+            node.into(),
+            100.0,
+            Some(2),
+            None,
+            LineAlign::default(),
+            false,
+            &items,
+        );
+        assert!(layout.truncated);
+        assert_eq!(layout.children.len(), 2);
+        assert_eq!(layout.size.height, 20.0);
+        assert_eq!(layout.lines, 2);
+    }
+
+    #[test]
+    fn counts_wrapped_lines() {
+        let node = Node::new(Span::default(), vec![]);
+        let items = vec![
+            fixed_item(60.0, 10.0),
+            fixed_item(30.0, 10.0),
+            fixed_item(60.0, 10.0),
+            fixed_item(60.0, 10.0),
+        ];
+        let layout = calc_inline_layout(
+            node.clone().into(),
+            100.0,
+            None,
+            None,
+            LineAlign::default(),
+            false,
+            &items,
+        );
+        assert_eq!(layout.lines, 3);
+
+        let layout = calc_inline_layout(
+            node.into(),
+            100.0,
+            None,
+            None,
+            LineAlign::default(),
+            false,
+            &[fixed_item(10.0, 10.0)],
+        );
+        assert_eq!(layout.lines, 1);
+    }
+
+    #[test]
+    fn overflowing_box_is_not_truncated() {
+        let node = Node::new(Span::default(), vec![]);
+        let layout = calc_inline_layout(
+            node.into(),
+            50.0,
+            None,
+            None,
+            LineAlign::default(),
+            false,
+            &[fixed_item(80.0, 10.0)],
+        );
+        assert_eq!(layout.size.width, 80.0);
+        assert!(!layout.truncated);
+    }
+
+    #[test]
+    fn fitting_content_is_not_truncated() {
+        let items = vec![fixed_item(60.0, 10.0), fixed_item(60.0, 10.0)];
+        let node = Node::new(Span::default(), vec![]);
+        let layout = calc_inline_layout(
+            node.into(),
+            100.0,
+            Some(2),
+            None,
+            LineAlign::default(),
+            false,
+            &items,
+        );
+        assert!(!layout.truncated);
+        assert_eq!(layout.children.len(), 2);
+    }
+
+    #[test]
+    fn baselines_snap_to_grid() {
+        // Two lines each, standing in for text of two different sizes,
+        // with the baseline at the bottom of every item.
+        for &height in &[10.0, 18.0] {
+            let items = vec![fixed_item(60.0, height), fixed_item(60.0, height)];
+            let node = Node::new(Span::default(), vec![]);
+            let layout = calc_inline_layout(
+                node.into(),
+                100.0,
+                None,
+                Some(24.0),
+                LineAlign::default(),
+                false,
+                &items,
+            );
+            let baselines: Vec<f32> = layout
+                .children
+                .iter()
+                .map(|child| child.position.y + child.layout.size.height)
+                .collect();
+            assert_eq!(baselines, vec![24.0, 48.0]);
+        }
+    }
+
+    #[test]
+    fn justified_text_ends_on_left_aligned_line() {
+        let align = LineAlign::new(&ComputedValues {
+            text_align: TextAlign::Justify,
+            ..Default::default()
+        });
+        assert_eq!(align.lines, TextAlign::Justify);
+        assert_eq!(align.last, TextAlign::Left);
+        assert_eq!(line_alignment(align.lines, 20.0, 4), (0.0, 5.0));
+        assert_eq!(line_alignment(align.last, 20.0, 4), (0.0, 0.0));
+
+        let align = LineAlign::new(&ComputedValues {
+            text_align: TextAlign::Justify,
+            text_align_last: Some(TextAlign::Justify),
+            ..Default::default()
+        });
+        assert_eq!(align.last, TextAlign::Justify);
+    }
+
+    #[test]
+    fn last_line_is_aligned_separately() {
+        let items = vec![fixed_item(60.0, 10.0), fixed_item(60.0, 10.0)];
+        let node = Node::new(Span::default(), vec![]);
+        let align = LineAlign {
+            lines: TextAlign::Right,
+            last: TextAlign::Center,
         };
-        calc_inline_layout(node.clone(), *max_width, &[item])
-    })
+        let layout = calc_inline_layout(node.into(), 100.0, None, None, align, false, &items);
+        assert_eq!(layout.size.width, 100.0);
+        assert_eq!(layout.children[0].position.x, 40.0);
+        assert_eq!(layout.children[1].position.x, 20.0);
+    }
 }
@@ -1,44 +1,141 @@
 //! This module handles creating the layout tree, which includes
 //! arranging elements and performing text layout.
 
-use crate::dom::node::AnyNode;
-use crate::dom::{Node, Window};
-use crate::style::DisplayType;
+use crate::dom::element::DynamicNode;
+use crate::dom::node::{AnyNode, NodeRef};
+use crate::dom::{LayoutEvent, Node, Window};
+use crate::style::{BorderRadius, ComputedValues, DisplayType, Overflow};
 use crate::util::equal_rc::EqualRc;
-use euclid::{Length, Point2D, SideOffsets2D, Size2D};
+pub use crate::util::keyed_cache::CacheStats;
+use crate::util::keyed_cache::KeyedCache;
+use crate::Color;
+use euclid::{
+    point2, size2, vec2, Length, Point2D, Rect, SideOffsets2D, Size2D, Transform2D, Vector2D,
+};
 use font_kit::family_name::FamilyName;
+use font_kit::font::Font;
 use font_kit::properties::Properties;
 use font_kit::source::SystemSource;
 use moxie::embed::Runtime;
-use moxie::*;
+use pixel_grid::PixelSnapping;
+use shaped::{ParagraphCache, PARAGRAPH_CACHE_CAPACITY};
 use skribo::{FontCollection, FontFamily, FontRef};
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::Path;
+use std::rc::Rc;
+pub use text::TextLayoutCache;
+use text::{MinTextSize, TextLayoutInfo, TextOptions};
 
+mod attributed;
 mod block;
+mod custom;
+mod debug;
 mod inline;
+pub mod pixel_grid;
+mod scrollbar;
+mod shaped;
+mod stats;
+#[cfg(test)]
+pub(crate) mod test_font;
 mod text;
 
+pub use attributed::{AttributedText, RunStyle, TextDecoration};
+pub use custom::{CustomLayout, CustomLayoutRef};
+pub use debug::DebugShape;
+pub use scrollbar::{Scrollbar, Scrollbars};
+pub use stats::LayoutStats;
+
 pub struct LogicalPixel;
 pub type LogicalPoint = Point2D<f32, LogicalPixel>;
 pub type LogicalSize = Size2D<f32, LogicalPixel>;
 pub type LogicalLength = Length<f32, LogicalPixel>;
 pub type LogicalSideOffsets = SideOffsets2D<f32, LogicalPixel>;
+pub type LogicalRect = Rect<f32, LogicalPixel>;
+pub type LogicalVector = Vector2D<f32, LogicalPixel>;
+pub type LogicalTransform = Transform2D<f32, LogicalPixel, LogicalPixel>;
+
+/// Returns `value` if it is finite, otherwise `fallback`. Non-finite
+/// lengths come from bad input further up (e.g. a broken style value),
+/// and are replaced rather than passed on to the renderer.
+fn finite_or(value: f32, fallback: f32) -> f32 {
+    if value.is_finite() {
+        value
+    } else {
+        fallback
+    }
+}
+
+/// A constraint without a limit, for laying content out at its natural
+/// size along an axis, like the height of a scroll container's content.
+/// Either length of the size given to `layout_fragment` may be one.
+pub const UNBOUNDED: f32 = std::f32::INFINITY;
+
+/// The limit a constraint sets, or `None` when it's unbounded. Unknown
+/// (NaN) constraints behave the same as no constraint.
+fn bounded(length: f32) -> Option<f32> {
+    Some(length).filter(|length| length.is_finite())
+}
+
+/// Takes `inset` off of a constraint. An unbounded constraint stays
+/// unbounded, while a bounded one stops at zero.
+fn shrink_constraint(length: f32, inset: f32) -> f32 {
+    bounded(length).map_or(UNBOUNDED, |length| (length - inset).max(0.0))
+}
+
+/// Makes sure both dimensions of a computed size are finite, replacing
+/// bad values with zero.
+fn finite_size(size: LogicalSize) -> LogicalSize {
+    LogicalSize::new(finite_or(size.width, 0.0), finite_or(size.height, 0.0))
+}
 
 /// Each edge of the layout tree contains information on the positions
 /// of the child elements, since elements are positioned relative to
 /// their parents, and the position is assigned by the parent.
+#[derive(Clone)]
 pub struct LayoutChild {
     pub position: LogicalPoint,
     pub layout: EqualRc<LayoutTreeNode>,
 }
 
+/// Width of the box substituted for a missing glyph by
+/// `MissingGlyphs::Placeholder`, as a fraction of the text size.
+pub const MISSING_GLYPH_ADVANCE: f32 = 0.5;
+
 pub struct Glyph {
     pub index: u32,
     pub offset: LogicalPoint,
+    /// Set when the glyph is missing from every font and was replaced
+    /// by a placeholder, which the renderer draws instead of the glyph.
+    pub missing: bool,
+}
+
+/// Styles the renderer has to fake for a run of text, because its font
+/// has no face with the requested weight or slant.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Synthesis {
+    pub bold: bool,
+    pub italic: bool,
+}
+
+/// Measurements of the font an element's text is set in, scaled to its
+/// text size, see `LayoutEngine::font_metrics`. Distances are from the
+/// baseline, with the descent below it and so negative.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FontMetrics {
+    pub ascent: f32,
+    pub descent: f32,
+    pub line_gap: f32,
+    pub cap_height: f32,
+    pub x_height: f32,
 }
 
 pub struct TextFragment {
     pub font: FontRef,
     pub glyphs: Vec<Glyph>,
+    pub synthesis: Synthesis,
 }
 
 /// Information passed to the renderer for rendering text.
@@ -46,6 +143,123 @@ pub struct LayoutText {
     pub fragments: Vec<TextFragment>,
     /// The text size of the text.
     pub size: f32,
+    /// Index of the source text among its parent's children, or 0 when
+    /// the source is the `AttributedText` given to `layout_attributed`.
+    pub index: usize,
+    /// Byte range of the source text covered by this line. Lines of an
+    /// `AttributedText` count from the start of the whole text, not of
+    /// the run they're part of.
+    pub range: Range<usize>,
+    /// Distance from the top of the line to its baseline.
+    pub baseline: f32,
+    /// The style of the run of an `AttributedText` this line is part of,
+    /// over that of `parent`. Plain text has no changes.
+    pub style: RunStyle,
+    /// Where the line's spaces and tabs are, when its text has
+    /// `show_whitespace` on. Lines whose glyphs were moved apart by
+    /// justification or cut by an ellipsis have none.
+    pub whitespace: Vec<WhitespaceMarker>,
+    /// The parts of the line under an `AttributedText` decoration.
+    pub decorations: Vec<DecorationSegment>,
+    info: EqualRc<TextLayoutInfo>,
+    /// Where the text `info` was shaped from starts in the source text,
+    /// which is past the start for every run of an `AttributedText` but
+    /// the first.
+    offset: usize,
+}
+
+/// A space or tab in a line of text, for the renderer to mark.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WhitespaceMarker {
+    pub kind: WhitespaceKind,
+    /// Where the character starts, relative to the start of the line.
+    pub x: f32,
+    pub width: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WhitespaceKind {
+    Space,
+    Tab,
+}
+
+/// A decoration along part of a line of text, for the renderer to draw.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecorationSegment {
+    pub kind: TextDecoration,
+    /// Byte range of the source text under the decoration.
+    pub range: Range<usize>,
+    /// Where the decorated glyphs start, relative to the start of the
+    /// line.
+    pub x: f32,
+    pub width: f32,
+}
+
+impl DecorationSegment {
+    /// The rect to fill for the decoration in a line of text of the
+    /// given size, relative to the line: an underline just below the
+    /// baseline, or a line through the middle of the lowercase letters.
+    pub fn line(&self, size: f32, baseline: f32) -> LogicalRect {
+        let thickness = (size / 14.0).max(1.0);
+        let y = match self.kind {
+            TextDecoration::Underline => baseline + size * 0.1,
+            TextDecoration::LineThrough => baseline - size * 0.3 - thickness / 2.0,
+        };
+        LogicalRect::new(point2(self.x, y), size2(self.width, thickness))
+    }
+}
+
+impl WhitespaceMarker {
+    /// The rect to fill to mark the character in a line of text of the
+    /// given size, relative to the line: a dot for a space and a dash
+    /// across a tab, about halfway up the lowercase letters.
+    pub fn mark(&self, size: f32, baseline: f32) -> LogicalRect {
+        let thickness = (size * 0.1).max(1.0);
+        let (x, width) = match self.kind {
+            WhitespaceKind::Space => (self.x + (self.width - thickness) / 2.0, thickness),
+            WhitespaceKind::Tab => (self.x + self.width * 0.1, self.width * 0.8),
+        };
+        let y = baseline - size * 0.25 - thickness / 2.0;
+        LogicalRect::new(point2(x, y), size2(width, thickness))
+    }
+}
+
+impl LayoutText {
+    /// Returns the x position of a byte offset of the source text,
+    /// relative to the start of this line. The offset is clamped to the
+    /// range covered by the line.
+    pub fn offset_to_x(&self, offset: usize) -> f32 {
+        let offset = offset.max(self.range.start).min(self.range.end);
+        self.info
+            .measure_width(self.range.start - self.offset, offset - self.offset)
+    }
+
+    /// Returns the byte offset of the source text covered by this line
+    /// that is closest to `x`, relative to the start of the line, e.g.
+    /// to place a caret where the line was clicked.
+    pub fn x_to_offset(&self, x: f32) -> usize {
+        self.run_offset_at_x(self.range.clone(), x)
+    }
+
+    /// `TextLayoutInfo::offset_at_x` for a range of the source text.
+    fn run_offset_at_x(&self, range: Range<usize>, x: f32) -> usize {
+        let run_range = range.start - self.offset..range.end - self.offset;
+        self.info.offset_at_x(run_range, x) + self.offset
+    }
+
+    /// The range of the line in the text `info` was shaped from.
+    fn run_range(&self) -> Range<usize> {
+        self.range.start - self.offset..self.range.end - self.offset
+    }
+}
+
+/// The region a node's children are clipped to, relative to the node.
+#[derive(Clone)]
+pub struct LayoutClip {
+    pub rect: LogicalRect,
+    /// Corner radii of the clip, so that children are cut along the
+    /// curve of a rounded border.
+    pub radius: BorderRadius,
 }
 
 pub enum RenderData {
@@ -60,56 +274,3076 @@ pub struct LayoutTreeNode {
     pub margin: LogicalSideOffsets,
     pub render: RenderData,
     pub children: Vec<LayoutChild>,
+    /// Present when children should be clipped, e.g. by `overflow: hidden`.
+    pub clip: Option<LayoutClip>,
+    /// Radii of the corners of the border box, scaled down to fit it.
+    /// Lines of text have square corners.
+    pub border_radius: BorderRadius,
+    /// Set when some of the text in this node was left out, either
+    /// lines dropped by `max_lines` or the end of a line cut off for its
+    /// `ellipsis`. Text that overflows its line and is only clipped
+    /// isn't truncated.
+    pub truncated: bool,
+    /// How many lines the inline content of this node was broken into,
+    /// not counting those dropped by `max_lines`.
+    pub lines: u32,
+}
+
+/// The band of a node's box that a point falls in, from the outside in,
+/// see `LayoutTreeNode::hit_test_region`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Region {
+    Border,
+    Padding,
+    Content,
+}
+
+/// Which way `LayoutTreeNode::move_caret` moves a caret.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaretDirection {
+    Up,
+    Down,
+}
+
+/// How `LayoutTreeNode::diff` matches up nodes between two trees: by
+/// the key of their element, or failing that by the child indices that
+/// lead to them from the root.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum NodeIdentity {
+    Key(String),
+    Index(Vec<usize>),
+}
+
+/// A node of a layout tree placed in the coordinates of the root, see
+/// `LayoutTreeNode::flatten`.
+pub struct FlatNode<'a> {
+    pub rect: LogicalRect,
+    /// The area the node's ancestors clip it to, if any.
+    pub clip: Option<LogicalRect>,
+    /// Where the node comes in paint order, later nodes being painted on
+    /// top of earlier ones.
+    pub order: usize,
+    pub layout: &'a LayoutTreeNode,
+}
+
+/// The size and shape of a layout tree, see `LayoutTreeNode::stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TreeStats {
+    /// Every node of the tree, lines of text included.
+    pub nodes: usize,
+    /// How many nodes the longest path from the root down goes through,
+    /// so a tree of only a root is one deep.
+    pub depth: usize,
+    /// Pieces of text, which is one per line of a text, or more when the
+    /// line is split up between runs of `AttributedText`.
+    pub text_runs: usize,
+}
+
+impl LayoutTreeNode {
+    /// Counts the nodes in this tree, for keeping an eye on its size,
+    /// e.g. to notice a paragraph that broke into thousands of lines.
+    pub fn stats(&self) -> TreeStats {
+        let mut stats = TreeStats {
+            nodes: 1,
+            depth: 1,
+            text_runs: match self.render {
+                RenderData::Text { .. } => 1,
+                RenderData::Node(_) => 0,
+            },
+        };
+        for child in &self.children {
+            let child = child.layout.stats();
+            stats.nodes += child.nodes;
+            stats.depth = stats.depth.max(child.depth + 1);
+            stats.text_runs += child.text_runs;
+        }
+        stats
+    }
+
+    /// Lists this node and every node below it in paint order, with
+    /// rects relative to this node, for consumers that would rather not
+    /// walk the tree.
+    pub fn flatten(&self) -> Vec<FlatNode> {
+        let mut nodes = vec![];
+        flatten_into(self, LogicalPoint::zero(), None, &mut nodes);
+        nodes
+    }
+
+    /// The extents of the glyphs in a line of text, relative to the
+    /// node, as opposed to its size, which comes from the font's line
+    /// metrics. Other nodes, and lines without visible glyphs, have
+    /// none.
+    pub fn ink_bounds(&self) -> Option<LogicalRect> {
+        match self.render {
+            RenderData::Text { text: ref line, .. } => text::ink_bounds(&line.fragments, line.size),
+            RenderData::Node(_) => None,
+        }
+    }
+
+    /// The color to clear the window to before drawing the layout whose
+    /// root this is, which is the `background_color` of the window
+    /// element, or white when it has none. Drawing it even before the
+    /// layout is ready keeps a resized window from flashing.
+    pub fn clear_color(&self) -> Color {
+        match self.render {
+            RenderData::Node(ref node) => node
+                .computed_values()
+                .get()
+                .map(|values| values.background_color)
+                .filter(|color| color.alpha > 0)
+                .unwrap_or_else(Color::white),
+            RenderData::Text { .. } => Color::white(),
+        }
+    }
+
+    /// The key of the element laid out by this node, if it has one.
+    pub fn key(&self) -> Option<&str> {
+        match self.render {
+            RenderData::Node(ref node) => node.key(),
+            RenderData::Text { .. } => None,
+        }
+    }
+
+    /// Matches the nodes of this tree up with those of a `new` layout
+    /// and returns where each node that moved or changed size was and
+    /// is now, in root coordinates, for animating between the two.
+    /// Nodes in only one of the trees are left out, and of the nodes
+    /// sharing a key only the first is matched.
+    pub fn diff(&self, new: &LayoutTreeNode) -> Vec<(NodeIdentity, LogicalRect, LogicalRect)> {
+        let mut old_rects = vec![];
+        collect_identities(self, LogicalPoint::zero(), &mut vec![], &mut old_rects);
+        let mut old_by_identity = HashMap::new();
+        for (identity, rect) in old_rects {
+            old_by_identity.entry(identity).or_insert(rect);
+        }
+        let mut new_rects = vec![];
+        collect_identities(new, LogicalPoint::zero(), &mut vec![], &mut new_rects);
+        new_rects
+            .into_iter()
+            .filter_map(|(identity, new_rect)| {
+                let old_rect = old_by_identity.remove(&identity)?;
+                if old_rect == new_rect {
+                    None
+                } else {
+                    Some((identity, old_rect, new_rect))
+                }
+            })
+            .collect()
+    }
+
+    /// Distance from the top of the node to the baseline of its first
+    /// line of text, or `None` if there is no text in it.
+    pub fn first_baseline(&self) -> Option<f32> {
+        match self.render {
+            RenderData::Text { ref text, .. } => Some(text.baseline),
+            RenderData::Node(_) => self.children.iter().find_map(|child| {
+                let baseline = child.layout.first_baseline()?;
+                Some(child.position.y + baseline)
+            }),
+        }
+    }
+
+    /// Distance from the top of the node to the baseline of its last
+    /// line of text, or `None` if there is no text in it.
+    pub fn last_baseline(&self) -> Option<f32> {
+        match self.render {
+            RenderData::Text { ref text, .. } => Some(text.baseline),
+            RenderData::Node(_) => self.children.iter().rev().find_map(|child| {
+                let baseline = child.layout.last_baseline()?;
+                Some(child.position.y + baseline)
+            }),
+        }
+    }
+
+    /// Size of everything inside of this node, including the padding
+    /// and border after the furthest child. Unlike `size`, this isn't
+    /// limited by a fixed width or height.
+    pub fn content_size(&self) -> LogicalSize {
+        let mut far_edges = LogicalSideOffsets::zero();
+        if let RenderData::Node(ref node) = self.render {
+            if let Some(values) = node.computed_values().get() {
+                if let DisplayType::Block(block) = values.display {
+                    far_edges = block.padding + values.border_thickness;
+                }
+            }
+        }
+        let mut content = LogicalSize::zero();
+        for child in &self.children {
+            let layout = &child.layout;
+            content.width = content
+                .width
+                .max(child.position.x + layout.size.width + layout.margin.right);
+            content.height = content
+                .height
+                .max(child.position.y + layout.size.height + layout.margin.bottom);
+        }
+        size2(
+            content.width + far_edges.right,
+            content.height + far_edges.bottom,
+        )
+    }
+
+    /// How far the content of this node reaches past each edge of its
+    /// box, e.g. for dev tools to show why content is cut off. Content
+    /// sticks out at the right and bottom when it's larger than the box,
+    /// as measured by `content_size`, and at the left and top when a
+    /// child is placed before the start of the box.
+    pub fn overflow(&self) -> LogicalSideOffsets {
+        let content = self.content_size();
+        let mut overflow = LogicalSideOffsets::new(
+            0.0,
+            (content.width - self.size.width).max(0.0),
+            (content.height - self.size.height).max(0.0),
+            0.0,
+        );
+        for child in &self.children {
+            let margin = child.layout.margin;
+            // Only a positive margin keeps its child away from the edge.
+            overflow.left = overflow.left.max(margin.left.max(0.0) - child.position.x);
+            overflow.top = overflow.top.max(margin.top.max(0.0) - child.position.y);
+        }
+        overflow
+    }
+
+    /// How far the content of this node can be scrolled on each axis,
+    /// which is how much larger the content is than the node.
+    pub fn scrollable_extent(&self) -> LogicalSize {
+        let content = self.content_size();
+        size2(
+            (content.width - self.size.width).max(0.0),
+            (content.height - self.size.height).max(0.0),
+        )
+    }
+
+    /// The scrollbars of a node along each axis with `overflow: scroll`,
+    /// `thickness` wide, for content scrolled by `offset`. The engine
+    /// doesn't scroll or draw them, it only works out where they go.
+    pub fn scrollbars(&self, offset: LogicalVector, thickness: f32) -> Scrollbars {
+        let (block, border) = match self.render {
+            RenderData::Node(ref node) => match node.computed_values().get() {
+                Some(ComputedValues {
+                    display: DisplayType::Block(block),
+                    border_thickness,
+                    ..
+                }) => (block, border_thickness),
+                _ => return Scrollbars::default(),
+            },
+            RenderData::Text { .. } => return Scrollbars::default(),
+        };
+        scrollbar::scrollbars(
+            self.size,
+            self.content_size(),
+            border,
+            offset,
+            thickness,
+            (
+                block.overflow_x == Overflow::Scroll,
+                block.overflow_y == Overflow::Scroll,
+            ),
+        )
+    }
+
+    /// The area that takes pointer input for this node when it's placed
+    /// at `position`, which is its box grown to the `min_touch_target`
+    /// of its element, if it has one.
+    pub fn hit_area(&self, position: LogicalPoint) -> LogicalRect {
+        let rect = LogicalRect::new(position, self.size);
+        let min_size = match self.render {
+            RenderData::Node(ref node) => node
+                .computed_values()
+                .get()
+                .and_then(|values| values.min_touch_target),
+            RenderData::Text { .. } => None,
+        };
+        match min_size {
+            Some(min_size) => {
+                let grow = size2(
+                    (min_size.width - rect.size.width).max(0.0),
+                    (min_size.height - rect.size.height).max(0.0),
+                );
+                rect.inflate(grow.width / 2.0, grow.height / 2.0)
+            }
+            None => rect,
+        }
+    }
+
+    /// The nodes an event at `point` goes to when this node is placed at
+    /// `position`, in the order they get to handle it: children before
+    /// their parents, and earlier children first. The point is taken
+    /// into transformed nodes like `hit_test_region` does, so a rotated
+    /// node gets the events over where it's drawn. Events without a
+    /// point go to every node.
+    pub fn event_targets(
+        &self,
+        position: LogicalPoint,
+        point: Option<LogicalPoint>,
+    ) -> Vec<AnyNode> {
+        let mut targets = vec![];
+        self.collect_event_targets(position, point, &mut targets);
+        targets
+    }
+
+    fn collect_event_targets(
+        &self,
+        position: LogicalPoint,
+        point: Option<LogicalPoint>,
+        targets: &mut Vec<AnyNode>,
+    ) {
+        let node = match self.render {
+            RenderData::Node(ref node) => node,
+            RenderData::Text { .. } => return,
+        };
+        let point = match (point, self.transform_at(position)) {
+            (Some(point), Some(transform)) => match transform.inverse() {
+                Some(inverse) => Some(inverse.transform_point(point)),
+                // Nothing is left to hit of a node squashed flat.
+                None => return,
+            },
+            (point, _) => point,
+        };
+        for child in &self.children {
+            child.layout.collect_event_targets(
+                position + child.position.to_vector(),
+                point,
+                targets,
+            );
+        }
+        if point.map_or(true, |point| self.hit_area(position).contains(point)) {
+            targets.push(node.clone());
+        }
+    }
+
+    /// The transform of this node's element when the node is placed at
+    /// `position`, turning it around its `transform_origin`. `None` when
+    /// the element isn't transformed.
+    pub fn transform_at(&self, position: LogicalPoint) -> Option<LogicalTransform> {
+        let values = match self.render {
+            RenderData::Node(ref node) => node.computed_values().get()?,
+            RenderData::Text { .. } => return None,
+        };
+        if values.transform == LogicalTransform::identity() {
+            return None;
+        }
+        let origin = position.to_vector() + values.transform_origin.resolve(self.size);
+        Some(
+            LogicalTransform::create_translation(-origin.x, -origin.y)
+                .post_transform(&values.transform)
+                .post_translate(origin),
+        )
+    }
+
+    /// The axis-aligned rect covering everything drawn for this node and
+    /// the nodes below it when it's placed at `position`, transforms
+    /// included, e.g. to find the area to redraw when it changes.
+    pub fn transformed_bounds(&self, position: LogicalPoint) -> LogicalRect {
+        let mut bounds = LogicalRect::new(position, self.size);
+        let clip = self
+            .clip
+            .as_ref()
+            .map(|clip| clip.rect.translate(position.to_vector()));
+        for child in &self.children {
+            let child_bounds = child
+                .layout
+                .transformed_bounds(position + child.position.to_vector());
+            let child_bounds = match clip {
+                Some(clip) => match clip.intersection(&child_bounds) {
+                    Some(visible) => visible,
+                    None => continue,
+                },
+                None => child_bounds,
+            };
+            bounds = bounds.union(&child_bounds);
+        }
+        match self.transform_at(position) {
+            Some(transform) => transform.transform_rect(&bounds),
+            None => bounds,
+        }
+    }
+
+    /// Finds the topmost DOM node under `point`, given relative to this
+    /// node, along with whether the point is on its border, in its
+    /// padding, or in its content box, e.g. to grab a resize handle on
+    /// the border. Parts of nodes clipped away by their ancestors can't
+    /// be hit, and text counts as the content of its parent. Around a
+    /// box smaller than its `min_touch_target`, the rest of the target
+    /// counts as border. Transformed nodes are hit where they're drawn.
+    pub fn hit_test_region(&self, point: LogicalPoint) -> Option<(AnyNode, Region)> {
+        hit_test_in(self, LogicalPoint::zero(), point)
+    }
+
+    /// Finds where `node` was placed, relative to this node, or `None`
+    /// if it isn't part of this tree.
+    pub fn node_rect(&self, node: &AnyNode) -> Option<LogicalRect> {
+        if let RenderData::Node(ref dom_node) = self.render {
+            if dom_node == node {
+                return Some(LogicalRect::new(LogicalPoint::zero(), self.size));
+            }
+        }
+        self.children.iter().find_map(|child| {
+            child
+                .layout
+                .node_rect(node)
+                .map(|rect| rect.translate(child.position.to_vector()))
+        })
+    }
+
+    /// Finds the rectangles covering a byte range of one of `parent`'s
+    /// text children, relative to this node. Text that wraps produces
+    /// one rectangle per line that the range touches. An empty range
+    /// produces a single zero width rectangle for a caret, on the line
+    /// that starts at the offset when it falls between two lines. A caret
+    /// at the end of text ending in a line break goes at the start of the
+    /// empty line below the text, where typing would continue.
+    pub fn text_range_rects(
+        &self,
+        parent: &AnyNode,
+        index: usize,
+        range: Range<usize>,
+    ) -> Vec<LogicalRect> {
+        let mut rects = vec![];
+        self.collect_text_range_rects(LogicalPoint::zero(), parent, index, &range, &mut rects);
+        if range.start == range.end {
+            rects.drain(..rects.len().saturating_sub(1));
+        }
+        rects
+    }
+
+    /// Moves a caret in one of `parent`'s text children to the line above
+    /// or below it, for the arrow keys of a text field. `caret` is where
+    /// the caret is now, as found by `text_range_rects`, and `goal_x` is
+    /// the x position to keep to, both relative to this node. Keeping
+    /// the x the caret had before its first move up or down, rather than
+    /// the x of `caret`, lets it come back to the same column after
+    /// passing through a shorter line. Returns the byte offset on the
+    /// other line closest to `goal_x`, or `None` when the caret is
+    /// already on the first or last line.
+    pub fn move_caret(
+        &self,
+        parent: &AnyNode,
+        index: usize,
+        caret: LogicalRect,
+        goal_x: f32,
+        direction: CaretDirection,
+    ) -> Option<usize> {
+        let mut lines = vec![];
+        self.collect_text_lines(LogicalPoint::zero(), parent, index, &mut lines);
+        let center = |line: &&(LogicalRect, &LayoutText)| line.0.center().y;
+        let (rect, text) = match direction {
+            CaretDirection::Up => lines
+                .iter()
+                .filter(|line| center(line) < caret.min_y())
+                .max_by(|a, b| {
+                    a.0.min_y()
+                        .partial_cmp(&b.0.min_y())
+                        .unwrap_or(Ordering::Equal)
+                })?,
+            CaretDirection::Down => lines
+                .iter()
+                .filter(|line| center(line) > caret.max_y())
+                .min_by(|a, b| {
+                    a.0.min_y()
+                        .partial_cmp(&b.0.min_y())
+                        .unwrap_or(Ordering::Equal)
+                })?,
+        };
+
+        // The end of a line that another one starts at, or that is a line
+        // break, puts the caret on the next line, so it's left out.
+        let source = text_child(parent, index)?;
+        let mut range = text.range.clone();
+        let continues = lines
+            .iter()
+            .any(|(_, other)| other.range.start == range.end && other.range.end > range.end);
+        if range.start < range.end && (continues || source[..range.end].ends_with('\n')) {
+            let last = source[..range.end]
+                .chars()
+                .next_back()
+                .map_or(0, char::len_utf8);
+            range.end -= last;
+        }
+        Some(text.run_offset_at_x(range, goal_x - rect.min_x()))
+    }
+
+    /// Finds the lines of one of `parent`'s text children, with their
+    /// rects relative to this node.
+    fn collect_text_lines<'a>(
+        &'a self,
+        position: LogicalPoint,
+        parent: &AnyNode,
+        index: usize,
+        lines: &mut Vec<(LogicalRect, &'a LayoutText)>,
+    ) {
+        if let RenderData::Text {
+            ref text,
+            parent: ref text_parent,
+        } = self.render
+        {
+            if text_parent == parent && text.index == index {
+                lines.push((LogicalRect::new(position, self.size), text));
+            }
+        }
+        for child in &self.children {
+            child.layout.collect_text_lines(
+                position + child.position.to_vector(),
+                parent,
+                index,
+                lines,
+            );
+        }
+    }
+
+    fn collect_text_range_rects(
+        &self,
+        position: LogicalPoint,
+        parent: &AnyNode,
+        index: usize,
+        range: &Range<usize>,
+        rects: &mut Vec<LogicalRect>,
+    ) {
+        if let RenderData::Text {
+            ref text,
+            parent: ref text_parent,
+        } = self.render
+        {
+            let start = range.start.max(text.range.start);
+            let end = range.end.min(text.range.end);
+            let caret = range.start == range.end
+                && text.range.start <= range.start
+                && range.start <= text.range.end;
+            if text_parent == parent && text.index == index && (start < end || caret) {
+                let x = text.offset_to_x(start);
+                let width = text.offset_to_x(end) - x;
+                let after_break = caret
+                    && text_child(parent, index).map_or(false, |source| {
+                        start == source.len() && source.ends_with('\n')
+                    });
+                let origin = if after_break {
+                    position + vec2(0.0, self.size.height)
+                } else {
+                    position + vec2(x, 0.0)
+                };
+                rects.push(LogicalRect::new(origin, size2(width, self.size.height)));
+            }
+        }
+        for child in &self.children {
+            child.layout.collect_text_range_rects(
+                position + child.position.to_vector(),
+                parent,
+                index,
+                range,
+                rects,
+            );
+        }
+    }
+}
+
+fn flatten_into<'a>(
+    node: &'a LayoutTreeNode,
+    position: LogicalPoint,
+    clip: Option<LogicalRect>,
+    nodes: &mut Vec<FlatNode<'a>>,
+) {
+    nodes.push(FlatNode {
+        rect: LogicalRect::new(position, node.size),
+        clip,
+        order: nodes.len(),
+        layout: node,
+    });
+    let clip = match node.clip {
+        Some(ref own) => {
+            let own = own.rect.translate(position.to_vector());
+            Some(clip.map_or(own, |clip| {
+                clip.intersection(&own).unwrap_or_else(LogicalRect::zero)
+            }))
+        }
+        None => clip,
+    };
+    for child in &node.children {
+        flatten_into(
+            &child.layout,
+            position + child.position.to_vector(),
+            clip,
+            nodes,
+        );
+    }
+}
+
+/// Hit tests `node` placed at `position`, bringing `point` into the
+/// untransformed space of each node on the way down, and trying the
+/// nodes painted last first.
+fn hit_test_in(
+    node: &LayoutTreeNode,
+    position: LogicalPoint,
+    point: LogicalPoint,
+) -> Option<(AnyNode, Region)> {
+    let point = match node.transform_at(position) {
+        // A transform that squashes the node flat leaves nothing to hit.
+        Some(transform) => transform.inverse()?.transform_point(point),
+        None => point,
+    };
+    let clipped = node.clip.as_ref().map_or(false, |clip| {
+        !clip.rect.translate(position.to_vector()).contains(point)
+    });
+    if !clipped {
+        let hit = node.children.iter().rev().find_map(|child| {
+            hit_test_in(&child.layout, position + child.position.to_vector(), point)
+        });
+        if hit.is_some() {
+            return hit;
+        }
+    }
+
+    let dom_node = match node.render {
+        RenderData::Node(ref dom_node) => dom_node,
+        RenderData::Text { .. } => return None,
+    };
+    if !node.hit_area(position).contains(point) {
+        return None;
+    }
+    let (border, padding) = match dom_node.computed_values().get() {
+        Some(values) => match values.display {
+            DisplayType::Block(block) => (values.border_thickness, block.padding),
+            DisplayType::Inline(_) => (values.border_thickness, LogicalSideOffsets::zero()),
+        },
+        None => (LogicalSideOffsets::zero(), LogicalSideOffsets::zero()),
+    };
+    let padding_box = LogicalRect::new(position, node.size).inner_rect(border);
+    let region = if !padding_box.contains(point) {
+        Region::Border
+    } else if !padding_box.inner_rect(padding).contains(point) {
+        Region::Padding
+    } else {
+        Region::Content
+    };
+    Some((dom_node.clone(), region))
+}
+
+/// The text at `index` among the children of `parent`.
+fn text_child(parent: &AnyNode, index: usize) -> Option<Rc<str>> {
+    match parent.get_child(index)?.get_node() {
+        DynamicNode::Text(text) => Some(text.clone()),
+        DynamicNode::Node(_) => None,
+    }
+}
+
+/// The element children of a table row, one per column.
+fn row_cells(row: &AnyNode) -> Vec<AnyNode> {
+    row.children()
+        .filter_map(|child| match child.get_node() {
+            DynamicNode::Node(cell) => Some(cell.to_owned()),
+            DynamicNode::Text(_) => None,
+        })
+        .collect()
+}
+
+fn collect_identities(
+    node: &LayoutTreeNode,
+    position: LogicalPoint,
+    path: &mut Vec<usize>,
+    rects: &mut Vec<(NodeIdentity, LogicalRect)>,
+) {
+    let identity = match node.key() {
+        Some(key) => NodeIdentity::Key(key.to_owned()),
+        None => NodeIdentity::Index(path.clone()),
+    };
+    rects.push((identity, LogicalRect::new(position, node.size)));
+    for (index, child) in node.children.iter().enumerate() {
+        path.push(index);
+        collect_identities(
+            &child.layout,
+            position + child.position.to_vector(),
+            path,
+            rects,
+        );
+        path.pop();
+    }
+}
+
+fn collect_node_rects(
+    node: &LayoutTreeNode,
+    position: LogicalPoint,
+    rects: &mut Vec<(AnyNode, LogicalRect)>,
+) {
+    if let RenderData::Node(ref dom_node) = node.render {
+        rects.push((dom_node.clone(), LogicalRect::new(position, node.size)));
+    }
+    for child in &node.children {
+        collect_node_rects(&child.layout, position + child.position.to_vector(), rects);
+    }
+}
+
+/// Reasons a layout pass can't be performed, see `LayoutEngine::try_layout`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LayoutError {
+    /// The system fonts couldn't be loaded.
+    FontLoad(String),
+    /// The font context has no fonts to lay out text with.
+    NoFonts,
+    /// The window size has to be finite and not negative.
+    InvalidSize(LogicalSize),
+    /// The DOM was laid out before its style was computed.
+    MissingStyle,
+}
+
+impl std::fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LayoutError::FontLoad(err) => write!(f, "failed to load fonts: {}", err),
+            LayoutError::NoFonts => write!(f, "no fonts available for text layout"),
+            LayoutError::InvalidSize(size) => write!(f, "invalid window size {:?}", size),
+            LayoutError::MissingStyle => write!(f, "layout requires computed styles"),
+        }
+    }
 }
 
+impl std::error::Error for LayoutError {}
+
+/// Collects the boxes with `position: fixed` found during a layout
+/// pass. They are placed against the window instead of their parent,
+/// and end up as the last children of the root so they paint above
+/// everything else.
+struct FixedLayer {
+    viewport: LogicalSize,
+    children: RefCell<Vec<LayoutChild>>,
+}
+
+/// A font collection that more fonts can be added to, see
+/// `FontContext::register_font_file`.
+type SharedCollection = EqualRc<RefCell<FontCollection>>;
+
+/// The fonts used for text layout. Loading them is slow, so a single
+/// context can be shared between layout engines, e.g. one per window.
+#[derive(Clone, PartialEq)]
+pub struct FontContext {
+    collection: SharedCollection,
+    families: EqualRc<Cell<usize>>,
+    /// A collection for each `font_stack` asked for so far.
+    stacks: EqualRc<RefCell<HashMap<&'static [&'static str], SharedCollection>>>,
+    /// Just the first font of each collection, for text that has
+    /// `font_fallback` turned off. Contexts made from families don't
+    /// know which of them is the primary one, and use all of them.
+    primaries: EqualRc<RefCell<HashMap<Option<&'static [&'static str]>, SharedCollection>>>,
+    /// Font files added after the context was created, which every
+    /// collection falls back to after its own families. How many there
+    /// are tells engines whether their cached layouts are out of date.
+    registered: EqualRc<RefCell<Vec<Font>>>,
+    /// Paragraphs of long texts shaped so far, see `ShapedText`. Kept
+    /// across layouts and engines until fonts are registered.
+    paragraphs: EqualRc<ParagraphCache>,
+}
+
+impl FontContext {
+    /// Loads the system's default sans-serif font, panicking if there
+    /// is none. See `try_new`.
+    pub fn new() -> FontContext {
+        FontContext::try_new().unwrap()
+    }
+
+    /// Loads the system's default sans-serif font.
+    pub fn try_new() -> Result<FontContext, LayoutError> {
+        let font = SystemSource::new()
+            .select_best_match(&[FamilyName::SansSerif], &Properties::new())
+            .map_err(|err| LayoutError::FontLoad(format!("{:?}", err)))?
+            .load()
+            .map_err(|err| LayoutError::FontLoad(format!("{:?}", err)))?;
+
+        let context = FontContext::from_families(vec![FontFamily::new_from_font(font.clone())]);
+        let mut primary = FontCollection::new();
+        primary.add_family(FontFamily::new_from_font(font));
+        context
+            .primaries
+            .borrow_mut()
+            .insert(None, EqualRc::new(RefCell::new(primary)));
+        Ok(context)
+    }
+
+    /// Uses the given families instead of loading system fonts, in order
+    /// of preference.
+    pub fn from_families(families: Vec<FontFamily>) -> FontContext {
+        let count = families.len();
+        let mut collection = FontCollection::new();
+        for family in families {
+            collection.add_family(family);
+        }
+        let paragraphs = ParagraphCache::new();
+        paragraphs.set_capacity(Some(PARAGRAPH_CACHE_CAPACITY));
+        FontContext {
+            collection: EqualRc::new(RefCell::new(collection)),
+            families: EqualRc::new(Cell::new(count)),
+            stacks: EqualRc::new(RefCell::new(HashMap::new())),
+            primaries: EqualRc::new(RefCell::new(HashMap::new())),
+            registered: EqualRc::new(RefCell::new(vec![])),
+            paragraphs: EqualRc::new(paragraphs),
+        }
+    }
+
+    /// The fonts for text with the given `font_stack`, loading them the
+    /// first time the stack is used. Without `fallback`, only the first
+    /// font is used, so characters it lacks show up as missing glyphs.
+    fn collection_for(
+        &self,
+        stack: Option<&'static [&'static str]>,
+        fallback: bool,
+    ) -> SharedCollection {
+        if !fallback {
+            let mut primaries = self.primaries.borrow_mut();
+            match stack {
+                Some(stack) => {
+                    return primaries
+                        .entry(Some(stack))
+                        .or_insert_with(|| {
+                            let mut collection = FontCollection::new();
+                            if let Some(font) = stack_fonts(stack).next() {
+                                collection.add_family(FontFamily::new_from_font(font));
+                            }
+                            EqualRc::new(RefCell::new(collection))
+                        })
+                        .clone()
+                }
+                None => {
+                    if let Some(primary) = primaries.get(&None) {
+                        return primary.clone();
+                    }
+                }
+            }
+        }
+        match stack {
+            Some(stack) => self
+                .stacks
+                .borrow_mut()
+                .entry(stack)
+                .or_insert_with(|| {
+                    let mut collection = load_font_stack(stack);
+                    for font in self.registered.borrow().iter() {
+                        collection.add_family(FontFamily::new_from_font(font.clone()));
+                    }
+                    EqualRc::new(RefCell::new(collection))
+                })
+                .clone(),
+            None => self.collection.clone(),
+        }
+    }
+
+    /// Adds the first font in a file as a fallback for every collection,
+    /// for every engine sharing this context. Each engine lays out from
+    /// scratch the next time, so text that had no font before picks up
+    /// the new one.
+    pub fn register_font_file(&self, path: impl AsRef<Path>) -> Result<(), LayoutError> {
+        // Loading is the only step that can fail, so the font is either
+        // added everywhere or nowhere.
+        let font = Font::from_path(path.as_ref(), 0)
+            .map_err(|err| LayoutError::FontLoad(format!("{:?}", err)))?;
+        self.collection
+            .borrow_mut()
+            .add_family(FontFamily::new_from_font(font.clone()));
+        for collection in self.stacks.borrow().values() {
+            collection
+                .borrow_mut()
+                .add_family(FontFamily::new_from_font(font.clone()));
+        }
+        self.families.set(self.families.get() + 1);
+        self.paragraphs.clear();
+        self.registered.borrow_mut().push(font);
+        Ok(())
+    }
+
+    /// Limits how many paragraphs of long texts stay shaped for every
+    /// engine sharing the context, letting go of the least recently used
+    /// first. It's `PARAGRAPH_CACHE_CAPACITY` unless set; nothing else
+    /// drops paragraphs until a font is registered, so `None` lets the
+    /// cache grow with every paragraph ever laid out.
+    pub fn set_shaping_cache_capacity(&self, capacity: Option<usize>) {
+        self.paragraphs.set_capacity(capacity);
+    }
+
+    /// Hits, misses and evictions of the shaped paragraphs over the
+    /// lifetime of the context, for tuning `set_shaping_cache_capacity`.
+    pub fn shaping_cache_stats(&self) -> CacheStats {
+        self.paragraphs.stats()
+    }
+
+    /// Changes whenever a font is registered.
+    fn generation(&self) -> usize {
+        self.registered.borrow().len()
+    }
+}
+
+fn family_name(name: &str) -> FamilyName {
+    match name {
+        "serif" => FamilyName::Serif,
+        "sans-serif" => FamilyName::SansSerif,
+        "monospace" => FamilyName::Monospace,
+        "cursive" => FamilyName::Cursive,
+        "fantasy" => FamilyName::Fantasy,
+        name => FamilyName::Title(name.to_owned()),
+    }
+}
+
+/// Loads the fonts of `stack_fonts` into a collection.
+fn load_font_stack(stack: &[&str]) -> FontCollection {
+    let mut collection = FontCollection::new();
+    for font in stack_fonts(stack) {
+        collection.add_family(FontFamily::new_from_font(font));
+    }
+    collection
+}
+
+/// The installed fonts of a font stack followed by the system's
+/// sans-serif font, loaded one at a time as they're asked for.
+fn stack_fonts<'a>(stack: &'a [&'a str]) -> impl Iterator<Item = Font> + 'a {
+    let source = SystemSource::new();
+    stack
+        .iter()
+        .map(|name| family_name(name))
+        .chain(std::iter::once(FamilyName::SansSerif))
+        .filter_map(move |name| {
+            source
+                .select_best_match(&[name], &Properties::new())
+                .ok()
+                .and_then(|handle| handle.load().ok())
+        })
+}
+
+impl Default for FontContext {
+    fn default() -> Self {
+        FontContext::new()
+    }
+}
+
+/// What a cached layout belongs to. Layouts are cached by owner rather
+/// than by position in the tree, so that inserting or removing a child
+/// doesn't invalidate the layouts of its siblings.
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum LayoutKey {
+    Node(AnyNode),
+    /// A text child, by its parent and the address of its shared text.
+    Text(AnyNode, usize),
+    /// The size of a node measured under a constraint, given as the
+    /// bits of its width and height.
+    Measure(AnyNode, u32, u32),
+    /// A text child measured under a width, by its parent, the address
+    /// of its text and the bits of the width.
+    MeasureText(AnyNode, usize, u32),
+    /// The layout of a block along with the fixed boxes inside of it,
+    /// taken as is while styling leaves the subtree unchanged.
+    Subtree(AnyNode),
+    /// What a layout boundary looks like to its parent.
+    Boundary(AnyNode),
+}
+
+impl LayoutKey {
+    fn measure(node: AnyNode, max_size: LogicalSize) -> LayoutKey {
+        LayoutKey::Measure(node, max_size.width.to_bits(), max_size.height.to_bits())
+    }
+
+    fn text(parent: AnyNode, text: &Rc<str>) -> LayoutKey {
+        LayoutKey::Text(parent, text.as_ptr() as usize)
+    }
+
+    fn measure_text(parent: AnyNode, text: &Rc<str>, max_width: f32) -> LayoutKey {
+        LayoutKey::MeasureText(parent, text.as_ptr() as usize, max_width.to_bits())
+    }
+}
+
+type LayoutCache = KeyedCache<LayoutKey>;
+
 /// Used to build the layout tree, with internal caching for
 /// performance.
 pub struct LayoutEngine {
     runtime: Runtime<fn() -> EqualRc<LayoutTreeNode>>,
+    /// Runs `layout_fragment`, which has its own root node.
+    fragments: Runtime<fn() -> EqualRc<LayoutTreeNode>>,
+    fonts: FontContext,
+    cache: Rc<LayoutCache>,
+    text_cache: Option<TextLayoutCache>,
+    min_text_size: Option<LogicalLength>,
+    pixel_snapping: Option<f32>,
+    /// Where each node ended up in the previous layout, to tell which
+    /// ones get a `LayoutEvent`.
+    rects: HashMap<AnyNode, LogicalRect>,
+    /// The window and size of the previous layout with the tree it made,
+    /// which is returned again as long as neither they nor the styles
+    /// below the window change.
+    last: Option<(Node<Window>, LogicalSize, EqualRc<LayoutTreeNode>)>,
+    /// Whether the previous `layout` made a new tree.
+    changed: bool,
+    stats: LayoutStats,
+    /// The font generation the cache was filled with, see
+    /// `FontContext::register_font_file`.
+    font_generation: usize,
 }
 
 impl LayoutEngine {
+    /// Creates an engine with its own fonts. Prefer `with_context` when
+    /// there is more than one engine.
     pub fn new() -> LayoutEngine {
+        LayoutEngine::with_context(FontContext::new())
+    }
+
+    pub fn with_context(fonts: FontContext) -> LayoutEngine {
         LayoutEngine {
             runtime: Runtime::new(LayoutEngine::run_layout),
+            fragments: Runtime::new(LayoutEngine::run_fragment),
+            cache: Rc::new(LayoutCache::new()),
+            text_cache: None,
+            min_text_size: None,
+            pixel_snapping: None,
+            rects: HashMap::new(),
+            last: None,
+            changed: true,
+            stats: LayoutStats::default(),
+            font_generation: fonts.generation(),
+            fonts,
         }
     }
 
     #[illicit::from_env(node: &Node<Window>, size: &LogicalSize)]
     fn run_layout() -> EqualRc<LayoutTreeNode> {
-        let collection = once!(|| {
-            let mut collection = FontCollection::new();
-            let source = SystemSource::new();
-            let font = source
-                .select_best_match(&[FamilyName::SansSerif], &Properties::new())
-                .unwrap()
-                .load()
-                .unwrap();
-            collection.add_family(FontFamily::new_from_font(font));
-
-            EqualRc::new(collection)
-        });
+        LayoutEngine::layout_root(node.into(), *size)
+    }
+
+    #[illicit::from_env(node: &AnyNode, size: &LogicalSize)]
+    fn run_fragment() -> EqualRc<LayoutTreeNode> {
+        LayoutEngine::layout_root(node.into(), *size)
+    }
 
-        illicit::child_env!(EqualRc<FontCollection> => collection).enter(|| {
-            topo::call!({
+    /// Lays out `node` against `size`, with the fixed boxes inside of it
+    /// added as its last children.
+    fn layout_root(node: NodeRef, size: LogicalSize) -> EqualRc<LayoutTreeNode> {
+        let fixed = FixedLayer {
+            viewport: size,
+            children: RefCell::new(vec![]),
+        };
+
+        illicit::child_env!(FixedLayer => fixed).enter(|| {
+            let root = topo::call!({
                 let values = node.computed_values().get().unwrap();
                 match values.display {
                     DisplayType::Block(ref block) => {
-                        block::layout_block(node.into(), &values, block, *size)
+                        block::layout_block(node, &values, block, size)
                     }
-                    DisplayType::Inline(_) => inline::layout_inline(node.into(), &values, *size),
+                    DisplayType::Inline(_) => inline::layout_inline(node, &values, size, false),
+                }
+            },);
+            LayoutEngine::add_fixed_children(node.to_owned(), root)
+        })
+    }
+
+    #[illicit::from_env(fixed: &FixedLayer)]
+    fn add_fixed_children(node: AnyNode, root: EqualRc<LayoutTreeNode>) -> EqualRc<LayoutTreeNode> {
+        let fixed = fixed.children.replace(vec![]);
+        if fixed.is_empty() {
+            return root;
+        }
+        EqualRc::new(LayoutTreeNode {
+            size: root.size,
+            margin: root.margin,
+            render: RenderData::Node(node),
+            children: root.children.iter().cloned().chain(fixed).collect(),
+            clip: root.clip.clone(),
+            border_radius: root.border_radius,
+            truncated: root.truncated,
+            lines: root.lines,
+        })
+    }
+
+    /// Forgets everything remembered from previous layouts, so that the
+    /// next one starts from scratch and tells every node its rect again.
+    /// Needed when something the layout depends on changes outside of
+    /// the DOM, like the available fonts.
+    pub fn invalidate(&mut self) {
+        self.runtime = Runtime::new(LayoutEngine::run_layout);
+        self.fragments = Runtime::new(LayoutEngine::run_fragment);
+        self.cache.clear();
+        self.rects.clear();
+        self.last = None;
+    }
+
+    /// Lets go of layouts made with fonts that have since been added to.
+    fn check_fonts(&mut self) {
+        let generation = self.fonts.generation();
+        if generation != self.font_generation {
+            self.invalidate();
+            if let Some(ref text_cache) = self.text_cache {
+                text_cache.clear();
+            }
+            self.font_generation = generation;
+        }
+    }
+
+    /// Adds a font file for this engine and every other one sharing its
+    /// fonts, see `FontContext::register_font_file`.
+    pub fn register_font_file(&mut self, path: impl AsRef<Path>) -> Result<(), LayoutError> {
+        self.fonts.register_font_file(path)?;
+        self.check_fonts();
+        Ok(())
+    }
+
+    /// Tells the nodes that moved or changed size since the previous
+    /// layout about their new rect, see `Element::layout_changed`.
+    fn dispatch_layout_events(&mut self, tree: &LayoutTreeNode) {
+        let mut rects = vec![];
+        collect_node_rects(tree, LogicalPoint::zero(), &mut rects);
+        for (node, rect) in &rects {
+            if self.rects.get(node) != Some(rect) {
+                node.layout_changed(&LayoutEvent { rect: *rect });
+            }
+        }
+        self.rects = rects.into_iter().collect();
+    }
+
+    /// The window rect of `node` in the most recent layout, e.g. to
+    /// anchor a popup to it. `None` if the node wasn't laid out.
+    pub fn node_rect(&self, node: &AnyNode) -> Option<LogicalRect> {
+        self.rects.get(node).copied()
+    }
+
+    /// Limits how many layouts and shaped texts the engine remembers.
+    /// Without a limit, everything used by the most recent layout is
+    /// kept, which for e.g. a long chat history can be a lot; with one,
+    /// the least recently used are let go of first. Either way, entries
+    /// that the last layout didn't use are dropped.
+    pub fn set_cache_capacity(&mut self, capacity: Option<usize>) {
+        self.cache.set_capacity(capacity);
+    }
+
+    /// Keeps shaped text in `cache` instead of only in the engine's own
+    /// cache, so that it's still there for a new engine, or after
+    /// `invalidate`. It can be shared between engines, which only find
+    /// each other's texts when they share fonts, too.
+    pub fn set_text_cache(&mut self, cache: Option<TextLayoutCache>) {
+        self.text_cache = cache;
+    }
+
+    /// Lays out all text at least `min_size` large, e.g. for a user who
+    /// needs larger text to read it, whatever size elements ask for.
+    /// Unlike scaling the whole window, text that's large enough already
+    /// and everything that isn't text keep their sizes.
+    pub fn set_min_text_size(&mut self, min_size: Option<LogicalLength>) {
+        if min_size != self.min_text_size {
+            self.min_text_size = min_size;
+            self.invalidate();
+        }
+    }
+
+    /// Snaps the boxes of blocks to the device pixel grid at `scale`
+    /// device pixels per logical pixel, the same way the renderer snaps
+    /// what it draws, so that hit testing and `node_rect` agree with
+    /// what's on screen. `None` keeps fractional positions and sizes.
+    pub fn set_pixel_snapping(&mut self, scale: Option<f32>) {
+        if scale != self.pixel_snapping {
+            self.pixel_snapping = scale;
+            self.invalidate();
+        }
+    }
+
+    /// Hits, misses and evictions of the layout cache over the lifetime
+    /// of the engine, for tuning `set_cache_capacity`.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    /// What the most recent `layout` or `layout_fragment` had to compute
+    /// rather than take from the cache.
+    pub fn stats(&self) -> LayoutStats {
+        self.stats
+    }
+
+    /// Whether the most recent `layout` returned a different tree than
+    /// the one before it. When it didn't, a host that lays out every
+    /// frame can skip drawing that frame, too.
+    pub fn changed(&self) -> bool {
+        self.changed
+    }
+
+    /// The metrics of the font text with `values` is set in, picked the
+    /// same way as during layout. `None` when there are no fonts.
+    pub fn font_metrics(&self, values: &ComputedValues) -> Option<FontMetrics> {
+        illicit::child_env!(
+            FontContext => self.fonts.clone(),
+            MinTextSize => MinTextSize(self.min_text_size)
+        )
+        .enter(|| text::font_metrics(&TextOptions::new(values)))
+    }
+
+    /// The narrowest and widest that `text` set in the style of `values`
+    /// lays out, for sizing columns to the text they hold: the width of
+    /// its longest word, and that of its longest line when it isn't
+    /// wrapped.
+    pub fn text_content_widths(&self, values: &ComputedValues, text: Rc<str>) -> (f32, f32) {
+        illicit::child_env!(
+            FontContext => self.fonts.clone(),
+            Option<TextLayoutCache> => self.text_cache.clone(),
+            MinTextSize => MinTextSize(self.min_text_size)
+        )
+        .enter(|| text::shape_text(text, TextOptions::new(values), true).content_widths())
+    }
+
+    /// Lays out rich text, like highlighted code, as the content of
+    /// `parent` at most `max_width` wide. Its lines and the runs on them
+    /// come out as the children of the returned node, each carrying the
+    /// style of its run. `parent` has to have been styled.
+    pub fn layout_attributed(
+        &mut self,
+        parent: AnyNode,
+        text: &AttributedText,
+        max_width: f32,
+    ) -> EqualRc<LayoutTreeNode> {
+        self.check_fonts();
+        let values = parent.computed_values().get().unwrap_or_default();
+        let (fonts, cache, text_cache) = (&self.fonts, &self.cache, &self.text_cache);
+        let min_text_size = MinTextSize(self.min_text_size);
+        let pixel_snapping = PixelSnapping(self.pixel_snapping);
+        let (layout, stats) = stats::collect(|| {
+            illicit::child_env!(
+                FontContext => fonts.clone(),
+                Rc<LayoutCache> => cache.clone(),
+                Option<TextLayoutCache> => text_cache.clone(),
+                MinTextSize => min_text_size,
+                PixelSnapping => pixel_snapping
+            )
+            .enter(|| inline::layout_attributed(parent, &values, text, max_width))
+        });
+        self.stats = stats;
+        layout
+    }
+
+    /// Measures the columns of a table with `rows`, e.g. a list whose rows
+    /// line up, for `layout_row`. Each element child of a row is a cell,
+    /// and a column is as wide as the widest of its cells at their
+    /// natural size, margins included. Rows can have fewer cells than
+    /// there are columns.
+    pub fn column_widths(&mut self, rows: &[AnyNode]) -> Vec<f32> {
+        let mut widths: Vec<f32> = vec![];
+        let mut stats = LayoutStats::default();
+        for row in rows {
+            for (column, cell) in row_cells(row).into_iter().enumerate() {
+                let layout = self.layout_fragment(cell, size2(UNBOUNDED, UNBOUNDED));
+                stats += self.stats;
+                let width = layout.size.width + layout.margin.horizontal();
+                match widths.get_mut(column) {
+                    Some(widest) => *widest = widest.max(width),
+                    None => widths.push(width),
                 }
-            },)
+            }
+        }
+        self.stats = stats;
+        widths
+    }
+
+    /// Lays out the cells of `row` side by side in columns of `widths`,
+    /// as measured by `column_widths`, without looking at the other rows
+    /// again. The row spans every column, even those it has no cell for,
+    /// and is as tall as its tallest cell. Cells past the last column are
+    /// left out, and each cell's margins come out of its column.
+    pub fn layout_row(&mut self, row: AnyNode, widths: &[f32]) -> EqualRc<LayoutTreeNode> {
+        let mut children = vec![];
+        let (mut x, mut height) = (0.0, 0.0f32);
+        let mut stats = LayoutStats::default();
+        for (cell, width) in row_cells(&row).into_iter().zip(widths) {
+            let margin = match cell.computed_values().get().map(|values| values.display) {
+                Some(DisplayType::Block(block)) => block.margin.horizontal(),
+                _ => 0.0,
+            };
+            let layout = self.layout_fragment(cell, size2((width - margin).max(0.0), UNBOUNDED));
+            stats += self.stats;
+            height = height.max(layout.size.height + layout.margin.vertical());
+            children.push(LayoutChild {
+                position: Point2D::new(x + layout.margin.left, layout.margin.top),
+                layout,
+            });
+            x += width;
+        }
+        self.stats = stats;
+        EqualRc::new(LayoutTreeNode {
+            size: size2(widths.iter().sum(), height),
+            margin: LogicalSideOffsets::zero(),
+            render: RenderData::Node(row),
+            truncated: children.iter().any(|child| child.layout.truncated),
+            children,
+            clip: None,
+            border_radius: BorderRadius::default(),
+            lines: 0,
         })
     }
 
+    /// Computes boxes and baselines for everything in `tree`, for
+    /// drawing on top of the window when debugging layout.
+    pub fn debug_boxes(&self, tree: &LayoutTreeNode) -> Vec<DebugShape> {
+        let mut shapes = vec![];
+        debug::collect_shapes(tree, LogicalPoint::zero(), &mut shapes);
+        shapes
+    }
+
+    /// Like `layout`, but checks for the problems that would otherwise
+    /// make layout panic or produce garbage, and reports them instead.
+    pub fn try_layout(
+        &mut self,
+        node: Node<Window>,
+        size: LogicalSize,
+    ) -> Result<EqualRc<LayoutTreeNode>, LayoutError> {
+        let valid = |length: f32| length.is_finite() && length >= 0.0;
+        if !valid(size.width) || !valid(size.height) {
+            return Err(LayoutError::InvalidSize(size));
+        }
+        if self.fonts.families.get() == 0 {
+            return Err(LayoutError::NoFonts);
+        }
+        if node.computed_values().get().is_none() {
+            return Err(LayoutError::MissingStyle);
+        }
+        Ok(self.layout(node, size))
+    }
+
     /// Perform a layout step based on the new DOM and content size, and
-    /// return a fresh layout tree.
+    /// return a fresh layout tree. Nodes don't change once created, so
+    /// when the window is the one laid out last time, at the same size,
+    /// and styling found nothing below it changed, the previous tree is
+    /// returned without laying anything out, see `changed`. What styling
+    /// found is the flag `StyleEngine::update` leaves on the window, so
+    /// the shortcut relies on it having run since the last layout.
+    /// Computed values set any other way go unnoticed until `invalidate`
+    /// is called.
+    ///
+    /// Whether the tree changed is kept for `changed` instead of being
+    /// returned with it, since most callers only want the tree, and a
+    /// host that does want to know usually asks later, when it decides
+    /// whether to draw.
     pub fn layout(&mut self, node: Node<Window>, size: LogicalSize) -> EqualRc<LayoutTreeNode> {
-        illicit::child_env! (
-            Node<Window> => node,
-            LogicalSize => size
-        )
-        .enter(|| topo::call!({ self.runtime.run_once() },))
+        self.check_fonts();
+        if let Some((ref window, last_size, ref tree)) = self.last {
+            if *window == node && last_size == size && !NodeRef::from(&node).style_changed().get() {
+                self.changed = false;
+                self.stats = LayoutStats::default();
+                return tree.clone();
+            }
+        }
+        let window = node.clone();
+        let (runtime, fonts, cache) = (&mut self.runtime, &self.fonts, &self.cache);
+        let (text_cache, min_text_size) = (&self.text_cache, MinTextSize(self.min_text_size));
+        let pixel_snapping = PixelSnapping(self.pixel_snapping);
+        let (layout, stats) = stats::collect(|| {
+            illicit::child_env!(
+                Node<Window> => node,
+                LogicalSize => size,
+                FontContext => fonts.clone(),
+                Rc<LayoutCache> => cache.clone(),
+                Option<TextLayoutCache> => text_cache.clone(),
+                MinTextSize => min_text_size,
+                PixelSnapping => pixel_snapping
+            )
+            .enter(|| topo::call!({ runtime.run_once() },))
+        });
+        self.stats = stats;
+        self.cache.sweep();
+        self.dispatch_layout_events(&layout);
+        self.changed = self
+            .last
+            .as_ref()
+            .map_or(true, |(_, _, last)| *last != layout);
+        self.last = Some((window, size, layout.clone()));
+        layout
+    }
+
+    /// Lays out a styled subtree on its own against `max_size`, e.g. a
+    /// widget drawn in many places, for the caller to place wherever it
+    /// likes. Fragments share the cache of the window's layout, so as
+    /// long as the subtree and its styles are unchanged the previous
+    /// layout is returned as is. Like the rest of the cache, a fragment
+    /// is forgotten once a window layout goes by without it being used.
+    /// Either axis of `max_size` can be `UNBOUNDED`, which measures the
+    /// subtree's natural size along it, e.g. text on a single line.
+    pub fn layout_fragment(
+        &mut self,
+        node: AnyNode,
+        max_size: LogicalSize,
+    ) -> EqualRc<LayoutTreeNode> {
+        self.check_fonts();
+        let (fragments, fonts, cache) = (&mut self.fragments, &self.fonts, &self.cache);
+        let (text_cache, min_text_size) = (&self.text_cache, MinTextSize(self.min_text_size));
+        let pixel_snapping = PixelSnapping(self.pixel_snapping);
+        let (layout, stats) = stats::collect(|| {
+            illicit::child_env!(
+                AnyNode => node,
+                LogicalSize => max_size,
+                FontContext => fonts.clone(),
+                Rc<LayoutCache> => cache.clone(),
+                Option<TextLayoutCache> => text_cache.clone(),
+                MinTextSize => min_text_size,
+                PixelSnapping => pixel_snapping
+            )
+            .enter(|| topo::call!({ fragments.run_once() },))
+        });
+        self.stats = stats;
+        layout
+    }
+
+    /// The size of `node` with nothing limiting it, e.g. for sizing a
+    /// tooltip to its content. Text stays on a single line, apart from
+    /// its own line breaks. A fixed width or height still applies, and
+    /// margins are left out. See `layout_fragment`.
+    pub fn natural_size(&mut self, node: AnyNode) -> LogicalSize {
+        self.layout_fragment(node, size2(UNBOUNDED, UNBOUNDED)).size
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        block, test_font, text, AttributedText, CacheStats, CaretDirection, CustomLayout,
+        CustomLayoutRef, FontContext, LayoutCache, LayoutChild, LayoutClip, LayoutEngine,
+        LayoutError, LayoutStats, LayoutTreeNode, LogicalSize, LogicalTransform, NodeIdentity,
+        Region, RenderData, RunStyle, TextDecoration, TextLayoutCache, TextLayoutInfo, TextOptions,
+        TreeStats, UNBOUNDED,
+    };
+    use crate::dom::element::{Element, HasAttribute, HasEvent};
+    use crate::dom::node::AnyNode;
+    use crate::dom::{AttrKey, AttrStyle, LayoutEvent, Node, Span, View, Window};
+    use crate::layout::{LogicalLength, LogicalRect};
+    use crate::style::{
+        Alignment, BlockValues, BorderRadius, ChildOverflow, CommonAttributes, ComputedValues,
+        DisplayType, InlineValues, Overflow, Style, StyleData, StyleEngine, TextAlign,
+        TransformOrigin, Value, WhiteSpace, DEFAULT_ATTRIBUTES,
+    };
+    use crate::util::equal_rc::EqualRc;
+    use crate::util::event_handler::EventHandler;
+    use crate::Color;
+    use euclid::{point2, size2, Angle, SideOffsets2D};
+    use font_kit::family_name::FamilyName;
+    use font_kit::handle::Handle;
+    use font_kit::properties::Properties;
+    use font_kit::source::SystemSource;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn engines_share_font_context() {
+        let fonts = FontContext::from_families(vec![]);
+        let first = LayoutEngine::with_context(fonts.clone());
+        let second = LayoutEngine::with_context(fonts.clone());
+        assert!(first.fonts == fonts);
+        assert!(second.fonts == fonts);
+    }
+
+    #[test]
+    fn font_stacks_are_loaded_once() {
+        let fonts = FontContext::from_families(vec![]);
+        assert!(fonts.collection_for(None, true) == fonts.collection);
+        let stack: &'static [&'static str] = &["monospace"];
+        let first = fonts.collection_for(Some(stack), true);
+        assert!(first == fonts.clone().collection_for(Some(stack), true));
+        assert!(first != fonts.collection_for(Some(&["serif"]), true));
+    }
+
+    #[test]
+    fn families_are_kept_without_fallback() {
+        // There's no telling which of the given families is the primary
+        // one, so all of them are used.
+        let fonts = FontContext::from_families(vec![]);
+        assert!(fonts.collection_for(None, false) == fonts.collection);
+        let stack: &'static [&'static str] = &["monospace"];
+        assert!(
+            fonts.collection_for(Some(stack), false) != fonts.collection_for(Some(stack), true)
+        );
+    }
+
+    #[test]
+    fn font_metrics_are_scaled_to_the_text_size() {
+        let engine = LayoutEngine::with_context(test_font::font_context());
+        let values = ComputedValues {
+            text_size: LogicalLength::new(16.0),
+            ..Default::default()
+        };
+        let metrics = engine.font_metrics(&values).unwrap();
+        // The test font's ascender is 800 units and its descender -200,
+        // out of 1000 to the em.
+        assert!((metrics.ascent - 12.8).abs() < 1e-4);
+        assert!((metrics.descent + 3.2).abs() < 1e-4);
+        assert_eq!(metrics.line_gap, 0.0);
+        assert!(metrics.x_height < metrics.cap_height);
+        assert!(
+            LayoutEngine::with_context(FontContext::from_families(vec![]))
+                .font_metrics(&values)
+                .is_none()
+        );
+    }
+
+    #[test]
+    #[ignore] // needs system fonts
+    fn registered_font_is_used_by_next_layout() {
+        let handle = SystemSource::new()
+            .select_best_match(&[FamilyName::SansSerif], &Properties::new())
+            .unwrap();
+        let path = match handle {
+            Handle::Path { path, .. } => path,
+            Handle::Memory { .. } => return,
+        };
+        let text: Rc<str> = "a".into();
+        let span = styled(Node::new(Span::default(), vec![text.into()]));
+        let view = styled(Node::new(View::default(), vec![span.into()]));
+        let window = styled(Node::new(Window::default(), vec![view]));
+        let fonts = FontContext::from_families(vec![]);
+        let mut engine = LayoutEngine::with_context(fonts.clone());
+        let mut other = LayoutEngine::with_context(fonts);
+        let width =
+            |layout: &LayoutTreeNode| layout.children[0].layout.children[0].layout.size.width;
+
+        let before = engine.layout(window.clone(), size2(100.0, 100.0));
+        assert_eq!(width(&before), 0.0);
+        other.layout(window.clone(), size2(100.0, 100.0));
+
+        engine.register_font_file(&path).unwrap();
+        let after = engine.layout(window.clone(), size2(100.0, 100.0));
+        assert!(width(&after) > 0.0);
+        // The other engine shares the fonts, and notices the new one.
+        let after = other.layout(window, size2(100.0, 100.0));
+        assert!(width(&after) > 0.0);
+    }
+
+    #[test]
+    fn font_registration_is_all_or_nothing() {
+        let fonts = FontContext::from_families(vec![]);
+        let missing = std::env::temp_dir().join("moxie-native-missing-font.ttf");
+        assert!(fonts.register_font_file(&missing).is_err());
+        assert_eq!(fonts.generation(), 0);
+        assert_eq!(fonts.families.get(), 0);
+
+        let path = std::env::temp_dir().join("moxie-native-test-font.ttf");
+        std::fs::write(&path, test_font::font_bytes()).unwrap();
+        fonts.register_font_file(&path).unwrap();
+        assert_eq!(fonts.generation(), 1);
+        assert_eq!(fonts.families.get(), 1);
+        // The font was loaded once, and the file isn't needed anymore.
+        std::fs::remove_file(&path).unwrap();
+        illicit::child_env!(FontContext => fonts.clone()).enter(|| {
+            let metrics = text::font_metrics(&TextOptions::new(&ComputedValues::default()));
+            assert_eq!(metrics.unwrap().ascent, 16.0 * test_font::ASCENT);
+        });
+    }
+
+    #[test]
+    fn shaping_cache_evicts_least_recently_used_paragraphs() {
+        let fonts = test_font::font_context();
+        fonts.set_shaping_cache_capacity(Some(2));
+        illicit::child_env!(FontContext => fonts.clone()).enter(|| {
+            let options = TextOptions::new(&ComputedValues::default());
+            TextLayoutInfo::new("a\nb\nc".into(), options, false);
+            assert_eq!(fonts.shaping_cache_stats().misses, 3);
+            assert_eq!(fonts.shaping_cache_stats().evictions, 1);
+            // "a" was let go of for "c", the others are still shaped.
+            TextLayoutInfo::new("b\nc".into(), options, false);
+            assert_eq!(
+                fonts.shaping_cache_stats(),
+                CacheStats {
+                    hits: 2,
+                    misses: 3,
+                    evictions: 1,
+                }
+            );
+        });
+    }
+
+    #[test]
+    fn broken_inputs_are_errors() {
+        let window = Node::new(Window::default(), vec![]);
+        let mut engine = LayoutEngine::with_context(FontContext::from_families(vec![]));
+
+        let result = engine.try_layout(window.clone(), size2(std::f32::NAN, 100.0));
+        match result {
+            Err(LayoutError::InvalidSize(size)) => assert!(size.width.is_nan()),
+            _ => panic!("NaN size accepted"),
+        }
+        let result = engine.try_layout(window.clone(), size2(100.0, -1.0));
+        assert_eq!(
+            result.err(),
+            Some(LayoutError::InvalidSize(size2(100.0, -1.0)))
+        );
+
+        let result = engine.try_layout(window.clone(), size2(100.0, 100.0));
+        assert_eq!(result.err(), Some(LayoutError::NoFonts));
+
+        window
+            .computed_values()
+            .set(Some(ComputedValues::default()));
+        let result = engine.try_layout(window, size2(100.0, 100.0));
+        assert_eq!(result.err(), Some(LayoutError::NoFonts));
+    }
+
+    #[test]
+    fn layout_shares_the_dom_text() {
+        let paragraphs: Vec<Rc<str>> = (0..20)
+            .map(|i| {
+                format!("{} lorem ipsum dolor sit amet. ", i)
+                    .repeat(40)
+                    .into()
+            })
+            .collect();
+        let spans = paragraphs
+            .iter()
+            .map(|paragraph| {
+                let span = Node::new(Span::default(), vec![paragraph.clone().into()]);
+                span.computed_values()
+                    .set(Some(Span::default().create_computed_values()));
+                span.into()
+            })
+            .collect();
+        let view = Node::new(View::default(), spans);
+        view.computed_values()
+            .set(Some(View::default().create_computed_values()));
+        let window = Node::new(Window::default(), vec![view]);
+        window
+            .computed_values()
+            .set(Some(ComputedValues::default()));
+        let before: Vec<usize> = paragraphs.iter().map(Rc::strong_count).collect();
+
+        let mut engine = LayoutEngine::with_context(test_font::font_context());
+        engine.layout(window, size2(600.0, 800.0));
+        // Each text is laid out from the DOM's own string, not a copy.
+        for (paragraph, before) in paragraphs.iter().zip(before) {
+            assert!(Rc::strong_count(paragraph) > before);
+        }
+    }
+
+    #[test]
+    fn invalidate_recomputes_unchanged_tree() {
+        let view = Node::new(View::default(), vec![]);
+        view.computed_values()
+            .set(Some(View::default().create_computed_values()));
+        let window = Node::new(Window::default(), vec![view.into()]);
+        window
+            .computed_values()
+            .set(Some(ComputedValues::default()));
+        let mut engine = LayoutEngine::with_context(FontContext::from_families(vec![]));
+
+        let first = engine.layout(window.clone(), size2(100.0, 100.0));
+        let boxes = engine.stats().boxes;
+        assert!(boxes > 0);
+        let cached = engine.layout(window.clone(), size2(100.0, 100.0));
+        assert!(first == cached);
+        assert_eq!(engine.stats().boxes, 0);
+
+        // Nothing about the nodes changed, but every one of them is
+        // laid out again.
+        engine.invalidate();
+        let recomputed = engine.layout(window, size2(100.0, 100.0));
+        assert_eq!(engine.stats().boxes, boxes);
+        assert!(first != recomputed);
+        assert_eq!(first.size, recomputed.size);
+    }
+
+    fn styled<Elt: Element>(node: Node<Elt>) -> Node<Elt> {
+        node.computed_values()
+            .set(Some(Elt::default().create_computed_values()));
+        node
+    }
+
+    #[test]
+    fn window_background_is_clear_color() {
+        let mut engine = LayoutEngine::with_context(FontContext::from_families(vec![]));
+        let window = styled(Node::new(Window::default(), vec![]));
+        let layout = engine.layout(window, size2(100.0, 100.0));
+        assert_eq!(layout.clear_color(), Color::white());
+
+        let window = Node::new(Window::default(), vec![]);
+        window.computed_values().set(Some(ComputedValues {
+            background_color: Color::new(20, 20, 30, 255),
+            ..Window::default().create_computed_values()
+        }));
+        let layout = engine.layout(window, size2(100.0, 100.0));
+        assert_eq!(layout.clear_color(), Color::new(20, 20, 30, 255));
+    }
+
+    #[test]
+    fn text_cache_outlives_engines() {
+        let fonts = test_font::font_context();
+        let text_cache = TextLayoutCache::new();
+        let text: Rc<str> = "shaped once".into();
+        let span = styled(Node::new(Span::default(), vec![text.into()]));
+        let view = styled(Node::new(View::default(), vec![span.into()]));
+        let window = styled(Node::new(Window::default(), vec![view]));
+        let layout_with_new_engine = || {
+            let mut engine = LayoutEngine::with_context(fonts.clone());
+            engine.set_text_cache(Some(text_cache.clone()));
+            engine.layout(window.clone(), size2(400.0, 400.0));
+            engine.stats()
+        };
+
+        assert_eq!(layout_with_new_engine().texts, 1);
+        assert_eq!(text_cache.len(), 1);
+        let stats = layout_with_new_engine();
+        assert_eq!(stats.texts, 0);
+        assert!(stats.lines > 0);
+    }
+
+    #[test]
+    fn text_cache_keeps_texts_of_other_fonts_apart() {
+        let text_cache = TextLayoutCache::new();
+        text_cache.set_capacity(Some(2));
+        let layout = |fonts: &FontContext, text: &str| {
+            let span = styled(Node::new(
+                Span::default(),
+                vec![Rc::<str>::from(text).into()],
+            ));
+            let view = styled(Node::new(View::default(), vec![span.into()]));
+            let window = styled(Node::new(Window::default(), vec![view]));
+            let mut engine = LayoutEngine::with_context(fonts.clone());
+            engine.set_text_cache(Some(text_cache.clone()));
+            engine.layout(window, size2(400.0, 400.0));
+            engine.stats().texts
+        };
+        let fonts = test_font::font_context();
+        assert_eq!(layout(&fonts, "a"), 1);
+        assert_eq!(layout(&fonts, "a"), 0);
+        // The same text in other fonts is shaped again.
+        assert_eq!(layout(&test_font::font_context(), "a"), 1);
+        assert_eq!(text_cache.len(), 1);
+
+        assert_eq!(layout(&fonts, "b"), 1);
+        assert_eq!(layout(&fonts, "c"), 1);
+        assert_eq!(text_cache.len(), 2);
+        assert_eq!(text_cache.stats().evictions, 1);
+    }
+
+    #[test]
+    #[ignore] // needs system fonts
+    fn attributed_runs_keep_their_styles() {
+        let red = Color::new(200, 0, 0, 255);
+        let bold_red = RunStyle {
+            text_color: Some(red),
+            font_weight: Some(700),
+            ..Default::default()
+        };
+        let text = AttributedText::new("fn main() {}").with_span(0..3, bold_red);
+        let parent = styled(Node::new(Span::default(), vec![]));
+        let mut engine = LayoutEngine::new();
+        let layout = engine.layout_attributed(parent.into(), &text, 400.0);
+
+        let runs: Vec<_> = layout
+            .children
+            .iter()
+            .map(|child| match child.layout.render {
+                RenderData::Text { ref text, .. } => (text.style, child.position.x),
+                RenderData::Node(_) => unreachable!(),
+            })
+            .collect();
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[0], (bold_red, 0.0));
+        assert_eq!(runs[1].0, RunStyle::default());
+        assert!(runs[1].1 > 0.0);
+    }
+
+    #[test]
+    #[ignore] // needs system fonts
+    fn underline_covers_only_its_word() {
+        let text = AttributedText::new("underlined word")
+            .with_decoration(0..10, TextDecoration::Underline);
+        let parent: AnyNode = styled(Node::new(Span::default(), vec![])).into();
+        let mut engine = LayoutEngine::new();
+        let layout = engine.layout_attributed(parent.clone(), &text, 400.0);
+
+        // Decorations don't split the text, so it's still one line.
+        assert_eq!(layout.children.len(), 1);
+        let line = match layout.children[0].layout.render {
+            RenderData::Text { ref text, .. } => text,
+            RenderData::Node(_) => unreachable!(),
+        };
+        let word = layout.text_range_rects(&parent, 0, 0..10)[0];
+        assert_eq!(line.decorations.len(), 1);
+        let underline = &line.decorations[0];
+        assert_eq!(underline.range, 0..10);
+        assert_eq!(underline.x, 0.0);
+        assert!((underline.width - word.size.width).abs() < 0.01);
+        assert!(underline.width < layout.children[0].layout.size.width);
+    }
+
+    #[test]
+    fn attributed_lines_count_from_the_start_of_the_text() {
+        let red = RunStyle {
+            text_color: Some(Color::new(200, 0, 0, 255)),
+            ..Default::default()
+        };
+        let text = AttributedText::new("fn main() {}")
+            .with_span(0..3, red)
+            .with_decoration(5..9, TextDecoration::Underline);
+        let parent: AnyNode = styled(Node::new(Span::default(), vec![])).into();
+        let mut engine = LayoutEngine::with_context(test_font::font_context());
+        let layout = engine.layout_attributed(parent.clone(), &text, 400.0);
+
+        let lines: Vec<_> = layout
+            .children
+            .iter()
+            .map(|child| match child.layout.render {
+                RenderData::Text { ref text, .. } => text,
+                RenderData::Node(_) => unreachable!(),
+            })
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].range, 0..3);
+        assert_eq!(lines[1].range, 3..12);
+        assert_eq!(lines[1].decorations[0].range, 5..9);
+        let (char_width, space_width) = (8.0, 4.0);
+        assert_eq!(lines[1].offset_to_x(5), 2.0 * char_width);
+        assert_eq!(lines[1].x_to_offset(2.0 * char_width), 5);
+        // "fn " is the first run, "ma" is before the range in the second.
+        let rect = layout.text_range_rects(&parent, 0, 5..9)[0];
+        assert_eq!(rect.origin.x, 4.0 * char_width + space_width);
+        assert_eq!(rect.size.width, 4.0 * char_width);
+    }
+
+    fn list_window(rows: &[Node<View>]) -> Node<Window> {
+        let list = styled(Node::new(
+            View::default(),
+            rows.iter().cloned().map(Into::into).collect(),
+        ));
+        styled(Node::new(Window::default(), vec![list]))
+    }
+
+    #[test]
+    fn inserted_row_reuses_sibling_layouts() {
+        let rows: Vec<_> = (0..10)
+            .map(|_| styled(Node::new(View::default(), vec![])))
+            .collect();
+        let mut engine = LayoutEngine::with_context(FontContext::from_families(vec![]));
+        let before = engine.layout(list_window(&rows), size2(100.0, 100.0));
+
+        let mut inserted = vec![styled(Node::new(View::default(), vec![]))];
+        inserted.extend(rows.iter().cloned());
+        let after = engine.layout(list_window(&inserted), size2(100.0, 100.0));
+
+        let before_rows = &before.children[0].layout.children;
+        let after_rows = &after.children[0].layout.children;
+        assert_eq!(after_rows.len(), before_rows.len() + 1);
+        for (old, new) in before_rows.iter().zip(&after_rows[1..]) {
+            assert!(old.layout == new.layout);
+        }
+    }
+
+    #[test]
+    fn update_inside_a_boundary_keeps_the_parent_layout() {
+        let boxes_after_update = |layout_boundary| {
+            let inner = sized(20.0);
+            let boundary = Node::new(View::default(), vec![inner.clone().into()]);
+            boundary.computed_values().set(Some(ComputedValues {
+                display: DisplayType::Block(BlockValues {
+                    width: Some(LogicalLength::new(50.0)),
+                    height: Some(LogicalLength::new(50.0)),
+                    layout_boundary,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }));
+            let window = list_window(&[boundary, sized(30.0)]);
+            let mut engine = LayoutEngine::with_context(FontContext::from_families(vec![]));
+            engine.layout(window.clone(), size2(100.0, 100.0));
+
+            inner
+                .computed_values()
+                .set(sized(40.0).computed_values().get());
+            let layout = engine.layout(window.clone(), size2(100.0, 100.0));
+            let list = &layout.children[0].layout;
+            assert_eq!(list.children[0].layout.children[0].layout.size.width, 40.0);
+            assert_eq!(list.children[1].position.y, 50.0);
+            let boxes = engine.stats().boxes;
+
+            // The list with the boundary put back in it is kept as well,
+            // so laying out again without changes redoes nothing.
+            let again = engine.layout(window, size2(100.0, 100.0));
+            assert!(again.children[0].layout == layout.children[0].layout);
+            assert_eq!(engine.stats().boxes, 0);
+            boxes
+        };
+        // The inner box, the boundary and the window, but not the list.
+        assert_eq!(boxes_after_update(true), 3);
+        assert_eq!(boxes_after_update(false), 4);
+    }
+
+    fn sized(width: f32) -> Node<View> {
+        let node = Node::new(View::default(), vec![]);
+        node.computed_values().set(Some(ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                width: Some(LogicalLength::new(width)),
+                height: Some(LogicalLength::new(10.0)),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }));
+        node
+    }
+
+    #[test]
+    fn fill_takes_the_space_left_by_siblings() {
+        let content = Node::new(View::default(), vec![]);
+        content.computed_values().set(Some(ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                fill: true,
+                margin: SideOffsets2D::new(5.0, 0.0, 5.0, 0.0),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }));
+        let column = Node::new(
+            View::default(),
+            vec![sized(20.0).into(), content.into(), sized(20.0).into()],
+        );
+        column.computed_values().set(Some(ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                height: Some(LogicalLength::new(600.0)),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }));
+        let window = styled(Node::new(Window::default(), vec![column.into()]));
+        let mut engine = LayoutEngine::with_context(FontContext::from_families(vec![]));
+
+        let layout = engine.layout(window, size2(100.0, 800.0));
+        let rows = &layout.children[0].layout.children;
+        assert_eq!(rows[1].position, point2(0.0, 15.0));
+        assert_eq!(rows[1].layout.size.height, 570.0);
+        assert_eq!(rows[2].position, point2(0.0, 590.0));
+    }
+
+    #[test]
+    fn boxes_snap_to_the_pixel_grid() {
+        let column = Node::new(
+            View::default(),
+            vec![sized(10.3).into(), sized(10.3).into()],
+        );
+        column.computed_values().set(Some(ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                padding: SideOffsets2D::new(0.3, 0.0, 0.0, 0.3),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }));
+        let window = styled(Node::new(Window::default(), vec![column.into()]));
+        let mut engine = LayoutEngine::with_context(FontContext::from_families(vec![]));
+
+        let layout = engine.layout(window.clone(), size2(100.0, 100.0));
+        let column = &layout.children[0].layout;
+        assert_eq!(column.children[0].layout.size, size2(10.3, 10.0));
+
+        // Half pixels at a scale of 2: positions move back and sizes grow
+        // to the pixels they touch.
+        engine.set_pixel_snapping(Some(2.0));
+        let layout = engine.layout(window, size2(100.0, 100.0));
+        let column = &layout.children[0].layout;
+        assert_eq!(column.children[0].position, point2(0.0, 0.0));
+        assert_eq!(column.children[0].layout.size, size2(10.5, 10.0));
+        assert_eq!(column.children[1].position, point2(0.0, 10.0));
+        assert_eq!(column.size, size2(11.0, 20.5));
+    }
+
+    #[test]
+    fn single_child_wrappers_snap_like_any_other_box() {
+        let layout_of = |children: Vec<AnyNode>| {
+            let wrapper = Node::new(View::default(), children);
+            wrapper.computed_values().set(Some(ComputedValues {
+                display: DisplayType::Block(BlockValues {
+                    padding: SideOffsets2D::new_all_same(0.3),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }));
+            let window = styled(Node::new(Window::default(), vec![wrapper.into()]));
+            let mut engine = LayoutEngine::with_context(FontContext::from_families(vec![]));
+            engine.set_pixel_snapping(Some(2.0));
+            let layout = engine.layout(window, size2(100.0, 100.0));
+            let wrapper = &layout.children[0].layout;
+            (wrapper.size, wrapper.children[0].position)
+        };
+        let single = layout_of(vec![sized(10.3).into()]);
+        assert_eq!(single, (size2(11.5, 11.0), point2(0.0, 0.0)));
+        // An empty sibling takes the general path without moving anything.
+        let empty = styled(Node::new(View::default(), vec![]));
+        assert_eq!(layout_of(vec![sized(10.3).into(), empty.into()]), single);
+    }
+
+    #[test]
+    fn measurement_is_reused_until_styles_change() {
+        let child = sized(10.0);
+        let row = styled(Node::new(View::default(), vec![child.clone().into()]));
+        let cache = Rc::new(LayoutCache::new());
+        let measure = || {
+            illicit::child_env!(Rc<LayoutCache> => cache.clone()).enter(|| {
+                let values = row.computed_values().get().unwrap();
+                match values.display {
+                    DisplayType::Block(ref block) => {
+                        block::measure_block((&row).into(), &values, block, size2(100.0, 100.0))
+                    }
+                    DisplayType::Inline(_) => unreachable!(),
+                }
+            })
+        };
+
+        assert_eq!(measure(), size2(10.0, 10.0));
+        let entries = cache.len();
+        assert_eq!(measure(), size2(10.0, 10.0));
+        assert_eq!(cache.len(), entries);
+
+        child
+            .computed_values()
+            .set(sized(20.0).computed_values().get());
+        assert_eq!(measure(), size2(20.0, 10.0));
+    }
+
+    fn cell() -> AnyNode {
+        let label = styled(Node::new(View::default(), vec![]));
+        styled(Node::new(View::default(), vec![label.into()])).into()
+    }
+
+    #[test]
+    fn unchanged_tree_computes_nothing() {
+        let rows: Vec<_> = (0..3)
+            .map(|_| styled(Node::new(View::default(), vec![])))
+            .collect();
+        let window = list_window(&rows);
+        let mut engine = LayoutEngine::with_context(FontContext::from_families(vec![]));
+
+        engine.layout(window.clone(), size2(100.0, 100.0));
+        // The window, the list, and its rows.
+        assert_eq!(engine.stats().boxes, 5);
+        engine.layout(window.clone(), size2(100.0, 100.0));
+        assert_eq!(engine.stats(), LayoutStats::default());
+
+        rows[1]
+            .computed_values()
+            .set(sized(10.0).computed_values().get());
+        engine.layout(window, size2(100.0, 100.0));
+        // The changed row and the blocks around it.
+        assert_eq!(engine.stats().boxes, 3);
+    }
+
+    #[test]
+    fn unchanged_subtrees_are_skipped_after_styling() {
+        static TALL: Style = Style(&StyleData {
+            name: "TALL",
+            file: file!(),
+            line: line!(),
+            attributes: CommonAttributes {
+                height: Some(Value {
+                    pixels: 10.0,
+                    ems: 0.0,
+                    view_width: 0.0,
+                    view_height: 0.0,
+                    percent: 0.0,
+                }),
+                ..DEFAULT_ATTRIBUTES
+            },
+            sub_styles: &[],
+        });
+        let tall = || {
+            let mut view = View::default();
+            <View as HasAttribute<AttrStyle>>::set_attribute(&mut view, Some(TALL));
+            Node::new(view, vec![])
+        };
+        let mut rows: Vec<_> = (0..3).map(|_| Node::new(View::default(), vec![])).collect();
+        let mut styles = StyleEngine::new();
+        let mut engine = LayoutEngine::with_context(FontContext::from_families(vec![]));
+        let row = |tree: &LayoutTreeNode, index: usize| {
+            let list = &tree.children[0].layout;
+            (
+                list.children[index].position,
+                list.children[index].layout.clone(),
+            )
+        };
+
+        let window = list_window(&rows);
+        styles.update(window.clone(), size2(100.0, 100.0));
+        let before = engine.layout(window.clone(), size2(100.0, 100.0));
+        // Styling found nothing changed, so the window's previous layout is
+        // returned without even looking in the cache.
+        styles.update(window.clone(), size2(100.0, 100.0));
+        let hits = engine.cache_stats().hits;
+        assert!(before == engine.layout(window, size2(100.0, 100.0)));
+        assert_eq!(engine.cache_stats().hits, hits);
+        assert!(!engine.changed());
+
+        let mut layout = |rows: &[Node<View>]| {
+            let window = list_window(rows);
+            styles.update(window.clone(), size2(100.0, 100.0));
+            engine.layout(window, size2(100.0, 100.0))
+        };
+        // A taller row in the middle moves the one after it.
+        rows[1] = tall();
+        let first = layout(&rows);
+        assert_eq!(row(&first, 2).0.y, 10.0);
+
+        // Replacing it again keeps the rows around it as they were.
+        rows[1] = tall();
+        let second = layout(&rows);
+        assert!(row(&first, 0).1 == row(&second, 0).1);
+        assert!(row(&first, 2).1 == row(&second, 2).1);
+        assert_eq!(row(&second, 2).0.y, 10.0);
+    }
+
+    #[test]
+    fn place_content_centers_in_both_axes() {
+        const fn px(pixels: f32) -> Option<Value> {
+            Some(Value {
+                pixels,
+                ems: 0.0,
+                view_width: 0.0,
+                view_height: 0.0,
+                percent: 0.0,
+            })
+        }
+        static DIALOG: Style = Style(&StyleData {
+            name: "DIALOG",
+            file: file!(),
+            line: line!(),
+            attributes: CommonAttributes {
+                width: px(400.0),
+                height: px(400.0),
+                place_content: Some(Alignment::Center),
+                ..DEFAULT_ATTRIBUTES
+            },
+            sub_styles: &[],
+        });
+        static SQUARE: Style = Style(&StyleData {
+            name: "SQUARE",
+            file: file!(),
+            line: line!(),
+            attributes: CommonAttributes {
+                width: px(100.0),
+                height: px(100.0),
+                ..DEFAULT_ATTRIBUTES
+            },
+            sub_styles: &[],
+        });
+        let styled_view = |style, children| {
+            let mut view = View::default();
+            <View as HasAttribute<AttrStyle>>::set_attribute(&mut view, Some(style));
+            Node::new(view, children)
+        };
+        let square = styled_view(SQUARE, vec![]);
+        let dialog = styled_view(DIALOG, vec![square.into()]);
+        let window = Node::new(Window::default(), vec![dialog.into()]);
+        StyleEngine::new().update(window.clone(), size2(800.0, 600.0));
+        let tree = LayoutEngine::with_context(FontContext::from_families(vec![]))
+            .layout(window, size2(800.0, 600.0));
+        let dialog = &tree.children[0].layout;
+        assert_eq!(dialog.size, size2(400.0, 400.0));
+        assert_eq!(dialog.children[0].position, point2(150.0, 150.0));
+    }
+
+    #[test]
+    fn unchanged_window_is_not_laid_out_again() {
+        let rows: Vec<_> = (0..3).map(|_| Node::new(View::default(), vec![])).collect();
+        let window = list_window(&rows);
+        let mut styles = StyleEngine::new();
+        let mut engine = LayoutEngine::with_context(FontContext::from_families(vec![]));
+        let mut layout = |window: &Node<Window>, size| {
+            styles.update(window.clone(), size);
+            let tree = engine.layout(window.clone(), size);
+            (tree, engine.changed(), engine.stats().boxes)
+        };
+
+        let (first, changed, _) = layout(&window, size2(100.0, 100.0));
+        assert!(changed);
+        let (second, changed, boxes) = layout(&window, size2(100.0, 100.0));
+        assert!(first == second);
+        assert!(!changed);
+        assert_eq!(boxes, 0);
+
+        let (resized, changed, _) = layout(&window, size2(50.0, 100.0));
+        assert!(resized != first);
+        assert!(changed);
+        // A new window is laid out, even over the same rows.
+        let (_, changed, boxes) = layout(&list_window(&rows), size2(50.0, 100.0));
+        assert!(changed);
+        assert!(boxes > 0);
+    }
+
+    #[test]
+    fn fragments_are_reused() {
+        let mut engine = LayoutEngine::with_context(FontContext::from_families(vec![]));
+        let cell = cell();
+        let first = engine.layout_fragment(cell.clone(), size2(100.0, 20.0));
+        assert!(first == engine.layout_fragment(cell.clone(), size2(100.0, 20.0)));
+        assert!(first != engine.layout_fragment(cell(), size2(100.0, 20.0)));
+    }
+
+    #[test]
+    fn range_rects_across_a_wrap_are_relative_to_the_root() {
+        let text: Rc<str> = "hello world".into();
+        let span = styled(Node::new(Span::default(), vec![text.into()]));
+        let view = Node::new(View::default(), vec![span.clone().into()]);
+        view.computed_values().set(Some(ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                width: Some(LogicalLength::new(60.0)),
+                padding: SideOffsets2D::new(5.0, 0.0, 0.0, 7.0),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }));
+        let window = styled(Node::new(Window::default(), vec![view.into()]));
+        let mut engine = LayoutEngine::with_context(test_font::font_context());
+        let layout = engine.layout(window, size2(200.0, 100.0));
+
+        // "lo wor" runs from after "hel" to the end of "hello " on the
+        // first line, and over "wor" on the second, inside the padding.
+        assert_eq!(
+            layout.text_range_rects(&span.into(), 0, 3..9),
+            vec![
+                LogicalRect::new(point2(31.0, 5.0), size2(20.0, 16.0)),
+                LogicalRect::new(point2(7.0, 21.0), size2(24.0, 16.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn selection_has_a_rect_per_line() {
+        let text: Rc<str> = "aaaaaaaaaa bbbbbbbbbb cccccccccc dddddddddd".into();
+        let span = styled(Node::new(Span::default(), vec![text.into()]));
+        let view = styled(Node::new(View::default(), vec![span.clone().into()]));
+        let window = styled(Node::new(Window::default(), vec![view]));
+        let mut engine = LayoutEngine::with_context(test_font::font_context());
+        // Narrow enough for one word per line, each word 80px wide and
+        // followed by a 4px space on its line.
+        let layout = engine.layout(window, size2(120.0, 400.0));
+        let span = span.into();
+        let rect = |x, y, width| LogicalRect::new(point2(x, y), size2(width, 16.0));
+
+        // From the middle of the first word to the middle of the third.
+        assert_eq!(
+            layout.text_range_rects(&span, 0, 5..27),
+            vec![
+                rect(40.0, 0.0, 44.0),
+                rect(0.0, 16.0, 84.0),
+                rect(0.0, 32.0, 40.0),
+            ]
+        );
+        // Within one word.
+        assert_eq!(
+            layout.text_range_rects(&span, 0, 2..7),
+            vec![rect(16.0, 0.0, 40.0)]
+        );
+
+        // A caret at a wrap goes at the start of the next line.
+        assert_eq!(
+            layout.text_range_rects(&span, 0, 11..11),
+            vec![rect(0.0, 16.0, 0.0)]
+        );
+    }
+
+    #[test]
+    fn caret_at_the_end_follows_a_final_line_break() {
+        let caret_at_end = |text: &str| {
+            let text: Rc<str> = text.into();
+            let len = text.len();
+            let span = styled(Node::new(Span::default(), vec![text.into()]));
+            let window = styled(Node::new(Window::default(), vec![span.clone().into()]));
+            let mut engine = LayoutEngine::with_context(test_font::font_context());
+            let layout = engine.layout(window, size2(200.0, 100.0));
+            let line = layout.text_range_rects(&span.clone().into(), 0, 0..len)[0];
+            let caret = layout.text_range_rects(&span.into(), 0, len..len);
+            assert_eq!(caret.len(), 1);
+            assert_eq!(caret[0].size.width, 0.0);
+            (line, caret[0])
+        };
+
+        // After the last glyph, on the same line.
+        let (line, caret) = caret_at_end("abc");
+        assert_eq!(line.size.width, 3.0 * 8.0);
+        assert_eq!(caret.origin, point2(line.max_x(), line.min_y()));
+
+        // At the start of the line below.
+        let (line, caret) = caret_at_end("abc\n");
+        assert_eq!(caret.origin, point2(line.min_x(), line.max_y()));
+    }
+
+    #[test]
+    fn caret_keeps_its_column_moving_down() {
+        let text: Rc<str> = "abcdefgh\nabc\nabcdefgh".into();
+        let span = styled(Node::new(Span::default(), vec![text.into()]));
+        let window = styled(Node::new(Window::default(), vec![span.clone().into()]));
+        let mut engine = LayoutEngine::with_context(test_font::font_context());
+        let layout = engine.layout(window, size2(400.0, 400.0));
+        let span = span.into();
+        let caret_at = |offset| layout.text_range_rects(&span, 0, offset..offset)[0];
+        let move_caret = |offset, goal_x, direction| {
+            layout.move_caret(&span, 0, caret_at(offset), goal_x, direction)
+        };
+
+        let goal_x = caret_at(6).origin.x;
+        assert_eq!(goal_x, 6.0 * 8.0);
+        // The second line is too short, so the caret stops at its end,
+        // before the line break.
+        assert_eq!(move_caret(6, goal_x, CaretDirection::Down), Some(12));
+        // Then back to the same column on the third line.
+        assert_eq!(move_caret(12, goal_x, CaretDirection::Down), Some(19));
+        assert_eq!(move_caret(19, goal_x, CaretDirection::Up), Some(12));
+
+        assert_eq!(move_caret(6, goal_x, CaretDirection::Up), None);
+        assert_eq!(move_caret(19, goal_x, CaretDirection::Down), None);
+    }
+
+    #[test]
+    fn nowrap_label_is_clipped_to_its_box() {
+        let text: Rc<str> = "A tab title much too long for its tab".into();
+        let span = Node::new(Span::default(), vec![text.into()]);
+        span.computed_values().set(Some(ComputedValues {
+            white_space: WhiteSpace::NoWrap,
+            display: DisplayType::Inline(Default::default()),
+            ..Default::default()
+        }));
+        let tab = Node::new(View::default(), vec![span.into()]);
+        tab.computed_values().set(Some(ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                width: Some(LogicalLength::new(60.0)),
+                overflow_x: Overflow::Hidden,
+                overflow_y: Overflow::Hidden,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }));
+        let window = styled(Node::new(Window::default(), vec![tab.into()]));
+        let mut engine = LayoutEngine::with_context(test_font::font_context());
+
+        let layout = engine.layout(window, size2(400.0, 400.0));
+        let tab = &layout.children[0].layout;
+        assert_eq!(tab.size, size2(60.0, 16.0));
+        let label = &tab.children[0].layout;
+        assert_eq!(label.lines, 1);
+        assert_eq!(label.size.width, 60.0);
+        // 29 letters and 8 spaces, all on the one line.
+        assert_eq!(label.children[0].layout.size.width, 264.0);
+        assert!(label.clip.is_some());
+        // Clipped, but without an ellipsis none of the text was dropped.
+        assert!(!label.truncated);
+    }
+
+    #[test]
+    fn justify_stretches_all_but_last_line() {
+        let text: Rc<str> = "aaaa bbbb cccc dddd eeee ffff gggg hhhh".into();
+        let span = Node::new(Span::default(), vec![text.into()]);
+        span.computed_values().set(Some(ComputedValues {
+            text_align: TextAlign::Justify,
+            ..Span::default().create_computed_values()
+        }));
+        let view = styled(Node::new(View::default(), vec![span.into()]));
+        let window = styled(Node::new(Window::default(), vec![view]));
+        let mut engine = LayoutEngine::with_context(test_font::font_context());
+        let layout = engine.layout(window, size2(200.0, 400.0));
+
+        let lines = &layout.children[0].layout.children[0].layout.children;
+        assert_eq!(lines.len(), 2);
+        let letters = |line: usize| match lines[line].layout.render {
+            RenderData::Text { ref text, .. } => text
+                .fragments
+                .iter()
+                .flat_map(|fragment| &fragment.glyphs)
+                .map(|glyph| lines[line].position.x + glyph.offset.x)
+                .collect::<Vec<_>>(),
+            RenderData::Node(_) => panic!("expected a line of text"),
+        };
+        // Five 32px words and their four 4px spaces take 176px of the
+        // first line, so each space grows by 6px and the last letter
+        // ends at the far edge.
+        let first = letters(0);
+        for word in 0..5 {
+            for letter in 0..4 {
+                let x = (word * 42 + letter * 8) as f32;
+                assert_eq!(first[word * 5 + letter], x);
+            }
+        }
+        assert_eq!(first[23] + 8.0, 200.0);
+        // The last line is left alone.
+        let last = letters(1);
+        assert_eq!(&last[..6], &[0.0, 8.0, 16.0, 24.0, 32.0, 36.0]);
+        assert_eq!(last[10], 72.0);
+    }
+
+    #[test]
+    fn layout_event_reports_window_rect_on_change() {
+        let events = Rc::new(RefCell::new(vec![]));
+        let inner = styled(Node::new(View::default(), vec![]));
+        let recorded = events.clone();
+        View::set_handler(
+            &mut inner.handlers().borrow_mut(),
+            EventHandler::with_func(move |event: &LayoutEvent| {
+                recorded.borrow_mut().push(event.rect)
+            }),
+        );
+        let inner_node: AnyNode = inner.clone().into();
+        let outer = Node::new(View::default(), vec![inner.into()]);
+        outer.computed_values().set(Some(ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                padding: SideOffsets2D::new(5.0, 0.0, 0.0, 7.0),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }));
+        let window = styled(Node::new(
+            Window::default(),
+            vec![styled(Node::new(View::default(), vec![])), outer],
+        ));
+        let mut engine = LayoutEngine::with_context(FontContext::from_families(vec![]));
+
+        engine.layout(window.clone(), size2(100.0, 100.0));
+        assert_eq!(
+            *events.borrow(),
+            vec![LogicalRect::new(point2(7.0, 5.0), size2(0.0, 0.0))]
+        );
+
+        // Same geometry in a taller window, so no new event.
+        engine.layout(window.clone(), size2(100.0, 120.0));
+        assert_eq!(events.borrow().len(), 1);
+
+        // Invalidating forgets the rects too, so they're sent again.
+        engine.invalidate();
+        assert_eq!(engine.node_rect(&inner_node), None);
+        engine.layout(window, size2(100.0, 120.0));
+        assert_eq!(events.borrow().len(), 2);
+    }
+
+    fn boxed(
+        size: LogicalSize,
+        clip: Option<LayoutClip>,
+        children: Vec<LayoutChild>,
+    ) -> EqualRc<LayoutTreeNode> {
+        EqualRc::new(LayoutTreeNode {
+            size,
+            margin: SideOffsets2D::new_all_same(0.0),
+            render: RenderData::Node(Node::new(View::default(), vec![]).into()),
+            children,
+            clip,
+            border_radius: BorderRadius::default(),
+            truncated: false,
+            lines: 0,
+        })
+    }
+
+    #[test]
+    fn flatten_accumulates_positions() {
+        let leaf = LayoutChild {
+            position: point2(5.0, 3.0),
+            layout: boxed(size2(10.0, 10.0), None, vec![]),
+        };
+        let clip = LayoutClip {
+            rect: LogicalRect::new(point2(0.0, 0.0), size2(12.0, 40.0)),
+            radius: BorderRadius::default(),
+        };
+        let middle = LayoutChild {
+            position: point2(20.0, 10.0),
+            layout: boxed(size2(40.0, 40.0), Some(clip), vec![leaf]),
+        };
+        let root = boxed(size2(100.0, 100.0), None, vec![middle]);
+
+        let flat = root.flatten();
+        let rects: Vec<_> = flat.iter().map(|node| node.rect).collect();
+        assert_eq!(
+            rects,
+            vec![
+                LogicalRect::new(point2(0.0, 0.0), size2(100.0, 100.0)),
+                LogicalRect::new(point2(20.0, 10.0), size2(40.0, 40.0)),
+                LogicalRect::new(point2(25.0, 13.0), size2(10.0, 10.0)),
+            ]
+        );
+        assert_eq!(flat[2].order, 2);
+        assert!(flat[1].clip.is_none());
+        assert_eq!(
+            flat[2].clip,
+            Some(LogicalRect::new(point2(20.0, 10.0), size2(12.0, 40.0)))
+        );
+    }
+
+    #[test]
+    fn diff_matches_keyed_nodes_that_moved() {
+        let keyed = |key: &str, y: f32| {
+            let mut view = View::default();
+            <View as HasAttribute<AttrKey>>::set_attribute(&mut view, key.to_owned());
+            LayoutChild {
+                position: point2(0.0, y),
+                layout: EqualRc::new(LayoutTreeNode {
+                    size: size2(10.0, 10.0),
+                    margin: SideOffsets2D::new_all_same(0.0),
+                    render: RenderData::Node(Node::new(view, vec![]).into()),
+                    children: vec![],
+                    clip: None,
+                    border_radius: BorderRadius::default(),
+                    truncated: false,
+                    lines: 0,
+                }),
+            }
+        };
+        let unkeyed = |y: f32| LayoutChild {
+            position: point2(0.0, y),
+            layout: boxed(size2(10.0, 10.0), None, vec![]),
+        };
+        let old = boxed(
+            size2(10.0, 30.0),
+            None,
+            vec![keyed("a", 0.0), keyed("b", 10.0), unkeyed(20.0)],
+        );
+        // "a" and "b" swap places, and the unkeyed node stays at index 2.
+        let new = boxed(
+            size2(10.0, 30.0),
+            None,
+            vec![keyed("b", 0.0), keyed("a", 10.0), unkeyed(25.0)],
+        );
+
+        let at = |y: f32| LogicalRect::new(point2(0.0, y), size2(10.0, 10.0));
+        assert_eq!(
+            old.diff(&new),
+            vec![
+                (NodeIdentity::Key("b".into()), at(10.0), at(0.0)),
+                (NodeIdentity::Key("a".into()), at(0.0), at(10.0)),
+                (NodeIdentity::Index(vec![2]), at(20.0), at(25.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn node_rect_finds_nested_child() {
+        let child = Node::new(View::default(), vec![]);
+        child.computed_values().set(Some(ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                width: Some(LogicalLength::new(30.0)),
+                height: Some(LogicalLength::new(20.0)),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }));
+        let outer = Node::new(View::default(), vec![child.clone().into()]);
+        outer.computed_values().set(Some(ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                padding: SideOffsets2D::new(4.0, 0.0, 0.0, 6.0),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }));
+        let window = styled(Node::new(Window::default(), vec![outer.into()]));
+        let mut engine = LayoutEngine::with_context(FontContext::from_families(vec![]));
+        let tree = engine.layout(window, size2(100.0, 100.0));
+
+        let expected = LogicalRect::new(point2(6.0, 4.0), size2(30.0, 20.0));
+        let child = child.into();
+        assert_eq!(tree.node_rect(&child), Some(expected));
+        assert_eq!(engine.node_rect(&child), Some(expected));
+
+        let detached = Node::new(View::default(), vec![]).into();
+        assert_eq!(tree.node_rect(&detached), None);
+        assert_eq!(engine.node_rect(&detached), None);
+    }
+
+    #[test]
+    fn tree_stats_count_nodes_and_depth() {
+        let at = |x, layout| LayoutChild {
+            position: point2(x, 0.0),
+            layout,
+        };
+        let leaf = || boxed(size2(10.0, 10.0), None, vec![]);
+        let middle = boxed(
+            size2(20.0, 10.0),
+            None,
+            vec![at(0.0, leaf()), at(10.0, leaf())],
+        );
+        let root = boxed(
+            size2(40.0, 10.0),
+            None,
+            vec![at(0.0, middle), at(20.0, leaf())],
+        );
+        assert_eq!(
+            root.stats(),
+            TreeStats {
+                nodes: 5,
+                depth: 3,
+                text_runs: 0,
+            }
+        );
+        assert_eq!(leaf().stats().depth, 1);
+    }
+
+    #[test]
+    fn hit_test_reports_padding_band() {
+        let view = Node::new(View::default(), vec![]);
+        view.computed_values().set(Some(ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                width: Some(LogicalLength::new(100.0)),
+                height: Some(LogicalLength::new(100.0)),
+                padding: SideOffsets2D::new_all_same(10.0),
+                ..Default::default()
+            }),
+            border_thickness: SideOffsets2D::new_all_same(2.0),
+            ..Default::default()
+        }));
+        let window = styled(Node::new(Window::default(), vec![view.clone().into()]));
+        let mut engine = LayoutEngine::with_context(FontContext::from_families(vec![]));
+        let tree = engine.layout(window, size2(200.0, 200.0));
+
+        let view: AnyNode = view.into();
+        let hit = |x, y| tree.hit_test_region(point2(x, y));
+        assert_eq!(hit(1.0, 50.0), Some((view.clone(), Region::Border)));
+        assert_eq!(hit(5.0, 50.0), Some((view.clone(), Region::Padding)));
+        assert_eq!(hit(50.0, 95.0), Some((view.clone(), Region::Padding)));
+        assert_eq!(hit(50.0, 50.0), Some((view.clone(), Region::Content)));
+        assert_ne!(hit(150.0, 150.0).map(|(node, _)| node), Some(view));
+    }
+
+    #[test]
+    fn rotated_square_is_hit_where_drawn() {
+        let square = Node::new(View::default(), vec![]);
+        square.computed_values().set(Some(ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                width: Some(LogicalLength::new(100.0)),
+                height: Some(LogicalLength::new(100.0)),
+                margin: SideOffsets2D::new_all_same(50.0),
+                ..Default::default()
+            }),
+            transform: LogicalTransform::create_rotation(Angle::degrees(45.0)),
+            ..Default::default()
+        }));
+        let window = styled(Node::new(Window::default(), vec![square.clone().into()]));
+        let mut engine = LayoutEngine::with_context(FontContext::from_families(vec![]));
+        let tree = engine.layout(window, size2(200.0, 200.0));
+
+        let square: AnyNode = square.into();
+        let hit = |x, y| tree.hit_test_region(point2(x, y)).map(|(node, _)| node);
+        // The top corner of the diamond pokes out above the unrotated box.
+        assert_eq!(hit(100.0, 35.0), Some(square.clone()));
+        assert_eq!(hit(100.0, 100.0), Some(square.clone()));
+        // The corners of the unrotated box are now empty.
+        assert_ne!(hit(55.0, 55.0), Some(square.clone()));
+
+        let bounds = tree.children[0]
+            .layout
+            .transformed_bounds(tree.children[0].position);
+        let half_diagonal = 50.0 * 2.0f32.sqrt();
+        assert!((bounds.min_x() - (100.0 - half_diagonal)).abs() < 0.01);
+        assert!((bounds.size.height - 2.0 * half_diagonal).abs() < 0.01);
+        assert!(!bounds.contains(point2(25.0, 25.0)));
+        assert!(bounds.contains(point2(35.0, 35.0)));
+    }
+
+    #[test]
+    fn events_reach_a_rotated_node_where_drawn() {
+        let square = Node::new(View::default(), vec![]);
+        square.computed_values().set(Some(ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                width: Some(LogicalLength::new(100.0)),
+                height: Some(LogicalLength::new(100.0)),
+                margin: SideOffsets2D::new_all_same(50.0),
+                ..Default::default()
+            }),
+            transform: LogicalTransform::create_rotation(Angle::degrees(45.0)),
+            ..Default::default()
+        }));
+        let window = styled(Node::new(Window::default(), vec![square.clone().into()]));
+        let mut engine = LayoutEngine::with_context(FontContext::from_families(vec![]));
+        let tree = engine.layout(window.clone(), size2(200.0, 200.0));
+
+        let (square, window): (AnyNode, AnyNode) = (square.into(), window.into());
+        let targets = |point| tree.event_targets(point2(0.0, 0.0), point);
+        assert_eq!(
+            targets(Some(point2(100.0, 35.0))),
+            vec![square.clone(), window.clone()]
+        );
+        assert_eq!(targets(Some(point2(55.0, 55.0))), vec![window.clone()]);
+        assert_eq!(targets(None), vec![square, window]);
+    }
+
+    #[test]
+    fn quarter_turn_stays_in_place_only_about_the_center() {
+        let turned_bounds = |origin: TransformOrigin| {
+            let square = Node::new(View::default(), vec![]);
+            square.computed_values().set(Some(ComputedValues {
+                display: DisplayType::Block(BlockValues {
+                    width: Some(LogicalLength::new(100.0)),
+                    height: Some(LogicalLength::new(100.0)),
+                    margin: SideOffsets2D::new_all_same(50.0),
+                    ..Default::default()
+                }),
+                transform: LogicalTransform::create_rotation(Angle::degrees(90.0)),
+                transform_origin: origin,
+                ..Default::default()
+            }));
+            let window = styled(Node::new(Window::default(), vec![square.into()]));
+            let mut engine = LayoutEngine::with_context(FontContext::from_families(vec![]));
+            let tree = engine.layout(window, size2(200.0, 200.0));
+            let child = &tree.children[0];
+            child.layout.transformed_bounds(child.position)
+        };
+        let near = |a: f32, b: f32| (a - b).abs() < 0.01;
+
+        let centered = turned_bounds(TransformOrigin::default());
+        assert!(near(centered.min_x(), 50.0) && near(centered.min_y(), 50.0));
+        assert!(near(centered.max_x(), 150.0) && near(centered.max_y(), 150.0));
+
+        // Turned about its top left corner, the square swings around that
+        // corner, which stays where it was, and off its own center.
+        let cornered = turned_bounds(TransformOrigin {
+            fraction: (0.0, 0.0),
+            ..Default::default()
+        });
+        assert!(near(cornered.size.width, 100.0) && near(cornered.size.height, 100.0));
+        assert!(near(cornered.min_x(), 50.0) || near(cornered.max_x(), 50.0));
+        assert!(near(cornered.min_y(), 50.0) || near(cornered.max_y(), 50.0));
+        assert!(!near(cornered.center().x, 100.0) || !near(cornered.center().y, 100.0));
+    }
+
+    #[test]
+    fn columns_are_as_wide_as_their_widest_cell() {
+        let row = |widths: &[f32]| -> AnyNode {
+            let cells = widths.iter().map(|width| sized(*width).into()).collect();
+            styled(Node::new(View::default(), cells)).into()
+        };
+        let rows = vec![
+            row(&[10.0, 40.0, 5.0]),
+            row(&[30.0, 20.0, 5.0]),
+            row(&[20.0, 10.0]),
+        ];
+        let mut engine = LayoutEngine::with_context(FontContext::from_families(vec![]));
+        let widths = engine.column_widths(&rows);
+        assert_eq!(widths, vec![30.0, 40.0, 5.0]);
+
+        let layout = engine.layout_row(rows[2].clone(), &widths);
+        let positions: Vec<_> = layout
+            .children
+            .iter()
+            .map(|child| child.position.x)
+            .collect();
+        assert_eq!(positions, vec![0.0, 30.0]);
+        assert_eq!(layout.size, size2(75.0, 10.0));
+        assert_eq!(layout.children[0].layout.size.width, 20.0);
+    }
+
+    #[test]
+    fn cells_fit_in_their_columns_with_their_margins() {
+        let text_cell = |text: &str, margin: f32| -> AnyNode {
+            let span = styled(Node::new(
+                Span::default(),
+                vec![Rc::<str>::from(text).into()],
+            ));
+            let cell = Node::new(View::default(), vec![span.into()]);
+            cell.computed_values().set(Some(ComputedValues {
+                display: DisplayType::Block(BlockValues {
+                    margin: SideOffsets2D::new(0.0, margin, 0.0, margin),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }));
+            cell.into()
+        };
+        let cells = vec![text_cell("aa aa", 5.0), text_cell("b", 0.0)];
+        let row: AnyNode = styled(Node::new(View::default(), cells.clone())).into();
+        let mut engine = LayoutEngine::with_context(test_font::font_context());
+        let layout = engine.layout_row(row.clone(), &[40.0, 20.0]);
+
+        // Without its margins the first cell has 30 of its column's 40,
+        // so its two words go on two lines.
+        let first = &layout.children[0];
+        assert_eq!(first.position.x, 5.0);
+        assert!(first.layout.size.width <= 30.0);
+        assert_eq!(layout.size.height, 32.0);
+        assert_eq!(layout.children[1].position.x, 40.0);
+
+        // The stats count both cells, not just the last one.
+        let mut fresh = LayoutEngine::with_context(test_font::font_context());
+        fresh.layout_fragment(cells[0].clone(), size2(30.0, UNBOUNDED));
+        let mut expected = fresh.stats();
+        fresh.layout_fragment(cells[1].clone(), size2(20.0, UNBOUNDED));
+        expected += fresh.stats();
+        assert_eq!(engine.stats(), expected);
+
+        // Unwrapped, "aa aa" is 36 wide, and its margins add 10.
+        assert_eq!(engine.column_widths(&[row]), vec![46.0, 8.0]);
+    }
+
+    /// Puts each child below and to the right of the one before it.
+    struct Diagonal;
+
+    impl CustomLayout for Diagonal {
+        fn layout(
+            &self,
+            children: &[EqualRc<LayoutTreeNode>],
+            _max_size: LogicalSize,
+        ) -> (LogicalSize, Vec<LayoutChild>) {
+            let mut corner = point2(0.0, 0.0);
+            let placed = children
+                .iter()
+                .map(|child| {
+                    let position = corner;
+                    corner += child.size.to_vector();
+                    LayoutChild {
+                        position,
+                        layout: child.clone(),
+                    }
+                })
+                .collect();
+            (size2(corner.x, corner.y), placed)
+        }
+    }
+
+    static DIAGONAL: Diagonal = Diagonal;
+
+    #[test]
+    fn custom_layout_places_children_on_a_diagonal() {
+        let children = vec![sized(10.0).into(), sized(20.0).into(), sized(30.0).into()];
+        let list = Node::new(View::default(), children);
+        list.computed_values().set(Some(ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                padding: SideOffsets2D::new_all_same(5.0),
+                custom_layout: Some(CustomLayoutRef(&DIAGONAL)),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }));
+        let window = styled(Node::new(Window::default(), vec![list.into()]));
+        let mut engine = LayoutEngine::with_context(FontContext::from_families(vec![]));
+        let layout = engine.layout(window, size2(200.0, 200.0));
+
+        let list = &layout.children[0].layout;
+        let positions: Vec<_> = list.children.iter().map(|child| child.position).collect();
+        assert_eq!(
+            positions,
+            vec![point2(5.0, 5.0), point2(15.0, 15.0), point2(35.0, 25.0)]
+        );
+        assert_eq!(list.size, size2(70.0, 40.0));
+    }
+
+    /// Columns 100 and 200 wide, filled a row at a time.
+    struct TwoColumns;
+
+    const COLUMNS: [f32; 2] = [100.0, 200.0];
+
+    impl CustomLayout for TwoColumns {
+        fn cells(&self, sizes: &[LogicalSize], _max_size: LogicalSize) -> Option<Vec<LogicalSize>> {
+            let cell = |index: usize| size2(COLUMNS[index % COLUMNS.len()], UNBOUNDED);
+            Some((0..sizes.len()).map(cell).collect())
+        }
+
+        fn layout(
+            &self,
+            children: &[EqualRc<LayoutTreeNode>],
+            _max_size: LogicalSize,
+        ) -> (LogicalSize, Vec<LayoutChild>) {
+            let mut placed = vec![];
+            let mut y = 0.0;
+            for row in children.chunks(COLUMNS.len()) {
+                let (mut x, mut height) = (0.0, 0.0f32);
+                for (child, width) in row.iter().zip(&COLUMNS) {
+                    placed.push(LayoutChild {
+                        position: point2(x, y),
+                        layout: child.clone(),
+                    });
+                    x += width;
+                    height = height.max(child.size.height);
+                }
+                y += height;
+            }
+            (size2(COLUMNS.iter().sum(), y), placed)
+        }
+    }
+
+    static TWO_COLUMNS: TwoColumns = TwoColumns;
+
+    fn grid_window(children: Vec<AnyNode>) -> Node<Window> {
+        let grid = Node::new(View::default(), children);
+        grid.computed_values().set(Some(ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                custom_layout: Some(CustomLayoutRef(&TWO_COLUMNS)),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }));
+        styled(Node::new(Window::default(), vec![grid.into()]))
+    }
+
+    #[test]
+    fn children_are_laid_out_in_their_cells() {
+        // Squares as wide as the space they're given.
+        let square = || {
+            let node = Node::new(View::default(), vec![]);
+            node.computed_values().set(Some(ComputedValues {
+                display: DisplayType::Block(BlockValues {
+                    aspect_ratio: Some(1.0),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }));
+            node.into()
+        };
+        let mut engine = LayoutEngine::with_context(FontContext::from_families(vec![]));
+        let layout = engine.layout(grid_window(vec![square(), square()]), size2(300.0, 400.0));
+        let grid = &layout.children[0].layout;
+        let sizes: Vec<_> = grid
+            .children
+            .iter()
+            .map(|child| child.layout.size)
+            .collect();
+        assert_eq!(sizes, vec![size2(100.0, 100.0), size2(200.0, 200.0)]);
+        assert_eq!(grid.size, size2(300.0, 200.0));
+
+        // Measuring the squares doesn't push their layouts in their cells
+        // out of the cache, so the next frame lays out nothing.
+        let window = grid_window(vec![square(), square()]);
+        let mut engine = LayoutEngine::with_context(FontContext::from_families(vec![]));
+        engine.layout(window.clone(), size2(300.0, 400.0));
+        engine.layout(window, size2(300.0, 400.0));
+        assert_eq!(engine.stats().boxes, 0);
+    }
+
+    #[test]
+    #[ignore] // needs system fonts
+    fn text_wraps_at_the_width_of_its_cell() {
+        let text: Rc<str> = "a few words that will not fit on one line of a narrow cell".into();
+        let span = styled(Node::new(Span::default(), vec![text.into()]));
+        let mut engine = LayoutEngine::new();
+        let layout = engine.layout(grid_window(vec![span.into()]), size2(300.0, 400.0));
+        let cell = &layout.children[0].layout.children[0].layout;
+        assert!(cell.size.width <= 100.0);
+        assert!(cell.lines > 1);
+    }
+
+    #[test]
+    fn natural_size_keeps_a_label_on_one_line() {
+        let text: Rc<str> = "a label of several words".into();
+        let span = styled(Node::new(Span::default(), vec![text.into()]));
+        let label: AnyNode = styled(Node::new(View::default(), vec![span.into()])).into();
+        let mut engine = LayoutEngine::with_context(test_font::font_context());
+        let natural = engine.natural_size(label.clone());
+        // 20 letters and 4 spaces.
+        assert_eq!(natural.width, 20.0 * 8.0 + 4.0 * 4.0);
+        let line = engine.layout_fragment(label.clone(), size2(UNBOUNDED, UNBOUNDED));
+        assert_eq!(line.children[0].layout.lines, 1);
+
+        // Wrapped to fit a narrow box, the label gets narrower and taller.
+        let wrapped = engine.layout_fragment(label, size2(40.0, UNBOUNDED));
+        assert!(wrapped.size.width < natural.width);
+        assert!(wrapped.size.height > natural.height);
+    }
+
+    #[test]
+    fn small_labels_grow_to_the_min_text_size() {
+        let label = |size: f32| -> AnyNode {
+            let text: Rc<str> = "small print".into();
+            let span = styled(Node::new(Span::default(), vec![text.into()]));
+            let values = span.computed_values().get().unwrap();
+            span.computed_values().set(Some(ComputedValues {
+                text_size: LogicalLength::new(size),
+                ..values
+            }));
+            styled(Node::new(View::default(), vec![span.into()])).into()
+        };
+        let (small, readable) = (label(8.0), label(12.0));
+        let mut engine = LayoutEngine::with_context(test_font::font_context());
+        let unclamped = engine.natural_size(small.clone());
+        let expected = engine.natural_size(readable.clone());
+        assert_eq!(unclamped.height, 8.0);
+        assert_eq!(expected.height, 12.0);
+
+        // Changing the minimum lays the same nodes out again.
+        engine.set_min_text_size(Some(LogicalLength::new(12.0)));
+        assert_eq!(engine.natural_size(small), expected);
+        assert_eq!(engine.natural_size(readable), expected);
+    }
+
+    #[test]
+    fn baselines_of_paragraphs_are_evenly_spaced() {
+        let paragraph = |text: &str| -> AnyNode {
+            let text: Rc<str> = text.into();
+            styled(Node::new(Span::default(), vec![text.into()])).into()
+        };
+        let list = Node::new(
+            View::default(),
+            vec![paragraph("First paragraph"), paragraph("Second paragraph")],
+        );
+        list.computed_values().set(Some(ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                baseline_spacing: Some(LogicalLength::new(28.0)),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }));
+        let window = styled(Node::new(Window::default(), vec![list.into()]));
+        let mut engine = LayoutEngine::with_context(test_font::font_context());
+        let layout = engine.layout(window, size2(400.0, 400.0));
+
+        let list = &layout.children[0].layout;
+        let (first, second) = (&list.children[0], &list.children[1]);
+        let first_baseline = first.position.y + first.layout.last_baseline().unwrap();
+        assert!((first_baseline - 16.0 * test_font::ASCENT).abs() < 0.01);
+        let second_baseline = second.position.y + second.layout.first_baseline().unwrap();
+        assert!((second_baseline - first_baseline - 28.0).abs() < 0.01);
+        assert_eq!(list.last_baseline(), Some(second_baseline));
+    }
+
+    #[test]
+    fn oversized_inline_child_shrinks_to_the_line() {
+        let inline_window = |child_overflow| {
+            let image = Node::new(View::default(), vec![]);
+            image.computed_values().set(Some(ComputedValues {
+                display: DisplayType::Block(BlockValues {
+                    width: Some(LogicalLength::new(300.0)),
+                    aspect_ratio: Some(2.0),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }));
+            let span = Node::new(Span::default(), vec![image.into()]);
+            span.computed_values().set(Some(ComputedValues {
+                display: DisplayType::Inline(InlineValues {
+                    child_overflow,
+                    ..Default::default()
+                }),
+                ..Span::default().create_computed_values()
+            }));
+            styled(Node::new(Window::default(), vec![span.into()]))
+        };
+        let mut engine = LayoutEngine::with_context(FontContext::from_families(vec![]));
+        let image_size = |tree: &LayoutTreeNode| tree.children[0].layout.children[0].layout.size;
+
+        let tree = engine.layout(inline_window(ChildOverflow::Visible), size2(200.0, 200.0));
+        assert_eq!(image_size(&tree), size2(300.0, 150.0));
+        assert!(tree.children[0].layout.clip.is_none());
+
+        let tree = engine.layout(inline_window(ChildOverflow::Clip), size2(200.0, 200.0));
+        assert_eq!(image_size(&tree), size2(300.0, 150.0));
+        assert_eq!(
+            tree.children[0]
+                .layout
+                .clip
+                .as_ref()
+                .map(|clip| clip.rect.size.width),
+            Some(200.0)
+        );
+
+        let tree = engine.layout(inline_window(ChildOverflow::Shrink), size2(200.0, 200.0));
+        assert_eq!(image_size(&tree), size2(200.0, 100.0));
+    }
+
+    #[test]
+    fn touch_target_grows_hit_area_only() {
+        let button = Node::new(View::default(), vec![]);
+        button.computed_values().set(Some(ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                width: Some(LogicalLength::new(20.0)),
+                height: Some(LogicalLength::new(20.0)),
+                margin: SideOffsets2D::new_all_same(50.0),
+                ..Default::default()
+            }),
+            min_touch_target: Some(size2(44.0, 44.0)),
+            ..Default::default()
+        }));
+        let window = styled(Node::new(Window::default(), vec![button.clone().into()]));
+        let mut engine = LayoutEngine::with_context(FontContext::from_families(vec![]));
+        let tree = engine.layout(window, size2(200.0, 200.0));
+
+        let button: AnyNode = button.into();
+        let rect = tree.node_rect(&button).unwrap();
+        assert_eq!(
+            rect,
+            LogicalRect::new(point2(50.0, 50.0), size2(20.0, 20.0))
+        );
+        let layout = &tree.children[0].layout;
+        assert_eq!(
+            layout.hit_area(rect.origin),
+            LogicalRect::new(point2(38.0, 38.0), size2(44.0, 44.0))
+        );
+        let hit = |x, y| tree.hit_test_region(point2(x, y)).map(|(node, _)| node);
+        assert_eq!(hit(39.0, 81.0), Some(button.clone()));
+        assert_eq!(hit(60.0, 60.0), Some(button.clone()));
+        assert_ne!(hit(37.0, 60.0), Some(button));
     }
 }
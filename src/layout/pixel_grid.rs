@@ -0,0 +1,115 @@
+//! Snapping of logical lengths to the device pixel grid. Positions and
+//! sizes round in different directions: a position moves back to the
+//! pixel it starts in, while a size grows to cover the last pixel it
+//! touches, so that content is never cut off by rounding.
+
+use super::{LayoutChild, LogicalPoint, LogicalRect, LogicalSize};
+use euclid::{point2, size2};
+
+/// How far off a pixel boundary a value can be, in device pixels, and
+/// still count as on it. Lengths summed up in layout pick up float
+/// error that would otherwise push them into the next pixel.
+const EPSILON: f32 = 1.0 / 256.0;
+
+/// Device pixels per logical pixel that layout snaps boxes to, see
+/// `LayoutEngine::set_pixel_snapping`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) struct PixelSnapping(pub Option<f32>);
+
+/// Rounds a position down to the device pixel it falls in, where
+/// `scale` is the number of device pixels per logical pixel.
+pub fn round_pos(value: f32, scale: f32) -> f32 {
+    (value * scale + EPSILON).floor() / scale
+}
+
+/// Rounds a size up to a whole number of device pixels.
+pub fn round_size(value: f32, scale: f32) -> f32 {
+    (value * scale - EPSILON).ceil() / scale
+}
+
+/// Snaps the span from `start` to `end`, moving the start back and the
+/// end out to the pixels they fall in.
+pub fn round_edges(start: f32, end: f32, scale: f32) -> (f32, f32) {
+    (round_pos(start, scale), round_size(end, scale))
+}
+
+/// Snaps a rect to device pixels, moving its origin back and its far
+/// edges out so that it covers every pixel the unsnapped rect touches.
+pub fn round_rect(rect: LogicalRect, scale: f32) -> LogicalRect {
+    let (left, right) = round_edges(rect.min_x(), rect.max_x(), scale);
+    let (top, bottom) = round_edges(rect.min_y(), rect.max_y(), scale);
+    let origin: LogicalPoint = point2(left, top);
+    LogicalRect::new(origin, size2(right - left, bottom - top))
+}
+
+/// The scale layout snaps to, if the engine running it asked for one.
+fn layout_scale() -> Option<f32> {
+    illicit::Env::get::<PixelSnapping>().and_then(|snapping| snapping.0)
+}
+
+/// Rounds the size of a box being laid out up to whole device pixels.
+pub(super) fn snap_size(size: LogicalSize) -> LogicalSize {
+    match layout_scale() {
+        Some(scale) => size2(
+            round_size(size.width, scale),
+            round_size(size.height, scale),
+        ),
+        None => size,
+    }
+}
+
+/// Moves the children of a box being laid out back onto the device
+/// pixels they start in. Their sizes were snapped when they were laid
+/// out, so both of their edges end up on the grid.
+pub(super) fn snap_positions(children: &mut [LayoutChild]) {
+    if let Some(scale) = layout_scale() {
+        for child in children {
+            child.position = point2(
+                round_pos(child.position.x, scale),
+                round_pos(child.position.y, scale),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{round_edges, round_pos, round_rect, round_size};
+    use crate::layout::LogicalRect;
+    use euclid::{point2, size2};
+
+    #[test]
+    fn values_on_the_grid_are_kept() {
+        assert_eq!(round_pos(10.5, 2.0), 10.5);
+        assert_eq!(round_size(10.5, 2.0), 10.5);
+        assert_eq!(round_pos(10.5, 1.0), 10.0);
+        assert_eq!(round_size(10.5, 1.0), 11.0);
+    }
+
+    #[test]
+    fn positions_floor_and_sizes_ceil() {
+        assert_eq!(round_pos(10.3, 2.0), 10.0);
+        assert_eq!(round_size(10.3, 2.0), 10.5);
+        assert_eq!(round_pos(10.75, 2.0), 10.5);
+        assert_eq!(round_size(10.75, 2.0), 11.0);
+    }
+
+    #[test]
+    fn float_error_stays_on_the_pixel() {
+        assert_eq!(round_pos(10.5 - 1e-5, 2.0), 10.5);
+        assert_eq!(round_size(10.5 + 1e-5, 2.0), 10.5);
+        // Ten tenths add up to a little over one.
+        let sum = (0..10).map(|_| 0.1).sum::<f32>();
+        assert!(sum > 1.0);
+        assert_eq!(round_edges(1.0 - sum, sum, 1.0), (0.0, 1.0));
+    }
+
+    #[test]
+    fn rect_covers_its_last_pixel() {
+        let rect = LogicalRect::new(point2(0.5, 0.0), size2(1.0, 10.25));
+        assert_eq!(
+            round_rect(rect, 1.0),
+            LogicalRect::new(point2(0.0, 0.0), size2(2.0, 11.0))
+        );
+    }
+}
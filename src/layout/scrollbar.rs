@@ -0,0 +1,94 @@
+use super::{LogicalRect, LogicalSideOffsets, LogicalSize, LogicalVector};
+use euclid::{point2, size2};
+
+/// Where a scrollbar goes, relative to its scroll container. The thumb
+/// is as long, compared to the track, as the visible part of the
+/// content is compared to all of it, and moves along the track as the
+/// content is scrolled.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Scrollbar {
+    pub track: LogicalRect,
+    pub thumb: LogicalRect,
+}
+
+/// The scrollbars of a scroll container, see
+/// `LayoutTreeNode::scrollbars`. An axis without anything to scroll has
+/// none.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Scrollbars {
+    pub horizontal: Option<Scrollbar>,
+    pub vertical: Option<Scrollbar>,
+}
+
+/// Where the thumb starts along a track and how long it is, for a box
+/// of `visible` length showing `content` scrolled by `offset`.
+fn thumb_span(track: f32, visible: f32, content: f32, offset: f32) -> (f32, f32) {
+    let length = track * (visible / content).min(1.0);
+    let extent = content - visible;
+    let progress = (offset / extent).max(0.0).min(1.0);
+    ((track - length) * progress, length)
+}
+
+/// Lays out the scrollbars of a box of `size` inside of its `border`,
+/// each `thickness` wide, along the bottom and right edges, for the
+/// axes in `scrolls` that have content to scroll to. When both are
+/// shown, they stop short of the corner they share.
+pub(super) fn scrollbars(
+    size: LogicalSize,
+    content: LogicalSize,
+    border: LogicalSideOffsets,
+    offset: LogicalVector,
+    thickness: f32,
+    scrolls: (bool, bool),
+) -> Scrollbars {
+    let inner = LogicalRect::new(
+        point2(border.left, border.top),
+        size - size2(border.horizontal(), border.vertical()),
+    );
+    let scrolls_x = scrolls.0 && content.width > size.width;
+    let scrolls_y = scrolls.1 && content.height > size.height;
+    let corner = if scrolls_x && scrolls_y {
+        thickness
+    } else {
+        0.0
+    };
+
+    let horizontal = if scrolls_x {
+        let track = LogicalRect::new(
+            point2(inner.min_x(), inner.max_y() - thickness),
+            size2((inner.size.width - corner).max(0.0), thickness),
+        );
+        let (start, length) = thumb_span(track.size.width, size.width, content.width, offset.x);
+        Some(Scrollbar {
+            track,
+            thumb: LogicalRect::new(
+                point2(track.min_x() + start, track.min_y()),
+                size2(length, thickness),
+            ),
+        })
+    } else {
+        None
+    };
+
+    let vertical = if scrolls_y {
+        let track = LogicalRect::new(
+            point2(inner.max_x() - thickness, inner.min_y()),
+            size2(thickness, (inner.size.height - corner).max(0.0)),
+        );
+        let (start, length) = thumb_span(track.size.height, size.height, content.height, offset.y);
+        Some(Scrollbar {
+            track,
+            thumb: LogicalRect::new(
+                point2(track.min_x(), track.min_y() + start),
+                size2(thickness, length),
+            ),
+        })
+    } else {
+        None
+    };
+
+    Scrollbars {
+        horizontal,
+        vertical,
+    }
+}
@@ -0,0 +1,181 @@
+//! Text shaped a paragraph at a time. Shaping is the slow part of laying
+//! out text, and a long text, like a document held in a single text
+//! node, would otherwise be shaped again in full for every character
+//! typed into it. Instead it's split after each line break and each
+//! paragraph is shaped on its own, and those that didn't change are
+//! found again in the font context's `ParagraphCache`.
+
+use super::text::{glyph_advance, TextOptions};
+use super::SharedCollection;
+use crate::util::keyed_cache::KeyedCache;
+use euclid::default::Vector2D;
+use euclid::vec2;
+use skribo::{FontRef, LayoutSession, TextStyle};
+use std::cell::RefCell;
+use std::ops::Range;
+use std::rc::Rc;
+
+/// How many paragraphs a font context keeps shaped, across all texts.
+pub(super) const PARAGRAPH_CACHE_CAPACITY: usize = 1024;
+
+/// A paragraph is shaped the same wherever it appears, as long as it's
+/// set in the same size and fonts.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub(super) struct ParagraphKey {
+    text: Rc<str>,
+    size: u32,
+    font_stack: Option<&'static [&'static str]>,
+    font_fallback: bool,
+}
+
+pub(super) type ParagraphCache = KeyedCache<ParagraphKey>;
+
+type Session = Rc<RefCell<LayoutSession<Rc<str>>>>;
+
+pub(super) struct ShapedGlyph {
+    pub glyph_id: u32,
+    pub offset: Vector2D<f32>,
+}
+
+/// Glyphs in a single font, like skribo's runs but positioned relative
+/// to the start of the whole text.
+pub(super) struct ShapedRun {
+    font: FontRef,
+    glyphs: Vec<ShapedGlyph>,
+}
+
+impl ShapedRun {
+    pub fn font(&self) -> &FontRef {
+        &self.font
+    }
+
+    pub fn glyphs(&self) -> impl Iterator<Item = &ShapedGlyph> {
+        self.glyphs.iter()
+    }
+}
+
+pub(super) struct ShapedText {
+    size: f32,
+    len: usize,
+    /// Each paragraph with the byte offset it starts at in the text.
+    paragraphs: Vec<(usize, Session)>,
+}
+
+impl ShapedText {
+    /// Shapes `text` in one piece.
+    pub fn new(text: Rc<str>, size: f32, collection: &SharedCollection) -> ShapedText {
+        let len = text.len();
+        let session = LayoutSession::create(text, &TextStyle { size }, &collection.borrow());
+        ShapedText {
+            size,
+            len,
+            paragraphs: vec![(0, Rc::new(RefCell::new(session)))],
+        }
+    }
+
+    /// Shapes `text` a paragraph at a time, taking the paragraphs that
+    /// were shaped before from `cache`. Text without line breaks is
+    /// shaped in one piece and isn't cached.
+    pub fn by_paragraph(
+        text: Rc<str>,
+        options: &TextOptions,
+        collection: &SharedCollection,
+        cache: &ParagraphCache,
+    ) -> ShapedText {
+        let starts = paragraph_starts(&text);
+        if starts.len() < 2 {
+            return ShapedText::new(text, options.size, collection);
+        }
+        let style = TextStyle { size: options.size };
+        let paragraphs = starts
+            .iter()
+            .enumerate()
+            .map(|(index, &start)| {
+                let end = starts.get(index + 1).cloned().unwrap_or_else(|| text.len());
+                let paragraph: Rc<str> = text[start..end].into();
+                let key = ParagraphKey {
+                    text: paragraph.clone(),
+                    size: options.size.to_bits(),
+                    font_stack: options.font_stack,
+                    font_fallback: options.font_fallback,
+                };
+                let session = cache.memo(key, (), |_| {
+                    let session = LayoutSession::create(paragraph, &style, &collection.borrow());
+                    Rc::new(RefCell::new(session))
+                });
+                (start, session)
+            })
+            .collect();
+        ShapedText {
+            size: options.size,
+            len: text.len(),
+            paragraphs,
+        }
+    }
+
+    pub fn style(&self) -> TextStyle {
+        TextStyle { size: self.size }
+    }
+
+    /// The runs of glyphs in a byte range of the text, placed as if it
+    /// had been shaped in one piece: each paragraph starts where the
+    /// glyphs of the one before it end.
+    pub fn iter_substr(&mut self, range: Range<usize>) -> Vec<ShapedRun> {
+        let mut runs = vec![];
+        let mut x = 0.0f32;
+        for (index, (start, session)) in self.paragraphs.iter().enumerate() {
+            let end = self
+                .paragraphs
+                .get(index + 1)
+                .map_or(self.len, |(next, _)| *next);
+            let (from, to) = (range.start.max(*start), range.end.min(end));
+            if from >= to {
+                continue;
+            }
+            let mut width = 0.0f32;
+            for run in session.borrow_mut().iter_substr(from - start..to - start) {
+                let font = run.font().to_owned();
+                let units_per_px = font.font.metrics().units_per_em as f32 / self.size;
+                let glyphs = run
+                    .glyphs()
+                    .map(|glyph| {
+                        let advance = glyph_advance(&font, glyph.glyph_id) / units_per_px;
+                        width = width.max(glyph.offset.x + advance);
+                        ShapedGlyph {
+                            glyph_id: glyph.glyph_id,
+                            offset: vec2(glyph.offset.x + x, glyph.offset.y),
+                        }
+                    })
+                    .collect();
+                runs.push(ShapedRun { font, glyphs });
+            }
+            x += width;
+        }
+        runs
+    }
+}
+
+/// The byte offsets paragraphs start at: the start of the text and the
+/// end of every line break that isn't the last character.
+fn paragraph_starts(text: &str) -> Vec<usize> {
+    std::iter::once(0)
+        .chain(
+            text.match_indices('\n')
+                .map(|(index, _)| index + 1)
+                .filter(|&start| start < text.len()),
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::paragraph_starts;
+
+    #[test]
+    fn paragraphs_start_after_line_breaks() {
+        assert_eq!(paragraph_starts("one line"), vec![0]);
+        assert_eq!(paragraph_starts("one\ntwo\n\nfour"), vec![0, 4, 8, 9]);
+        assert_eq!(paragraph_starts("trailing\n"), vec![0]);
+        assert_eq!(paragraph_starts(""), vec![0]);
+    }
+}
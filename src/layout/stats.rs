@@ -0,0 +1,53 @@
+//! Counts of the work done by a layout, so that tests can tell a
+//! layout that was reused from one that was computed again with the
+//! same result.
+
+use std::cell::Cell;
+use std::ops::AddAssign;
+
+/// How many times each step of layout ran during one call to
+/// `LayoutEngine::layout` or `layout_fragment`, or to `column_widths`
+/// or `layout_row` for all of their cells. Steps served from the cache
+/// aren't counted, so laying out an unchanged tree again counts
+/// nothing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LayoutStats {
+    /// Blocks and runs of inline content arranged from their children.
+    pub boxes: u32,
+    /// Lines filled with text.
+    pub lines: u32,
+    /// Texts shaped, which a `TextLayoutCache` can spare even when the
+    /// layouts around them weren't cached.
+    pub texts: u32,
+}
+
+impl AddAssign for LayoutStats {
+    fn add_assign(&mut self, other: LayoutStats) {
+        self.boxes += other.boxes;
+        self.lines += other.lines;
+        self.texts += other.texts;
+    }
+}
+
+thread_local! {
+    // Layout never leaves the thread it was started on, so the counts
+    // don't have to be threaded through every layout function.
+    static STATS: Cell<LayoutStats> = Cell::new(LayoutStats::default());
+}
+
+/// Adds to the counts of the layout running on this thread.
+pub(super) fn count(update: impl FnOnce(&mut LayoutStats)) {
+    STATS.with(|stats| {
+        let mut counts = stats.get();
+        update(&mut counts);
+        stats.set(counts);
+    });
+}
+
+/// Runs a layout, returning what it counted along with its result.
+pub(super) fn collect<T>(run: impl FnOnce() -> T) -> (T, LayoutStats) {
+    let outer = STATS.with(|stats| stats.replace(LayoutStats::default()));
+    let result = run();
+    let counts = STATS.with(|stats| stats.replace(outer));
+    (result, counts)
+}
@@ -0,0 +1,288 @@
+//! A tiny TrueType font built in memory, so that text layout can be
+//! tested without relying on the fonts installed on the machine. Every
+//! printable ASCII character is a box half an em wide, spaces and tabs
+//! are a quarter em, and line breaks take up no room. Lines are an em
+//! tall with the baseline 0.8 em from the top, and anything outside
+//! ASCII is missing.
+
+use super::FontContext;
+use font_kit::font::Font;
+use skribo::FontFamily;
+use std::sync::Arc;
+
+/// Advance of the box glyph, in ems.
+pub const CHAR_WIDTH: f32 = 0.5;
+/// Advance of a space or tab, in ems.
+pub const SPACE_WIDTH: f32 = 0.25;
+/// Distance from the top of a line to its baseline, in ems.
+pub const ASCENT: f32 = 0.8;
+
+/// A font context with the test font as its only family.
+pub fn font_context() -> FontContext {
+    FontContext::from_families(vec![FontFamily::new_from_font(font())])
+}
+
+/// The test font.
+pub fn font() -> Font {
+    Font::from_bytes(Arc::new(font_bytes()), 0).unwrap()
+}
+
+/// Units per em of the test font.
+const UNITS_PER_EM: u16 = 1000;
+const ASCENDER: i16 = 800;
+const DESCENDER: i16 = -200;
+
+/// The glyphs of the font: the missing glyph, a blank quarter em for
+/// spaces and tabs, the box every other printable character is drawn
+/// as, and an empty glyph without advance for line breaks.
+const GLYPHS: [(u16, Option<[i16; 4]>); 4] = [
+    (500, Some([50, 0, 450, 700])),
+    (250, None),
+    (500, Some([50, 0, 450, 700])),
+    (0, None),
+];
+
+/// Ranges of characters and the glyph drawn for all of them.
+const CHARACTERS: [(u16, u16, u16); 4] = [
+    (0x09, 0x09, 1),
+    (0x0A, 0x0A, 3),
+    (0x20, 0x20, 1),
+    (0x21, 0x7E, 2),
+];
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u16(&mut self, value: u16) -> &mut Self {
+        self.0.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    fn i16(&mut self, value: i16) -> &mut Self {
+        self.u16(value as u16)
+    }
+
+    fn u32(&mut self, value: u32) -> &mut Self {
+        self.0.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+}
+
+fn table(build: impl FnOnce(&mut Writer)) -> Vec<u8> {
+    let mut writer = Writer(vec![]);
+    build(&mut writer);
+    writer.0
+}
+
+fn head() -> Vec<u8> {
+    table(|w| {
+        w.u32(0x0001_0000).u32(0x0001_0000).u32(0).u32(0x5F0F_3CF5);
+        w.u16(0x000B).u16(UNITS_PER_EM);
+        w.u32(0).u32(0).u32(0).u32(0);
+        w.i16(0).i16(DESCENDER).i16(500).i16(ASCENDER);
+        w.u16(0).u16(8).i16(2).i16(0).i16(0);
+    })
+}
+
+fn hhea() -> Vec<u8> {
+    table(|w| {
+        w.u32(0x0001_0000).i16(ASCENDER).i16(DESCENDER).i16(0);
+        w.u16(500).i16(0).i16(0).i16(450);
+        w.i16(1).i16(0).i16(0);
+        w.i16(0).i16(0).i16(0).i16(0);
+        w.i16(0).u16(GLYPHS.len() as u16);
+    })
+}
+
+fn maxp() -> Vec<u8> {
+    table(|w| {
+        w.u32(0x0001_0000).u16(GLYPHS.len() as u16);
+        w.u16(4).u16(1).u16(0).u16(0).u16(2);
+        for _ in 0..8 {
+            w.u16(0);
+        }
+    })
+}
+
+fn os2() -> Vec<u8> {
+    table(|w| {
+        w.u16(4).i16(500).u16(400).u16(5).u16(0);
+        for _ in 0..10 {
+            w.i16(0);
+        }
+        w.i16(0);
+        w.0.extend_from_slice(&[0; 10]);
+        w.u32(1).u32(0).u32(0).u32(0);
+        w.0.extend_from_slice(b"TEST");
+        w.u16(0x0040).u16(0x09).u16(0x7E);
+        w.i16(ASCENDER).i16(DESCENDER).i16(0);
+        w.u16(ASCENDER as u16).u16(-DESCENDER as u16);
+        w.u32(1).u32(0);
+        w.i16(500).i16(700).u16(0).u16(0x20).u16(0);
+    })
+}
+
+fn hmtx() -> Vec<u8> {
+    table(|w| {
+        for &(advance, outline) in &GLYPHS {
+            w.u16(advance).i16(outline.map_or(0, |rect| rect[0]));
+        }
+    })
+}
+
+/// The outlines, each a rectangle, and the short offsets of each in
+/// the `glyf` table.
+fn glyf_and_loca() -> (Vec<u8>, Vec<u8>) {
+    let mut glyf = Writer(vec![]);
+    let mut loca = Writer(vec![]);
+    for &(_, outline) in &GLYPHS {
+        loca.u16((glyf.0.len() / 2) as u16);
+        if let Some([x_min, y_min, x_max, y_max]) = outline {
+            glyf.i16(1).i16(x_min).i16(y_min).i16(x_max).i16(y_max);
+            glyf.u16(3).u16(0);
+            glyf.0.extend_from_slice(&[1, 1, 1, 1]);
+            glyf.i16(x_min).i16(0).i16(x_max - x_min).i16(0);
+            glyf.i16(y_min).i16(y_max - y_min).i16(0).i16(y_min - y_max);
+        }
+    }
+    loca.u16((glyf.0.len() / 2) as u16);
+    (glyf.0, loca.0)
+}
+
+fn cmap() -> Vec<u8> {
+    // Each range looks its glyphs up in the array after the segments,
+    // with a last segment mapping U+FFFF to the missing glyph as the
+    // format requires.
+    let count = CHARACTERS.len() as u16 + 1;
+    let glyphs: Vec<u16> = CHARACTERS
+        .iter()
+        .flat_map(|&(first, last, glyph)| (first..=last).map(move |_| glyph))
+        .collect();
+    let search_range = 2 * (1 << (15 - count.leading_zeros())) as u16;
+    let subtable = table(|w| {
+        w.u16(4).u16(16 + 8 * count + 2 * glyphs.len() as u16);
+        w.u16(0);
+        w.u16(2 * count).u16(search_range);
+        w.u16(search_range.trailing_zeros() as u16 - 1);
+        w.u16(2 * count - search_range);
+        for &(_, last, _) in &CHARACTERS {
+            w.u16(last);
+        }
+        w.u16(0xFFFF).u16(0);
+        for &(first, _, _) in &CHARACTERS {
+            w.u16(first);
+        }
+        w.u16(0xFFFF);
+        for _ in &CHARACTERS {
+            w.u16(0);
+        }
+        w.u16(1);
+        let mut start = 0;
+        for (index, &(first, last, _)) in CHARACTERS.iter().enumerate() {
+            w.u16(2 * (count - index as u16 + start));
+            start += last - first + 1;
+        }
+        w.u16(0);
+        for &glyph in &glyphs {
+            w.u16(glyph);
+        }
+    });
+    table(|w| {
+        w.u16(0).u16(1).u16(3).u16(1).u32(12);
+        w.0.extend_from_slice(&subtable);
+    })
+}
+
+fn name() -> Vec<u8> {
+    let names: [(u16, &str); 4] = [
+        (1, "Layout Test"),
+        (2, "Regular"),
+        (4, "Layout Test Regular"),
+        (6, "LayoutTest-Regular"),
+    ];
+    let mut strings = vec![];
+    let mut records = Writer(vec![]);
+    for &(id, name) in &names {
+        let encoded: Vec<u8> = name
+            .encode_utf16()
+            .flat_map(|unit| unit.to_be_bytes().to_vec())
+            .collect();
+        records.u16(3).u16(1).u16(0x409).u16(id);
+        records.u16(encoded.len() as u16).u16(strings.len() as u16);
+        strings.extend(encoded);
+    }
+    table(|w| {
+        w.u16(0)
+            .u16(names.len() as u16)
+            .u16(6 + records.0.len() as u16);
+        w.0.extend_from_slice(&records.0);
+        w.0.extend_from_slice(&strings);
+    })
+}
+
+fn post() -> Vec<u8> {
+    table(|w| {
+        w.u32(0x0003_0000).u32(0).i16(-100).i16(50);
+        w.u32(0).u32(0).u32(0).u32(0).u32(0);
+    })
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut word = [0; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
+
+/// The font file, with its tables in tag order.
+pub fn font_bytes() -> Vec<u8> {
+    let (glyf, loca) = glyf_and_loca();
+    let tables: Vec<(&[u8; 4], Vec<u8>)> = vec![
+        (b"OS/2", os2()),
+        (b"cmap", cmap()),
+        (b"glyf", glyf),
+        (b"head", head()),
+        (b"hhea", hhea()),
+        (b"hmtx", hmtx()),
+        (b"loca", loca),
+        (b"maxp", maxp()),
+        (b"name", name()),
+        (b"post", post()),
+    ];
+    let count = tables.len() as u16;
+    let search_range = 16 * (1 << (15 - count.leading_zeros())) as u16;
+    let mut file = Writer(vec![]);
+    file.u32(0x0001_0000).u16(count).u16(search_range);
+    file.u16((search_range / 16).trailing_zeros() as u16);
+    file.u16(16 * count - search_range);
+    let mut offset = 12 + 16 * tables.len();
+    for (tag, data) in &tables {
+        file.0.extend_from_slice(&tag[..]);
+        file.u32(checksum(data))
+            .u32(offset as u32)
+            .u32(data.len() as u32);
+        offset += (data.len() + 3) & !3;
+    }
+    for (_, data) in &tables {
+        file.0.extend_from_slice(data);
+        while file.0.len() % 4 != 0 {
+            file.0.push(0);
+        }
+    }
+    file.0
+}
+
+#[test]
+fn font_has_the_documented_metrics() {
+    let font = font();
+    let metrics = font.metrics();
+    assert_eq!(metrics.units_per_em, 1000);
+    assert_eq!(metrics.ascent, ASCENT * 1000.0);
+    assert_eq!(metrics.ascent - metrics.descent, 1000.0);
+    let advance = |c| font.advance(font.glyph_for_char(c).unwrap()).unwrap().x / 1000.0;
+    assert_eq!(advance('a'), CHAR_WIDTH);
+    assert_eq!(advance('~'), CHAR_WIDTH);
+    assert_eq!(advance(' '), SPACE_WIDTH);
+    assert_eq!(advance('\n'), 0.0);
+}
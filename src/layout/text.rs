@@ -1,15 +1,375 @@
-use super::{Glyph, TextFragment};
+use super::shaped::ShapedText;
+use super::{
+    stats, DecorationSegment, FontContext, FontMetrics, Glyph, LogicalLength, LogicalRect,
+    SharedCollection, Synthesis, TextDecoration, TextFragment, WhitespaceKind, WhitespaceMarker,
+    MISSING_GLYPH_ADVANCE, UNBOUNDED,
+};
+use crate::style::{
+    ComputedValues, ControlCharacters, EllipsisPosition, FontStyle, FontSynthesis, MissingGlyphs,
+    OverflowWrap, TextPositioning, WhiteSpace,
+};
 use crate::util::equal_rc::EqualRc;
-use crate::util::word_break_iter;
-use euclid::point2;
-use skribo::{FontCollection, LayoutSession, TextStyle};
+use crate::util::keyed_cache::{CacheStats, KeyedCache};
+use crate::util::word_break_iter::{
+    cluster_starts, WordBreakIterator, WordBreakStrategy, ZERO_WIDTH_SPACE,
+};
+use euclid::{point2, size2};
+use font_kit::metrics::Metrics;
+use font_kit::properties::{Properties, Style as FontKitStyle};
+use skribo::{FontRef, LayoutSession, TextStyle};
 use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::rc::Rc;
+
+/// The subset of `ComputedValues` that affects how text is laid out.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextOptions {
+    pub size: f32,
+    pub overflow_wrap: OverflowWrap,
+    pub word_break: WordBreakStrategy,
+    pub min_line_height: Option<f32>,
+    pub weight: u32,
+    pub italic: bool,
+    pub allow_synthetic: bool,
+    pub strip_control: bool,
+    pub missing_glyphs: MissingGlyphs,
+    pub positioning: TextPositioning,
+    pub nowrap: bool,
+    pub font_stack: Option<&'static [&'static str]>,
+    pub font_fallback: bool,
+    pub collapse_whitespace: bool,
+    pub show_whitespace: bool,
+    pub ellipsis: Option<&'static str>,
+    pub ellipsis_position: EllipsisPosition,
+}
+
+/// Extra advance given to each glyph of a faux bold run, as a fraction
+/// of the text size, to make room for the thicker strokes.
+const SYNTHETIC_BOLD_ADVANCE: f32 = 1.0 / 24.0;
+
+/// Smallest text size laid out. Sizes of zero or less (or NaN) from bad
+/// style values would otherwise turn font metrics into infinities.
+const MIN_TEXT_SIZE: f32 = 1.0 / 64.0;
+
+/// The smallest size text is laid out at by an engine, see
+/// `LayoutEngine::set_min_text_size`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) struct MinTextSize(pub Option<LogicalLength>);
+
+/// The glyph fonts use for characters they don't have.
+const NOTDEF_GLYPH: u32 = 0;
+
+/// Most word measurements kept per text. A text has roughly one per
+/// word, so this only starts over for very long texts.
+const WORD_CACHE_CAPACITY: usize = 4096;
+
+impl TextOptions {
+    pub fn new(values: &ComputedValues) -> TextOptions {
+        let mut size = values.text_size.get();
+        if let Some(min) = illicit::Env::get::<MinTextSize>().and_then(|min| min.0) {
+            size = size.max(min.get());
+        }
+        TextOptions {
+            size: clamp_text_size(size),
+            overflow_wrap: values.overflow_wrap,
+            word_break: values.word_break,
+            min_line_height: values.min_line_height.map(|height| height.get()),
+            weight: values.font_weight,
+            italic: values.font_style == FontStyle::Italic,
+            allow_synthetic: values.font_synthesis == FontSynthesis::Auto,
+            strip_control: values.control_characters == ControlCharacters::Strip,
+            missing_glyphs: values.missing_glyphs,
+            positioning: values.text_positioning,
+            nowrap: values.white_space == WhiteSpace::NoWrap,
+            font_stack: values.font_stack,
+            font_fallback: values.font_fallback,
+            collapse_whitespace: values.collapse_whitespace,
+            show_whitespace: values.show_whitespace,
+            ellipsis: values.ellipsis,
+            ellipsis_position: values.ellipsis_position,
+        }
+    }
+
+    /// Decides what has to be faked for a run drawn with a font that
+    /// has the given properties.
+    fn synthesis(&self, font: &Properties) -> Synthesis {
+        if !self.allow_synthetic {
+            return Synthesis::default();
+        }
+        Synthesis {
+            bold: self.weight >= 600 && font.weight.0 < 600.0,
+            italic: self.italic && font.style == FontKitStyle::Normal,
+        }
+    }
+
+    fn extra_advance(&self, synthesis: Synthesis) -> f32 {
+        if synthesis.bold {
+            self.size * SYNTHETIC_BOLD_ADVANCE
+        } else {
+            0.0
+        }
+    }
+
+    /// Whether a glyph gets replaced by a placeholder box.
+    fn is_placeholder(&self, glyph_id: u32) -> bool {
+        glyph_id == NOTDEF_GLYPH && self.missing_glyphs == MissingGlyphs::Placeholder
+    }
+
+    /// How much wider a glyph is laid out than the font's own advance,
+    /// which the shaper's offsets already account for.
+    fn adjustment(&self, glyph_id: u32, font_advance: f32, synthesis: Synthesis) -> f32 {
+        let mut adjustment = self.extra_advance(synthesis);
+        if self.is_placeholder(glyph_id) {
+            adjustment += self.size * MISSING_GLYPH_ADVANCE - font_advance;
+        }
+        if self.positioning == TextPositioning::IntegerSnap {
+            let advance = font_advance + adjustment;
+            adjustment += advance.round() - advance;
+        }
+        adjustment
+    }
+
+    /// Snaps a position within a line to the pixel grid if required.
+    /// Since `adjustment` already rounds the advances, this only cleans
+    /// up the error of the shaper's fractional offsets.
+    fn snap(&self, x: f32) -> f32 {
+        match self.positioning {
+            TextPositioning::Subpixel => x,
+            TextPositioning::IntegerSnap => x.round(),
+        }
+    }
+}
+
+fn clamp_text_size(size: f32) -> f32 {
+    if size >= MIN_TEXT_SIZE {
+        size.min(std::f32::MAX)
+    } else {
+        MIN_TEXT_SIZE
+    }
+}
+
+/// Zero width spaces are always taken out of the text, leaving behind a
+/// soft break. With `strip_control`, so are control characters other
+/// than tabs and line breaks, which would otherwise be shaped into
+/// missing glyph boxes.
+fn is_stripped(ch: char, strip_control: bool) -> bool {
+    match ch {
+        ZERO_WIDTH_SPACE => true,
+        '\t' | '\n' | '\r' => false,
+        ch => strip_control && ch.is_control(),
+    }
+}
+
+/// Whitespace that collapses like it does under CSS `white-space:
+/// normal`. No-break spaces are left alone.
+fn is_collapsible(ch: char) -> bool {
+    match ch {
+        ' ' | '\t' | '\n' | '\r' => true,
+        _ => false,
+    }
+}
+
+/// Byte offsets of the whitespace that collapses away. Each run keeps
+/// its first character unless it continues the previous text's run.
+fn collapsed_whitespace(text: &str, strip_control: bool, mut after_space: bool) -> Vec<usize> {
+    let mut collapsed = vec![];
+    for (index, ch) in text.char_indices() {
+        if is_stripped(ch, strip_control) {
+            continue;
+        }
+        if is_collapsible(ch) {
+            if after_space {
+                collapsed.push(index);
+            }
+            after_space = true;
+        } else {
+            after_space = false;
+        }
+    }
+    collapsed
+}
+
+/// Whether a run of whitespace is still open at the end of `text`.
+pub fn ends_in_space(text: &str, options: &TextOptions, after_space: bool) -> bool {
+    if !options.collapse_whitespace {
+        return false;
+    }
+    match text
+        .chars()
+        .rev()
+        .find(|&ch| !is_stripped(ch, options.strip_control))
+    {
+        Some(ch) => is_collapsible(ch),
+        None => after_space,
+    }
+}
+
+/// Removes the characters matched by `is_stripped` along with the
+/// `collapsed` whitespace. Returns the remaining text along with the
+/// byte ranges that were removed.
+fn strip_chars(
+    text: &str,
+    strip_control: bool,
+    collapsed: &[usize],
+) -> (String, Vec<Range<usize>>) {
+    let mut stripped = String::with_capacity(text.len());
+    let mut removed = vec![];
+    let mut collapsed = collapsed.iter().peekable();
+    for (index, ch) in text.char_indices() {
+        let is_collapsed = collapsed.peek() == Some(&&index);
+        if is_collapsed {
+            collapsed.next();
+        }
+        if is_collapsed || is_stripped(ch, strip_control) {
+            removed.push(index..index + ch.len_utf8());
+        } else {
+            stripped.push(ch);
+        }
+    }
+    (stripped, removed)
+}
+
+/// Where the zero width spaces among the `removed` ranges of `source`
+/// end up in the stripped text.
+fn soft_breaks(source: &str, removed: &[Range<usize>]) -> Vec<usize> {
+    removed
+        .iter()
+        .filter(|range| source[range.start..].starts_with(ZERO_WIDTH_SPACE))
+        .map(|range| to_layout_offset(removed, range.start))
+        .collect()
+}
 
 pub struct TextLayoutInfo {
-    session: RefCell<LayoutSession<String>>,
+    /// The text being laid out, shared with the DOM unless characters
+    /// had to be stripped from it.
+    text: Rc<str>,
+    session: RefCell<ShapedText>,
+    options: TextOptions,
+    /// Byte ranges of the source text left out of the session, sorted.
+    /// Offsets into the session have to be mapped back past them.
+    removed: Vec<Range<usize>>,
+    /// Offsets into the session where a zero width space was removed,
+    /// and the text may be broken.
+    soft_breaks: Vec<usize>,
+    /// Height and ascender of an empty line, only present when the text
+    /// is empty or whitespace, since it still needs to take up a line.
+    blank_line: Option<(f32, f32)>,
+    /// Width, line height, and ascender of every word measured so far,
+    /// by its byte range in the session. A word that doesn't fit at the
+    /// end of one line is measured again at the start of the next, and
+    /// rewrapping at a new width measures all of them again. Cleared
+    /// when it reaches `WORD_CACHE_CAPACITY`.
+    words: RefCell<HashMap<(usize, usize), (f32, f32, f32)>>,
+    /// The fonts the text is set in, kept for shaping the ellipsis.
+    collection: SharedCollection,
+}
+
+/// How many texts a `TextLayoutCache` keeps shaped unless told otherwise.
+const TEXT_CACHE_CAPACITY: usize = 1024;
+
+/// Texts set in different sizes or fonts are kept apart, while the
+/// rest of their options have to match for an entry to be used.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct TextKey {
+    text: Rc<str>,
+    after_space: bool,
+    size: u32,
+    font_stack: Option<&'static [&'static str]>,
+    font_fallback: bool,
+}
+
+/// Shaped text that belongs to the caller instead of to a layout engine,
+/// so that it outlives engines and `LayoutEngine::invalidate`, see
+/// `LayoutEngine::set_text_cache`. Texts are found again by their
+/// content, style and fonts, so engines with different font contexts
+/// can share a cache. The least recently used are let go of once there
+/// are `TEXT_CACHE_CAPACITY` of them. Clones share the same entries.
+#[derive(Clone)]
+pub struct TextLayoutCache {
+    entries: Rc<KeyedCache<TextKey>>,
+}
+
+impl TextLayoutCache {
+    pub fn new() -> TextLayoutCache {
+        let entries = KeyedCache::new();
+        entries.set_capacity(Some(TEXT_CACHE_CAPACITY));
+        TextLayoutCache {
+            entries: Rc::new(entries),
+        }
+    }
+
+    /// Changes how many shaped texts are kept. `None` keeps every one
+    /// until the cache is cleared.
+    pub fn set_capacity(&self, capacity: Option<usize>) {
+        self.entries.set_capacity(capacity);
+    }
+
+    /// Hits, misses and evictions since the cache was created.
+    pub fn stats(&self) -> CacheStats {
+        self.entries.stats()
+    }
+
+    /// How many shaped texts are kept.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Lets go of every shaped text.
+    pub fn clear(&self) {
+        self.entries.clear();
+    }
+
+    fn shape(
+        &self,
+        fonts: &FontContext,
+        text: Rc<str>,
+        options: TextOptions,
+        after_space: bool,
+    ) -> EqualRc<TextLayoutInfo> {
+        let key = TextKey {
+            text: text.clone(),
+            after_space,
+            size: options.size.to_bits(),
+            font_stack: options.font_stack,
+            font_fallback: options.font_fallback,
+        };
+        // Registering a font adds it to the collection in place, so the
+        // collection alone doesn't tell whether the fonts changed.
+        let collection = fonts.collection_for(options.font_stack, options.font_fallback);
+        let fonts_used = (collection, fonts.generation());
+        self.entries.memo(key, (options, fonts_used), |_| {
+            EqualRc::new(TextLayoutInfo::new(text, options, after_space))
+        })
+    }
+}
+
+impl Default for TextLayoutCache {
+    fn default() -> Self {
+        TextLayoutCache::new()
+    }
+}
+
+/// Shapes `text`, or takes it from the caller's `TextLayoutCache` when
+/// the engine was given one.
+#[illicit::from_env(fonts: &FontContext, text_cache: &Option<TextLayoutCache>)]
+pub fn shape_text(
+    text: Rc<str>,
+    options: TextOptions,
+    after_space: bool,
+) -> EqualRc<TextLayoutInfo> {
+    match text_cache {
+        Some(text_cache) => text_cache.shape(fonts, text, options, after_space),
+        None => EqualRc::new(TextLayoutInfo::new(text, options, after_space)),
+    }
 }
 
 pub struct FilledLine {
+    pub range: Range<usize>,
     pub width: f32,
     pub height: f32,
     pub ascender: f32,
@@ -20,54 +380,678 @@ pub struct FilledLine {
 pub struct TextState<'a> {
     offset: usize,
     layout: &'a TextLayoutInfo,
+    /// Set once `fill_line` has been called. Blank text only gets a line
+    /// of its own when it starts one, not when it first failed to fit
+    /// after other content.
+    started: bool,
 }
 
 impl TextLayoutInfo {
-    #[illicit::from_env(collection: &EqualRc<FontCollection>)]
-    pub fn new(text: String, size: f32) -> Self {
+    /// Lays out `text`, which follows whitespace if `after_space`.
+    #[illicit::from_env(fonts: &FontContext)]
+    pub fn new(text: Rc<str>, options: TextOptions, after_space: bool) -> Self {
+        stats::count(|stats| stats.texts += 1);
+        let collection = fonts.collection_for(options.font_stack, options.font_fallback);
+        let strip = |ch| is_stripped(ch, options.strip_control);
+        let collapsed = if options.collapse_whitespace {
+            collapsed_whitespace(&text, options.strip_control, after_space)
+        } else {
+            vec![]
+        };
+        let (text, removed, soft_breaks) = if !collapsed.is_empty() || text.chars().any(strip) {
+            let (stripped, removed) = strip_chars(&text, options.strip_control, &collapsed);
+            let soft_breaks = soft_breaks(&text, &removed);
+            (stripped.into(), removed, soft_breaks)
+        } else {
+            (text, vec![], vec![])
+        };
+        let blank_line = if text.trim().is_empty() {
+            let mut session = ShapedText::new(Rc::from(" "), options.size, &collection);
+            let (_, height, ascender) = TextState::measure(&mut session, &options, 0, 1);
+            Some((height, ascender))
+        } else {
+            None
+        };
         TextLayoutInfo {
-            session: RefCell::new(LayoutSession::create(text, &TextStyle { size }, collection)),
+            session: RefCell::new(ShapedText::by_paragraph(
+                text.clone(),
+                &options,
+                &collection,
+                &fonts.paragraphs,
+            )),
+            text,
+            options,
+            removed,
+            soft_breaks,
+            blank_line,
+            words: RefCell::new(HashMap::new()),
+            collection,
+        }
+    }
+
+    /// Width of the text between two byte offsets of the source text
+    /// when laid out on a single line.
+    pub fn measure_width(&self, start: usize, end: usize) -> f32 {
+        let start = to_layout_offset(&self.removed, start);
+        let end = to_layout_offset(&self.removed, end);
+        TextState::measure(&mut *self.session.borrow_mut(), &self.options, start, end).0
+    }
+
+    /// The offset of the source text within `range`, itself in source
+    /// offsets, whose x position is closest to `x`, measured from the
+    /// start of the range. Only offsets between characters are found.
+    pub fn offset_at_x(&self, range: Range<usize>, x: f32) -> usize {
+        let start = to_layout_offset(&self.removed, range.start);
+        let end = to_layout_offset(&self.removed, range.end);
+        let mut closest = (range.start, std::f32::INFINITY);
+        for (offset, width) in self.boundary_positions(start, end) {
+            let distance = (width - x).abs();
+            if distance < closest.1 {
+                let offset = to_source_offset(&self.removed, offset);
+                closest = (offset.max(range.start).min(range.end), distance);
+            }
+        }
+        closest.0
+    }
+
+    /// Where `decorations`, given by byte ranges of the source text, fall
+    /// on the line covering the source range `line`, relative to its
+    /// start. `extra` is what each space of the line was widened by if
+    /// it was justified, see `justified_fragments`.
+    pub fn decoration_segments(
+        &self,
+        line: Range<usize>,
+        decorations: &[(Range<usize>, TextDecoration)],
+        extra: f32,
+    ) -> Vec<DecorationSegment> {
+        let start = to_layout_offset(&self.removed, line.start);
+        let end = to_layout_offset(&self.removed, line.end);
+        let stretched_end = start + self.text[start..end].trim_end().len();
+        let mut session = self.session.borrow_mut();
+        let mut x = |offset| {
+            let offset = to_layout_offset(&self.removed, offset);
+            let gaps = self.text[start..offset.min(stretched_end)]
+                .matches(' ')
+                .count();
+            TextState::measure(&mut *session, &self.options, start, offset).0 + extra * gaps as f32
+        };
+        decorations
+            .iter()
+            .filter_map(|(range, kind)| {
+                let (from, to) = (range.start.max(line.start), range.end.min(line.end));
+                if from >= to {
+                    return None;
+                }
+                let (left, right) = (x(from), x(to));
+                Some(DecorationSegment {
+                    kind: *kind,
+                    range: from..to,
+                    x: left,
+                    width: right - left,
+                })
+            })
+            .collect()
+    }
+
+    /// Where the spaces and tabs in a range of the laid out text are,
+    /// relative to its start, or nothing unless the options ask for
+    /// them. They're measured like the text before them, so that each
+    /// marker starts where the glyphs before it end.
+    pub fn whitespace_markers(&self, range: Range<usize>) -> Vec<WhitespaceMarker> {
+        if !self.options.show_whitespace {
+            return vec![];
+        }
+        let positions = self.boundary_positions(range.start, range.end);
+        positions
+            .windows(2)
+            .filter_map(|pair| {
+                let ((from, before), (to, after)) = (pair[0], pair[1]);
+                let kind = match &self.text[from..to] {
+                    " " => WhitespaceKind::Space,
+                    "\t" => WhitespaceKind::Tab,
+                    _ => return None,
+                };
+                Some(WhitespaceMarker {
+                    kind,
+                    x: before,
+                    width: after - before,
+                })
+            })
+            .collect()
+    }
+
+    /// Each cluster boundary in a range of the laid out text with its x
+    /// position relative to the start of the range, found in one pass by
+    /// adding up the widths of the clusters in turn. The clusters are
+    /// sliced out of the shaped text rather than shaped alone, so they
+    /// keep their kerning, and a caret never lands inside a cluster like
+    /// a flag.
+    fn boundary_positions(&self, start: usize, end: usize) -> Vec<(usize, f32)> {
+        let mut session = self.session.borrow_mut();
+        let mut x = 0.0;
+        let mut positions = vec![(start, x)];
+        if start == end {
+            return positions;
+        }
+        let boundaries = cluster_starts(&self.text[start..end])
+            .map(|index| start + index)
+            .chain(std::iter::once(end));
+        for to in boundaries {
+            let from = positions[positions.len() - 1].0;
+            x += TextState::measure(&mut *session, &self.options, from, to).0;
+            positions.push((to, x));
+        }
+        positions
+    }
+
+    /// Number of spaces in a range of the source text that justified
+    /// text is stretched at, which leaves out the ones at its end.
+    pub fn justify_gaps(&self, range: Range<usize>) -> usize {
+        let start = to_layout_offset(&self.removed, range.start);
+        let end = to_layout_offset(&self.removed, range.end);
+        self.text[start..end].trim_end().matches(' ').count()
+    }
+
+    /// The min-content and max-content widths of the text, for sizing
+    /// table columns and the like to their content: the widest line when
+    /// it's broken wherever it can be, which is the longest word unless
+    /// `overflow_wrap` lets words break, and the widest line when it
+    /// isn't wrapped at all. Spaces at the ends of lines don't count.
+    pub fn content_widths(&self) -> (f32, f32) {
+        let widest_line = |width| {
+            let mut state = TextState::new(self);
+            let mut widest = 0.0f32;
+            while !state.finished() {
+                let line = match state.fill_line(width, true) {
+                    Some(line) => line,
+                    None => break,
+                };
+                let trailing = self.trailing_space_width(self.source_range(line.range));
+                widest = widest.max(line.width - trailing);
+            }
+            widest
+        };
+        (widest_line(0.0), widest_line(UNBOUNDED))
+    }
+
+    /// Width of the spaces at the end of a range of the source text,
+    /// which hang past the edge of an aligned line.
+    pub fn trailing_space_width(&self, range: Range<usize>) -> f32 {
+        let start = to_layout_offset(&self.removed, range.start);
+        let end = to_layout_offset(&self.removed, range.end);
+        let trimmed = start + self.text[start..end].trim_end().len();
+        if trimmed == end {
+            return 0.0;
+        }
+        let mut session = self.session.borrow_mut();
+        let full = TextState::measure(&mut *session, &self.options, start, end).0;
+        full - TextState::measure(&mut *session, &self.options, start, trimmed).0
+    }
+
+    /// Lays out a range of the source text on a single line with each of
+    /// its `justify_gaps` widened by `extra`, returning the fragments
+    /// and the width of the line.
+    pub fn justified_fragments(&self, range: Range<usize>, extra: f32) -> (Vec<TextFragment>, f32) {
+        let start = to_layout_offset(&self.removed, range.start);
+        let end = to_layout_offset(&self.removed, range.end);
+        let state = TextState::new(self);
+        let mut session = self.session.borrow_mut();
+        let stretched = &self.text[start..start + self.text[start..end].trim_end().len()];
+        let word_ends = stretched
+            .match_indices(' ')
+            .map(|(index, _)| start + index + 1)
+            .chain(std::iter::once(end));
+
+        let mut fragments = vec![];
+        let mut word_start = start;
+        let mut shift = 0.0;
+        for word_end in word_ends {
+            let x = TextState::measure(&mut *session, &self.options, start, word_start).0 + shift;
+            let x = self.options.snap(x);
+            for mut fragment in state.create_fragments(&mut *session, word_start, word_end) {
+                for glyph in &mut fragment.glyphs {
+                    glyph.offset.x += x;
+                }
+                fragments.push(fragment);
+            }
+            word_start = word_end;
+            shift += extra;
+        }
+        let gaps = stretched.matches(' ').count();
+        let width = TextState::measure(&mut *session, &self.options, start, end).0;
+        (fragments, width + extra * gaps as f32)
+    }
+
+    /// Lays out a range of the source text cut down to `width` with the
+    /// `ellipsis` in place of what was left out, returning the fragments,
+    /// the width of the line, and where `decorations` fall on the parts
+    /// that were kept, like `decoration_segments`. `None` when there is
+    /// no ellipsis set.
+    pub fn ellipsized_fragments(
+        &self,
+        range: Range<usize>,
+        decorations: &[(Range<usize>, TextDecoration)],
+        width: f32,
+    ) -> Option<(Vec<TextFragment>, f32, Vec<DecorationSegment>)> {
+        let ellipsis = self.options.ellipsis?;
+        let start = to_layout_offset(&self.removed, range.start);
+        let end = to_layout_offset(&self.removed, range.end);
+        let state = TextState::new(self);
+        let mut ellipsis_session =
+            ShapedText::new(Rc::from(ellipsis), self.options.size, &self.collection);
+        let ellipsis_width =
+            TextState::measure(&mut ellipsis_session, &self.options, 0, ellipsis.len()).0;
+
+        let mut session = self.session.borrow_mut();
+        let options = &self.options;
+        let (head_end, tail_start) = ellipsis_cut(
+            &self.text[start..end],
+            width,
+            ellipsis_width,
+            options.ellipsis_position,
+            |part| {
+                TextState::measure(&mut *session, options, start + part.start, start + part.end).0
+            },
+        );
+
+        // The kept head, the ellipsis, and the kept tail, one after another.
+        let pieces = [
+            (false, start, start + head_end),
+            (true, 0, ellipsis.len()),
+            (false, start + tail_start, end),
+        ];
+        let mut fragments = vec![];
+        let mut kept = vec![];
+        let mut x = 0.0;
+        for &(is_ellipsis, start, end) in &pieces {
+            let session = if is_ellipsis {
+                &mut ellipsis_session
+            } else {
+                kept.push((start..end, x));
+                &mut *session
+            };
+            for mut fragment in state.create_fragments(session, start, end) {
+                for glyph in &mut fragment.glyphs {
+                    glyph.offset.x += x;
+                }
+                fragments.push(fragment);
+            }
+            x += TextState::measure(session, options, start, end).0;
+        }
+        drop(session);
+
+        // The head and the tail each start their own decorations, the
+        // tail's moved to where it ended up after the ellipsis.
+        let decorations = kept
+            .into_iter()
+            .flat_map(|(part, part_x)| {
+                self.decoration_segments(self.source_range(part), decorations, 0.0)
+                    .into_iter()
+                    .map(move |segment| DecorationSegment {
+                        x: segment.x + part_x,
+                        ..segment
+                    })
+            })
+            .collect();
+        Some((fragments, x, decorations))
+    }
+
+    /// Maps a range of the laid out text back to the source text.
+    pub fn source_range(&self, range: Range<usize>) -> Range<usize> {
+        to_source_offset(&self.removed, range.start)..to_source_offset(&self.removed, range.end)
+    }
+}
+
+/// Decides how much of `text` to keep when it is cut down to `width`,
+/// with an ellipsis of `ellipsis_width` at `position`. Returns the end
+/// of the part kept before the ellipsis and the start of the part kept
+/// after it. `measure` gives the width of a range of the text.
+fn ellipsis_cut(
+    text: &str,
+    width: f32,
+    ellipsis_width: f32,
+    position: EllipsisPosition,
+    mut measure: impl FnMut(Range<usize>) -> f32,
+) -> (usize, usize) {
+    let len = text.len();
+    let available = width - ellipsis_width;
+    let boundaries: Vec<usize> = text
+        .char_indices()
+        .map(|(index, _)| index)
+        .chain(std::iter::once(len))
+        .collect();
+    // The longest head and shortest tail that fit in the given widths.
+    // Widths grow with the head and shrink with the tail, so both are
+    // binary searched rather than measured one character at a time.
+    let mut longest_head = |budget: f32| {
+        // Everything before `low` fits and nothing from `high` on does.
+        let (mut low, mut high) = (1, boundaries.len());
+        while low < high {
+            let mid = (low + high) / 2;
+            if measure(0..boundaries[mid]) > budget {
+                high = mid;
+            } else {
+                low = mid + 1;
+            }
+        }
+        boundaries[low - 1]
+    };
+    match position {
+        EllipsisPosition::End => (longest_head(available), len),
+        EllipsisPosition::Start | EllipsisPosition::Middle => {
+            let head = if position == EllipsisPosition::Middle {
+                longest_head(available / 2.0)
+            } else {
+                0
+            };
+            let budget = available - measure(0..head);
+            // Nothing before `low` fits and everything from `high` on does.
+            let mut low = boundaries
+                .binary_search(&head)
+                .unwrap_or_else(|index| index);
+            let mut high = boundaries.len();
+            while low < high {
+                let mid = (low + high) / 2;
+                if measure(boundaries[mid]..len) <= budget {
+                    high = mid;
+                } else {
+                    low = mid + 1;
+                }
+            }
+            (head, boundaries.get(low).cloned().unwrap_or(len))
+        }
+    }
+}
+
+/// Height of a single line of text with the given options, the same as
+/// an empty line would take up.
+#[illicit::from_env(fonts: &FontContext)]
+pub fn line_height(options: &TextOptions) -> f32 {
+    let collection = fonts.collection_for(options.font_stack, options.font_fallback);
+    let mut session = ShapedText::new(Rc::from(" "), options.size, &collection);
+    let (_, height, _) = TextState::measure(&mut session, options, 0, 1);
+    options
+        .min_line_height
+        .map_or(height, |min_height| height.max(min_height))
+}
+
+/// The metrics of the font a space is set in, which is the font text
+/// falls back to first.
+#[illicit::from_env(fonts: &FontContext)]
+pub fn font_metrics(options: &TextOptions) -> Option<FontMetrics> {
+    let collection = fonts.collection_for(options.font_stack, options.font_fallback);
+    let style = TextStyle { size: options.size };
+    let mut session = LayoutSession::create(Rc::from(" "), &style, &collection.borrow());
+    let run = session.iter_substr(0..1).next()?;
+    Some(scale_metrics(&run.font().font.metrics(), options.size))
+}
+
+/// Converts metrics in font units to pixels at `size`.
+fn scale_metrics(metrics: &Metrics, size: f32) -> FontMetrics {
+    let scale = size / metrics.units_per_em as f32;
+    FontMetrics {
+        ascent: metrics.ascent * scale,
+        descent: -metrics.descent.abs() * scale,
+        line_gap: metrics.line_gap * scale,
+        cap_height: metrics.cap_height * scale,
+        x_height: metrics.x_height * scale,
+    }
+}
+
+/// Height of a line set in a font, in font units. Fonts disagree on the
+/// sign of the descent, which is normally negative, so only its size is
+/// used.
+fn line_height_of(metrics: &Metrics) -> f32 {
+    metrics.ascent + metrics.descent.abs()
+}
+
+/// The advance of a glyph in font units. A glyph the font fails to load
+/// takes up no space rather than taking down the layout.
+pub(super) fn glyph_advance(font: &FontRef, glyph_id: u32) -> f32 {
+    font.font
+        .advance(glyph_id)
+        .map(|advance| advance.x)
+        .unwrap_or(0.0)
+}
+
+/// The union of the outlines of the glyphs in a line of text, relative
+/// to the top left of the line, or `None` when nothing in it leaves a
+/// mark, like a run of spaces. Synthesized italics are left out.
+pub fn ink_bounds(fragments: &[TextFragment], size: f32) -> Option<LogicalRect> {
+    let mut ink: Option<LogicalRect> = None;
+    for fragment in fragments {
+        let font = &fragment.font.font;
+        let units_per_px = font.metrics().units_per_em as f32 / size;
+        for glyph in &fragment.glyphs {
+            let rect = if glyph.missing {
+                // The box the renderer draws in place of the glyph.
+                let advance = size * MISSING_GLYPH_ADVANCE;
+                LogicalRect::new(
+                    point2(advance * 0.1, -size * 0.7),
+                    size2(advance * 0.8, size * 0.7),
+                )
+            } else {
+                match font.typographic_bounds(glyph.index) {
+                    Ok(bounds) if bounds.size.width > 0.0 && bounds.size.height > 0.0 => {
+                        // Font units point up, and layout units down.
+                        LogicalRect::new(
+                            point2(
+                                bounds.min_x() / units_per_px,
+                                -bounds.max_y() / units_per_px,
+                            ),
+                            size2(
+                                bounds.size.width / units_per_px,
+                                bounds.size.height / units_per_px,
+                            ),
+                        )
+                    }
+                    _ => continue,
+                }
+            };
+            let rect = rect.translate(glyph.offset.to_vector());
+            ink = Some(ink.map_or(rect, |ink| ink.union(&rect)));
         }
     }
+    ink
+}
+
+/// Maps an offset into the text with `removed` left out to an offset
+/// into the source text, skipping over any removed text at the offset.
+fn to_source_offset(removed: &[Range<usize>], offset: usize) -> usize {
+    let mut offset = offset;
+    for range in removed {
+        if range.start > offset {
+            break;
+        }
+        offset += range.len();
+    }
+    offset
+}
+
+/// Maps an offset into the source text to one into the text with
+/// `removed` left out. Offsets inside of removed text snap to its start.
+fn to_layout_offset(removed: &[Range<usize>], offset: usize) -> usize {
+    let mut skipped = 0;
+    for range in removed {
+        if range.start >= offset {
+            break;
+        }
+        skipped += range.end.min(offset) - range.start;
+    }
+    offset - skipped
 }
 
 impl<'a> TextState<'a> {
     pub fn new(layout: &'a TextLayoutInfo) -> TextState<'a> {
-        TextState { offset: 0, layout }
+        TextState {
+            offset: 0,
+            layout,
+            started: false,
+        }
     }
 
     fn create_fragments(
         &self,
-        session: &mut LayoutSession<String>,
+        session: &mut ShapedText,
         start: usize,
         end: usize,
     ) -> Vec<TextFragment> {
         let mut fragments = vec![];
         let size = session.style().size;
+        let options = &self.layout.options;
+        let mut shift = 0.0f32;
         for run in session.iter_substr(start..end) {
             let font = run.font().to_owned();
             let metrics = font.font.metrics();
             let units_per_px = metrics.units_per_em as f32 / size;
             let baseline_offset = metrics.ascent / units_per_px;
+            let synthesis = options.synthesis(&font.font.properties());
 
             let glyphs = run
                 .glyphs()
-                .map(|glyph| Glyph {
-                    index: glyph.glyph_id,
-                    offset: point2(glyph.offset.x, glyph.offset.y + baseline_offset),
+                .map(|glyph| {
+                    let x = options.snap(glyph.offset.x + shift);
+                    let advance = glyph_advance(&font, glyph.glyph_id) / units_per_px;
+                    shift += options.adjustment(glyph.glyph_id, advance, synthesis);
+                    Glyph {
+                        index: glyph.glyph_id,
+                        offset: point2(x, glyph.offset.y + baseline_offset),
+                        missing: options.is_placeholder(glyph.glyph_id),
+                    }
                 })
                 .collect();
-            fragments.push(TextFragment { font, glyphs });
+            fragments.push(TextFragment {
+                font,
+                glyphs,
+                synthesis,
+            });
         }
 
         fragments
     }
 
+    /// Measures the width, line height, and ascender of a range of the
+    /// text, in that order.
+    fn measure(
+        session: &mut ShapedText,
+        options: &TextOptions,
+        start: usize,
+        end: usize,
+    ) -> (f32, f32, f32) {
+        let size = session.style().size;
+        let mut width = 0.0f32;
+        let mut height = 0.0f32;
+        let mut ascender = 0.0f32;
+        let mut shift = 0.0f32;
+        for run in session.iter_substr(start..end) {
+            let font = run.font();
+            let metrics = font.font.metrics();
+            let units_per_px = metrics.units_per_em as f32 / size;
+            let synthesis = options.synthesis(&font.font.properties());
+            height = height.max(line_height_of(&metrics) / units_per_px);
+            ascender = ascender.max(metrics.ascent / units_per_px);
+            for glyph in run.glyphs() {
+                let advance = glyph_advance(font, glyph.glyph_id) / units_per_px;
+                shift += options.adjustment(glyph.glyph_id, advance, synthesis);
+                width = width.max(options.snap(glyph.offset.x + advance + shift));
+            }
+        }
+        (width, height, ascender)
+    }
+
+    /// Finds the furthest character boundary in `start..end` that still
+    /// fits in `width`, always keeping at least one character so that
+    /// the caller makes progress. Characters that combine into one, like
+    /// a joined emoji, count as one. Widths only grow with the end, so
+    /// the boundary is binary searched, which keeps a long URL from
+    /// being measured once per character.
+    fn find_hard_break(
+        session: &mut ShapedText,
+        options: &TextOptions,
+        text: &str,
+        start: usize,
+        end: usize,
+        width: f32,
+    ) -> usize {
+        let boundaries: Vec<usize> = cluster_starts(&text[start..end])
+            .map(|index| start + index)
+            .chain(std::iter::once(end))
+            .collect();
+        // The first boundary is always kept. Of the rest, everything
+        // before `low` fits and nothing from `high` on does.
+        let (mut low, mut high) = (1, boundaries.len());
+        while low < high {
+            let mid = (low + high) / 2;
+            if Self::measure(session, options, start, boundaries[mid]).0 > width {
+                high = mid;
+            } else {
+                low = mid + 1;
+            }
+        }
+        boundaries[low - 1]
+    }
+
     pub fn finished(&self) -> bool {
-        self.offset == self.layout.session.borrow().text().len()
+        self.offset == self.layout.text.len()
     }
 
+    /// Lays out as much of the remaining text as fits in `width`,
+    /// breaking between words. An infinite width takes the rest of the
+    /// text. If not even one word fits and this is the start of a new
+    /// line, the `overflow_wrap` setting decides whether the word
+    /// overflows or is broken between characters, so that progress is
+    /// always made.
     pub fn fill_line(&mut self, width: f32, is_new_line: bool) -> Option<FilledLine> {
+        stats::count(|stats| stats.lines += 1);
+        // Text that doesn't wrap runs past the edge, which the caller
+        // finds out from the width of the line.
+        let width = if self.layout.options.nowrap {
+            UNBOUNDED
+        } else {
+            width
+        };
+        let line = self
+            .break_line(width, is_new_line)
+            .or_else(|| self.blank_line(is_new_line));
+        self.started = true;
+        line.map(|line| self.apply_min_line_height(line))
+    }
+
+    /// Empty and whitespace-only text still reserves a line, so that
+    /// e.g. an empty paragraph keeps its height.
+    fn blank_line(&self, is_new_line: bool) -> Option<FilledLine> {
+        match self.layout.blank_line {
+            Some((height, ascender)) if is_new_line && !self.started => Some(FilledLine {
+                range: self.offset..self.offset,
+                width: 0.0,
+                height,
+                ascender,
+                fragments: vec![],
+                text_size: self.layout.options.size,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Grows a line that is shorter than `min_line_height`, keeping the
+    /// glyphs vertically centered in the taller line box.
+    fn apply_min_line_height(&self, mut line: FilledLine) -> FilledLine {
+        if let Some(min_height) = self.layout.options.min_line_height {
+            if line.height < min_height {
+                let leading = (min_height - line.height) / 2.0;
+                for fragment in &mut line.fragments {
+                    for glyph in &mut fragment.glyphs {
+                        glyph.offset.y += leading;
+                    }
+                }
+                line.height = min_height;
+                line.ascender += leading;
+            }
+        }
+        line
+    }
+
+    fn break_line(&mut self, width: f32, is_new_line: bool) -> Option<FilledLine> {
         let mut session = self.layout.session.borrow_mut();
 
         let mut x = 0.0;
@@ -77,61 +1061,124 @@ impl<'a> TextState<'a> {
         let mut last_word_x = 0.0;
         let mut last_word_height = 0.0;
         let mut last_word_ascender = 0.0;
+        let mut overflowing = false;
         let size = session.style().size;
-        let text = session.text().to_owned();
+        let text = self.layout.text.clone();
 
-        if is_new_line {
+        // Kept whitespace, like the indentation of preformatted text,
+        // stays at the start of the line.
+        if is_new_line && self.layout.options.collapse_whitespace {
             let trimmed = text[self.offset..].trim_start();
             self.offset = trimmed.as_ptr() as usize - text.as_ptr() as usize;
         };
 
-        for word in word_break_iter::WordBreakIterator::new(&text[self.offset..]) {
+        let words =
+            WordBreakIterator::with_strategy(&text[self.offset..], self.layout.options.word_break)
+                .with_soft_breaks(&self.layout.soft_breaks, self.offset);
+        for word in words {
             let start = word.as_ptr() as usize - text.as_ptr() as usize;
             let end = start + word.len();
 
-            for run in session.iter_substr(start..end) {
-                let font = run.font();
-                let metrics = font.font.metrics();
-                let units_per_px = metrics.units_per_em as f32 / size;
-                let line_height = (metrics.ascent - metrics.descent) / units_per_px;
-                let line_ascent = metrics.ascent / units_per_px;
-
-                for glyph in run.glyphs() {
-                    let new_x = glyph.offset.x
-                        + font.font.advance(glyph.glyph_id).unwrap().x / units_per_px;
-
-                    if last_word_x + new_x > width {
-                        let start = self.offset;
-                        self.offset += last_word_end;
-                        if last_word_end > 0 {
-                            // soft break
-                            return Some(FilledLine {
-                                fragments: self.create_fragments(&mut *session, start, self.offset),
-                                width: last_word_x,
-                                height: last_word_height,
-                                ascender: last_word_ascender,
-                                text_size: size,
-                            });
-                        } else {
-                            // todo: force progress by hard breaking if is_new_line is true
-                            return None;
+            let measured = self.layout.words.borrow().get(&(start, end)).cloned();
+            let (word_width, word_height, word_ascender) = measured.unwrap_or_else(|| {
+                let measured = Self::measure(&mut *session, &self.layout.options, start, end);
+                let mut words = self.layout.words.borrow_mut();
+                if words.len() >= WORD_CACHE_CAPACITY {
+                    words.clear();
+                }
+                words.insert((start, end), measured);
+                measured
+            });
+            if last_word_x + word_width <= width {
+                // The whole word fits, so there is no need to look at
+                // its glyphs one by one.
+                x = last_word_x + word_width;
+                height = height.max(word_height);
+                ascender = ascender.max(word_ascender);
+            } else {
+                let mut shift = 0.0f32;
+                for run in session.iter_substr(start..end) {
+                    let font = run.font();
+                    let metrics = font.font.metrics();
+                    let units_per_px = metrics.units_per_em as f32 / size;
+                    let line_height = line_height_of(&metrics) / units_per_px;
+                    let line_ascent = metrics.ascent / units_per_px;
+                    let options = &self.layout.options;
+                    let synthesis = options.synthesis(&font.font.properties());
+
+                    for glyph in run.glyphs() {
+                        let advance = glyph_advance(font, glyph.glyph_id) / units_per_px;
+                        shift += options.adjustment(glyph.glyph_id, advance, synthesis);
+                        let new_x = options.snap(glyph.offset.x + advance + shift);
+
+                        if last_word_x + new_x > width && !overflowing {
+                            let line_start = self.offset;
+                            if last_word_end > 0 {
+                                // soft break
+                                self.offset += last_word_end;
+                                return Some(FilledLine {
+                                    range: line_start..self.offset,
+                                    fragments: self.create_fragments(
+                                        &mut *session,
+                                        line_start,
+                                        self.offset,
+                                    ),
+                                    width: last_word_x,
+                                    height: last_word_height,
+                                    ascender: last_word_ascender,
+                                    text_size: size,
+                                });
+                            } else if !is_new_line {
+                                return None;
+                            } else if self.layout.options.overflow_wrap == OverflowWrap::BreakWord {
+                                // hard break inside of the word
+                                let options = &self.layout.options;
+                                let break_at = Self::find_hard_break(
+                                    &mut *session,
+                                    options,
+                                    &text,
+                                    line_start,
+                                    end,
+                                    width,
+                                );
+                                let (line_width, line_height, line_ascender) =
+                                    Self::measure(&mut *session, options, line_start, break_at);
+                                self.offset = break_at;
+                                return Some(FilledLine {
+                                    range: line_start..break_at,
+                                    fragments: self.create_fragments(
+                                        &mut *session,
+                                        line_start,
+                                        break_at,
+                                    ),
+                                    width: line_width,
+                                    height: line_height,
+                                    ascender: line_ascender,
+                                    text_size: size,
+                                });
+                            } else {
+                                // let the word overflow the line
+                                overflowing = true;
+                            }
                         }
+                        x = last_word_x + new_x;
+                        height = height.max(line_height);
+                        ascender = ascender.max(line_ascent);
                     }
-                    x = last_word_x + new_x;
-                    height = height.max(line_height);
-                    ascender = ascender.max(line_ascent);
                 }
             }
             last_word_end = end - self.offset;
             last_word_x = x;
             last_word_height = height;
             last_word_ascender = ascender;
+            overflowing = false;
         }
 
         let start = self.offset;
         self.offset += last_word_end;
         if last_word_end > 0 {
             Some(FilledLine {
+                range: start..self.offset,
                 fragments: self.create_fragments(&mut *session, start, self.offset),
                 width: last_word_x,
                 height: last_word_height,
@@ -143,3 +1190,733 @@ impl<'a> TextState<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{
+        collapsed_whitespace, ellipsis_cut, ends_in_space, ink_bounds, line_height, line_height_of,
+        scale_metrics, soft_breaks, strip_chars, to_layout_offset, to_source_offset,
+        DecorationSegment, FilledLine, MinTextSize, ShapedText, Synthesis, TextDecoration,
+        TextLayoutInfo, TextOptions, TextState, MIN_TEXT_SIZE, MISSING_GLYPH_ADVANCE, NOTDEF_GLYPH,
+        WORD_CACHE_CAPACITY,
+    };
+    use crate::dom::{Node, Span, Window};
+    use crate::layout::{test_font, FontContext, LayoutEngine, LogicalLength};
+    use crate::style::{
+        ComputedValues, EllipsisPosition, FontStyle, FontSynthesis, MissingGlyphs, StyleEngine,
+        TextPositioning,
+    };
+    use euclid::{size2, Rect};
+    use font_kit::metrics::Metrics;
+    use font_kit::properties::{Properties, Style, Weight};
+    use std::rc::Rc;
+
+    fn options(weight: u32, font_style: FontStyle, font_synthesis: FontSynthesis) -> TextOptions {
+        TextOptions::new(&ComputedValues {
+            font_weight: weight,
+            font_style,
+            font_synthesis,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn bold_of_regular_face_is_synthesized() {
+        let options = options(700, FontStyle::Normal, FontSynthesis::Auto);
+        let synthesis = options.synthesis(&Properties::new());
+        assert_eq!(
+            synthesis,
+            Synthesis {
+                bold: true,
+                italic: false
+            }
+        );
+        assert!(options.extra_advance(synthesis) > 0.0);
+
+        let mut bold_face = Properties::new();
+        bold_face.weight = Weight::BOLD;
+        assert_eq!(options.synthesis(&bold_face), Synthesis::default());
+    }
+
+    #[test]
+    fn italic_of_upright_face_is_synthesized() {
+        let options = options(400, FontStyle::Italic, FontSynthesis::Auto);
+        let synthesis = options.synthesis(&Properties::new());
+        assert!(synthesis.italic && !synthesis.bold);
+        assert_eq!(options.extra_advance(synthesis), 0.0);
+
+        let mut italic_face = Properties::new();
+        italic_face.style = Style::Italic;
+        assert_eq!(options.synthesis(&italic_face), Synthesis::default());
+    }
+
+    #[test]
+    fn synthesis_can_be_disabled() {
+        let options = options(700, FontStyle::Italic, FontSynthesis::None);
+        assert_eq!(options.synthesis(&Properties::new()), Synthesis::default());
+    }
+
+    #[test]
+    fn control_chars_are_stripped() {
+        let (text, removed) = strip_chars("a\0b\x07c", true, &[]);
+        assert_eq!(text, "abc");
+        assert_eq!(removed, vec![1..2, 3..4]);
+
+        let (text, removed) = strip_chars("tab\tline\n\u{9c}", true, &[]);
+        assert_eq!(text, "tab\tline\n");
+        assert_eq!(removed, vec![9..11]);
+    }
+
+    #[test]
+    fn whitespace_collapses_across_texts() {
+        let first = "a  \n b ";
+        let collapsed = collapsed_whitespace(first, true, true);
+        assert_eq!(collapsed, vec![2, 3, 4]);
+        assert_eq!(strip_chars(first, true, &collapsed).0, "a b ");
+
+        // The next text starts with a space that the first one's trailing
+        // space swallows, leaving a single space between the words.
+        let options = TextOptions::new(&ComputedValues::default());
+        let after_space = ends_in_space(first, &options, true);
+        assert!(after_space);
+        let second = " \x07c";
+        let collapsed = collapsed_whitespace(second, true, after_space);
+        assert_eq!(
+            strip_chars(second, true, &collapsed),
+            ("c".into(), vec![0..1, 1..2])
+        );
+        assert!(!ends_in_space(second, &options, after_space));
+
+        let keep = TextOptions {
+            collapse_whitespace: false,
+            ..options
+        };
+        assert!(!ends_in_space(first, &keep, true));
+    }
+
+    #[test]
+    fn middle_ellipsis_keeps_the_extension() {
+        let path = "very/long/directory/name.txt";
+        let measure = |range: std::ops::Range<usize>| range.len() as f32 * 10.0;
+        let cut = |position| ellipsis_cut(path, 130.0, 10.0, position, measure);
+
+        let (head, tail) = cut(EllipsisPosition::Middle);
+        assert_eq!(&path[..head], "very/l");
+        assert_eq!(&path[tail..], "me.txt");
+        assert_eq!(cut(EllipsisPosition::End), (12, path.len()));
+        assert_eq!(cut(EllipsisPosition::Start), (0, path.len() - 12));
+
+        // Long text is cut without measuring every prefix of it.
+        let long = "x".repeat(1000);
+        let mut calls = 0;
+        let measure = |range: std::ops::Range<usize>| {
+            calls += 1;
+            range.len() as f32
+        };
+        let cut = ellipsis_cut(&long, 500.0, 10.0, EllipsisPosition::Middle, measure);
+        assert_eq!(cut, (245, 755));
+        assert!(calls < 50);
+    }
+
+    #[test]
+    fn decorations_follow_justified_spaces() {
+        illicit::child_env!(FontContext => test_font::font_context()).enter(|| {
+            let options = TextOptions::new(&ComputedValues::default());
+            let info = TextLayoutInfo::new("aa bb cc".into(), options, true);
+            let (char_width, space_width) = (8.0, 4.0);
+            let segments =
+                info.decoration_segments(0..8, &[(3..8, TextDecoration::Underline)], 2.0);
+            assert_eq!(
+                segments,
+                vec![DecorationSegment {
+                    kind: TextDecoration::Underline,
+                    range: 3..8,
+                    // After the first space, and over the second.
+                    x: 2.0 * char_width + space_width + 2.0,
+                    width: 4.0 * char_width + space_width + 2.0,
+                }]
+            );
+        });
+    }
+
+    #[test]
+    fn decorations_skip_what_the_ellipsis_left_out() {
+        illicit::child_env!(FontContext => test_font::font_context()).enter(|| {
+            let decorations = [
+                (0..2, TextDecoration::Underline),
+                (8..10, TextDecoration::LineThrough),
+            ];
+            let char_width = 8.0;
+            let ellipsized = |ellipsis_position| {
+                let options = TextOptions {
+                    ellipsis: Some("~"),
+                    ellipsis_position,
+                    ..TextOptions::new(&ComputedValues::default())
+                };
+                let info = TextLayoutInfo::new("abcdefghij".into(), options, true);
+                let (_, width, segments) = info
+                    .ellipsized_fragments(0..10, &decorations, 5.0 * char_width)
+                    .unwrap();
+                assert_eq!(width, 5.0 * char_width);
+                segments
+                    .into_iter()
+                    .map(|segment| (segment.range, segment.x, segment.width))
+                    .collect::<Vec<_>>()
+            };
+            // "abcd~"
+            assert_eq!(
+                ellipsized(EllipsisPosition::End),
+                vec![(0..2, 0.0, 2.0 * char_width)]
+            );
+            // "~ghij"
+            assert_eq!(
+                ellipsized(EllipsisPosition::Start),
+                vec![(8..10, 3.0 * char_width, 2.0 * char_width)]
+            );
+            // "ab~ij"
+            assert_eq!(
+                ellipsized(EllipsisPosition::Middle),
+                vec![
+                    (0..2, 0.0, 2.0 * char_width),
+                    (8..10, 3.0 * char_width, 2.0 * char_width)
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn blank_text_reserves_one_line() {
+        illicit::child_env!(FontContext => test_font::font_context()).enter(|| {
+            let options = TextOptions::new(&ComputedValues::default());
+            for &text in &["", " ", "\n"] {
+                let info = TextLayoutInfo::new(text.into(), options, false);
+                let mut state = TextState::new(&info);
+                let line = state.fill_line(100.0, true).unwrap();
+                assert_eq!((line.width, line.height), (0.0, options.size));
+                assert!(state.finished());
+                assert!(state.fill_line(100.0, true).is_none());
+            }
+
+            // A space that doesn't fit after other content on its line
+            // doesn't add a blank line after it.
+            let info = TextLayoutInfo::new(" ".into(), options, false);
+            let mut state = TextState::new(&info);
+            assert!(state.fill_line(0.0, false).is_none());
+            assert!(state.fill_line(100.0, true).is_none());
+            assert!(state.finished());
+        });
+    }
+
+    #[test]
+    fn long_url_wraps_at_zero_width_spaces() {
+        illicit::child_env!(FontContext => test_font::font_context()).enter(|| {
+            let options = TextOptions::new(&ComputedValues::default());
+            let char_width = test_font::CHAR_WIDTH * options.size;
+            let lines = |text: &str| {
+                let info = TextLayoutInfo::new(text.into(), options, true);
+                let mut state = TextState::new(&info);
+                let mut lines = vec![];
+                while !state.finished() {
+                    let line = state.fill_line(10.0 * char_width, true).unwrap();
+                    lines.push(text[info.source_range(line.range)].to_owned());
+                }
+                lines
+            };
+            assert_eq!(
+                lines("http://\u{200B}example.\u{200B}com/\u{200B}a/\u{200B}b"),
+                vec![
+                    "http://\u{200B}",
+                    "example.\u{200B}",
+                    "com/\u{200B}a/\u{200B}b"
+                ]
+            );
+            // Without them the URL is a single word, which overflows.
+            assert_eq!(
+                lines("http://example.com/a/b"),
+                vec!["http://example.com/a/b"]
+            );
+        });
+    }
+
+    #[test]
+    fn offsets_map_around_stripped_chars() {
+        // "a\0b\x07c" laid out as "abc"
+        let removed = vec![1..2, 3..4];
+        assert_eq!(to_source_offset(&removed, 0), 0);
+        assert_eq!(to_source_offset(&removed, 1), 2);
+        assert_eq!(to_source_offset(&removed, 2), 4);
+        assert_eq!(to_source_offset(&removed, 3), 5);
+        assert_eq!(to_layout_offset(&removed, 2), 1);
+        assert_eq!(to_layout_offset(&removed, 3), 2);
+        assert_eq!(to_layout_offset(&removed, 4), 2);
+        assert_eq!(to_layout_offset(&removed, 5), 3);
+    }
+
+    #[test]
+    fn missing_glyph_placeholder_has_fixed_width() {
+        let options = TextOptions::new(&ComputedValues {
+            missing_glyphs: MissingGlyphs::Placeholder,
+            ..Default::default()
+        });
+        let expected = options.size * MISSING_GLYPH_ADVANCE;
+        for &font_advance in &[0.0, 3.0, 40.0] {
+            let adjustment = options.adjustment(NOTDEF_GLYPH, font_advance, Synthesis::default());
+            assert_eq!(font_advance + adjustment, expected);
+        }
+        assert_eq!(options.adjustment(5, 3.0, Synthesis::default()), 0.0);
+
+        let options = TextOptions::new(&ComputedValues::default());
+        assert!(!options.is_placeholder(NOTDEF_GLYPH));
+        assert_eq!(
+            options.adjustment(NOTDEF_GLYPH, 3.0, Synthesis::default()),
+            0.0
+        );
+    }
+
+    /// Mirrors the glyph loops: the shaper's offsets are the sum of the
+    /// font advances, and adjustments accumulate on top of them.
+    fn glyph_positions(options: &TextOptions, advances: &[f32]) -> (Vec<f32>, f32) {
+        let mut offset = 0.0;
+        let mut shift = 0.0;
+        let mut positions = vec![];
+        for &advance in advances {
+            positions.push(options.snap(offset + shift));
+            shift += options.adjustment(5, advance, Synthesis::default());
+            offset += advance;
+        }
+        (positions, options.snap(offset + shift))
+    }
+
+    #[test]
+    fn integer_snap_places_glyphs_on_pixels() {
+        let advances = [3.3, 4.6, 2.5, 7.45];
+        let options = TextOptions::new(&ComputedValues {
+            text_positioning: TextPositioning::IntegerSnap,
+            ..Default::default()
+        });
+        let (positions, width) = glyph_positions(&options, &advances);
+        assert_eq!(positions, vec![0.0, 3.0, 8.0, 11.0]);
+        assert_eq!(width, 18.0);
+
+        let options = TextOptions::new(&ComputedValues::default());
+        let (positions, width) = glyph_positions(&options, &advances);
+        assert_eq!(positions[1], 3.3);
+        assert!((width - 17.85).abs() < 1e-4);
+    }
+
+    #[test]
+    fn descent_sign_does_not_shrink_lines() {
+        let mut metrics = Metrics {
+            units_per_em: 1000,
+            ascent: 800.0,
+            descent: -200.0,
+            line_gap: 0.0,
+            underline_position: -100.0,
+            underline_thickness: 50.0,
+            cap_height: 700.0,
+            x_height: 500.0,
+            bounding_box: Rect::zero(),
+        };
+        assert_eq!(line_height_of(&metrics), 1000.0);
+        metrics.descent = 200.0;
+        assert_eq!(line_height_of(&metrics), 1000.0);
+    }
+
+    #[test]
+    fn metrics_scale_to_text_size() {
+        let metrics = Metrics {
+            units_per_em: 1000,
+            ascent: 800.0,
+            descent: 200.0,
+            line_gap: 100.0,
+            underline_position: -100.0,
+            underline_thickness: 50.0,
+            cap_height: 700.0,
+            x_height: 500.0,
+            bounding_box: Rect::zero(),
+        };
+        let scaled = scale_metrics(&metrics, 20.0);
+        assert_eq!(scaled.ascent, 16.0);
+        assert_eq!(scaled.descent, -4.0);
+        assert_eq!(scaled.line_gap, 2.0);
+        assert_eq!(scaled.cap_height, 14.0);
+        assert_eq!(scaled.x_height, 10.0);
+    }
+
+    #[test]
+    fn doubling_base_size_doubles_text_heights() {
+        // The height of a line of text in the test font, which is its
+        // size, in a DOM styled with `base`.
+        let height_at = |base| {
+            let text: Rc<str> = "Hello".into();
+            let span = Node::new(Span::default(), vec![text.into()]);
+            let window = Node::new(Window::default(), vec![span.into()]);
+            let mut styles = StyleEngine::new();
+            styles.set_base_text_size(LogicalLength::new(base));
+            styles.update(window.clone(), size2(100.0, 100.0));
+            let layout = LayoutEngine::with_context(test_font::font_context())
+                .layout(window, size2(100.0, 100.0));
+            layout.children[0].layout.size.height
+        };
+        assert_eq!(height_at(16.0), 16.0);
+        assert_eq!(height_at(32.0), 32.0);
+        assert_eq!(height_at(24.0), 24.0);
+    }
+
+    #[test]
+    fn zero_width_spaces_become_soft_breaks() {
+        let url = "example.com/\u{200B}some/\x07\u{200B}path";
+        let (text, removed) = strip_chars(url, false, &[]);
+        assert_eq!(text, "example.com/some/\x07path");
+        assert_eq!(soft_breaks(url, &removed), vec![12, 18]);
+
+        let (text, removed) = strip_chars(url, true, &[]);
+        assert_eq!(text, "example.com/some/path");
+        assert_eq!(soft_breaks(url, &removed), vec![12, 17]);
+    }
+
+    #[test]
+    fn min_text_size_only_raises_small_text() {
+        let size = |text_size: f32| {
+            let values = ComputedValues {
+                text_size: LogicalLength::new(text_size),
+                ..Default::default()
+            };
+            TextOptions::new(&values).size
+        };
+        assert_eq!(size(8.0), 8.0);
+        let min = MinTextSize(Some(LogicalLength::new(12.0)));
+        illicit::child_env!(MinTextSize => min).enter(|| {
+            assert_eq!(size(8.0), 12.0);
+            assert_eq!(size(16.0), 16.0);
+        });
+    }
+
+    #[test]
+    fn bad_text_sizes_are_clamped() {
+        for &bad in &[0.0, -12.0, std::f32::NAN, std::f32::NEG_INFINITY] {
+            let options = TextOptions::new(&ComputedValues {
+                text_size: LogicalLength::new(bad),
+                ..Default::default()
+            });
+            assert_eq!(options.size, MIN_TEXT_SIZE);
+            // units_per_px as computed when laying out glyphs
+            assert!((1000.0 / options.size).is_finite());
+        }
+        let options = TextOptions::new(&ComputedValues {
+            text_size: LogicalLength::new(std::f32::INFINITY),
+            ..Default::default()
+        });
+        assert!(options.size.is_finite());
+    }
+
+    #[test]
+    fn rewrapping_measures_each_word_once() {
+        illicit::child_env!(FontContext => test_font::font_context()).enter(|| {
+            let options = TextOptions::new(&ComputedValues::default());
+            let text: Rc<str> = "lorem ipsum dolor sit amet ".repeat(200).into();
+            let info = TextLayoutInfo::new(text, options, true);
+            for width in (0..20).map(|i| 100.0 + 10.0 * i as f32) {
+                let mut state = TextState::new(&info);
+                while !state.finished() {
+                    state.fill_line(width, true).unwrap();
+                }
+            }
+            // One measurement per word, however often it was wrapped.
+            assert_eq!(info.words.borrow().len(), 1000);
+
+            let text: Rc<str> = "a ".repeat(WORD_CACHE_CAPACITY + 10).into();
+            let info = TextLayoutInfo::new(text, options, true);
+            let mut state = TextState::new(&info);
+            while !state.finished() {
+                state.fill_line(100.0, true).unwrap();
+            }
+            assert!(info.words.borrow().len() <= WORD_CACHE_CAPACITY);
+        });
+    }
+
+    #[test]
+    fn editing_a_paragraph_shapes_only_it_again() {
+        let fonts = test_font::font_context();
+        illicit::child_env!(FontContext => fonts.clone()).enter(|| {
+            let options = TextOptions {
+                collapse_whitespace: false,
+                ..TextOptions::new(&ComputedValues::default())
+            };
+            let mut paragraphs = vec!["lorem ipsum dolor sit amet ".repeat(10); 100];
+            let layout = |paragraphs: &[String]| {
+                let info = TextLayoutInfo::new(paragraphs.join("\n").into(), options, true);
+                let mut state = TextState::new(&info);
+                while !state.finished() {
+                    state.fill_line(400.0, true).unwrap();
+                }
+            };
+            layout(&paragraphs);
+            // All paragraphs but the last end in a line break, and are
+            // shaped once between them.
+            let shaped = fonts.paragraphs.len();
+            assert_eq!(shaped, 2);
+
+            let edits = 5;
+            for edit in 0..edits {
+                paragraphs[50] = format!("edited {}", edit);
+                layout(&paragraphs);
+            }
+            // Only the edited paragraph was shaped again each time.
+            assert_eq!(fonts.paragraphs.len(), shaped + edits);
+
+            // Paragraphs line up the same as text shaped in one piece.
+            let text: Rc<str> = paragraphs.join("\n").into();
+            let whole = TextState::measure(
+                &mut ShapedText::new(text.clone(), options.size, &fonts.collection),
+                &options,
+                0,
+                text.len(),
+            );
+            let split =
+                TextLayoutInfo::new(text.clone(), options, true).measure_width(0, text.len());
+            assert!((whole.0 - split).abs() < 0.01);
+        });
+    }
+
+    #[test]
+    fn whitespace_markers_sit_on_space_glyphs() {
+        illicit::child_env!(FontContext => test_font::font_context()).enter(|| {
+            let text = "ab cd  e";
+            let hidden = TextOptions {
+                collapse_whitespace: false,
+                ..TextOptions::new(&ComputedValues::default())
+            };
+            let shown = TextOptions {
+                show_whitespace: true,
+                ..hidden
+            };
+            let info = TextLayoutInfo::new(text.into(), shown, true);
+            let line = TextState::new(&info).fill_line(1000.0, true).unwrap();
+            let markers = info.whitespace_markers(line.range.clone());
+            let space_offsets: Vec<f32> = line
+                .fragments
+                .iter()
+                .flat_map(|fragment| {
+                    let space = fragment.font.font.glyph_for_char(' ');
+                    fragment
+                        .glyphs
+                        .iter()
+                        .filter(move |glyph| Some(glyph.index) == space)
+                        .map(|glyph| glyph.offset.x)
+                })
+                .collect();
+            assert_eq!(markers.len(), 3);
+            assert_eq!(space_offsets.len(), 3);
+            for (marker, x) in markers.iter().zip(space_offsets) {
+                assert!((marker.x - x).abs() < 0.01);
+                assert_eq!(marker.width, 4.0);
+            }
+
+            // Marking whitespace doesn't move anything, and is off unless
+            // asked for.
+            let info = TextLayoutInfo::new(text.into(), hidden, true);
+            let unmarked = TextState::new(&info).fill_line(1000.0, true).unwrap();
+            assert_eq!(unmarked.width, line.width);
+            assert!(info.whitespace_markers(unmarked.range).is_empty());
+        });
+    }
+
+    #[test]
+    fn content_widths_are_the_longest_word_and_the_whole_line() {
+        illicit::child_env!(FontContext => test_font::font_context()).enter(|| {
+            let options = TextOptions::new(&ComputedValues::default());
+            let info = TextLayoutInfo::new("hello wonderful".into(), options, true);
+            let (min, max) = info.content_widths();
+            assert_eq!(min, 9.0 * 8.0);
+            assert_eq!(max, 14.0 * 8.0 + 4.0);
+            assert!((min - info.measure_width(6, 15)).abs() < 0.5);
+            assert!((max - info.measure_width(0, 15)).abs() < 0.5);
+
+            let trailing = TextLayoutInfo::new("hello wonderful ".into(), options, true);
+            assert_eq!(trailing.content_widths(), (min, max));
+        });
+    }
+
+    #[test]
+    #[ignore] // needs system fonts
+    fn font_stacks_pick_fonts_per_element() {
+        illicit::child_env!(FontContext => FontContext::new()).enter(|| {
+            let font_of = |font_stack: &'static [&'static str]| {
+                let options = TextOptions {
+                    font_stack: Some(font_stack),
+                    ..TextOptions::new(&ComputedValues::default())
+                };
+                let info = TextLayoutInfo::new("a".into(), options, true);
+                let line = TextState::new(&info).fill_line(100.0, true).unwrap();
+                line.fragments[0].font.font.full_name()
+            };
+            assert_ne!(font_of(&["monospace"]), font_of(&["serif"]));
+        });
+    }
+
+    #[test]
+    #[ignore] // needs system fonts
+    fn text_without_fallback_uses_one_font() {
+        illicit::child_env!(FontContext => FontContext::new()).enter(|| {
+            let options = TextOptions {
+                font_fallback: false,
+                ..TextOptions::new(&ComputedValues::default())
+            };
+            let measure = || {
+                let info = TextLayoutInfo::new("a \u{1F600} \u{4E2D}".into(), options, true);
+                TextState::new(&info).fill_line(1000.0, true).unwrap()
+            };
+            let line = measure();
+            let first = line.fragments[0].font.font.full_name();
+            assert!(line
+                .fragments
+                .iter()
+                .all(|fragment| fragment.font.font.full_name() == first));
+            assert_eq!(line.width, measure().width);
+        });
+    }
+
+    #[test]
+    fn text_without_fallback_measures_missing_glyphs_in_the_font() {
+        illicit::child_env!(FontContext => test_font::font_context()).enter(|| {
+            let options = TextOptions {
+                font_fallback: false,
+                ..TextOptions::new(&ComputedValues::default())
+            };
+            let info = TextLayoutInfo::new("a \u{1F600} \u{4E2D}".into(), options, true);
+            let line = TextState::new(&info).fill_line(1000.0, true).unwrap();
+            // Neither the emoji nor the ideograph is in the test font, so
+            // both are its missing glyph, as wide as any other character.
+            let missing = line
+                .fragments
+                .iter()
+                .flat_map(|fragment| &fragment.glyphs)
+                .filter(|glyph| glyph.index == NOTDEF_GLYPH)
+                .count();
+            assert_eq!(missing, 2);
+            let char_width = options.size * test_font::CHAR_WIDTH;
+            let space_width = options.size * test_font::SPACE_WIDTH;
+            assert_eq!(line.width, 3.0 * char_width + 2.0 * space_width);
+        });
+    }
+
+    #[test]
+    fn kept_spaces_each_take_up_room() {
+        illicit::child_env!(FontContext => test_font::font_context()).enter(|| {
+            let width = |collapse_whitespace| {
+                let options = TextOptions {
+                    collapse_whitespace,
+                    ..TextOptions::new(&ComputedValues::default())
+                };
+                let info = TextLayoutInfo::new("a    b".into(), options, true);
+                TextState::new(&info).fill_line(1000.0, true).unwrap().width
+            };
+            // Three more spaces than the one left after collapsing.
+            let space_width = 16.0 * test_font::SPACE_WIDTH;
+            assert_eq!(width(false), width(true) + 3.0 * space_width);
+        });
+    }
+
+    #[test]
+    fn min_line_height_centers_the_glyphs() {
+        illicit::child_env!(FontContext => test_font::font_context()).enter(|| {
+            let options = |min_line_height| TextOptions {
+                size: 12.0,
+                min_line_height,
+                ..TextOptions::new(&ComputedValues::default())
+            };
+            let line = |min_line_height| {
+                let info = TextLayoutInfo::new("ab".into(), options(min_line_height), true);
+                TextState::new(&info).fill_line(1000.0, true).unwrap()
+            };
+            let (plain, tall) = (line(None), line(Some(20.0)));
+            assert_eq!(plain.height, 12.0);
+            assert_eq!(tall.height, 20.0);
+            // Half of the 8px the line grew by goes above the glyphs.
+            assert!((tall.ascender - (12.0 * test_font::ASCENT + 4.0)).abs() < 1e-4);
+            let y = |line: &FilledLine| line.fragments[0].glyphs[0].offset.y;
+            assert!((y(&tall) - y(&plain) - 4.0).abs() < 1e-4);
+            assert_eq!(line_height(&options(Some(20.0))), 20.0);
+            assert_eq!(line_height(&options(Some(8.0))), 12.0);
+        });
+    }
+
+    #[test]
+    fn break_word_splits_a_long_word_between_clusters() {
+        illicit::child_env!(FontContext => test_font::font_context()).enter(|| {
+            let options = TextOptions {
+                overflow_wrap: OverflowWrap::BreakWord,
+                ..TextOptions::new(&ComputedValues::default())
+            };
+            // Three 8px characters fit in 30px.
+            let text = "abcdefghij";
+            let info = TextLayoutInfo::new(text.into(), options, true);
+            let mut state = TextState::new(&info);
+            let mut lines = vec![];
+            while !state.finished() {
+                let line = state.fill_line(30.0, true).unwrap();
+                lines.push(&text[info.source_range(line.range)]);
+            }
+            assert_eq!(lines, vec!["abc", "def", "ghi", "j"]);
+        });
+    }
+
+    #[test]
+    fn kept_spaces_indent_the_line() {
+        illicit::child_env!(FontContext => test_font::font_context()).enter(|| {
+            let options = TextOptions {
+                collapse_whitespace: false,
+                show_whitespace: true,
+                ..TextOptions::new(&ComputedValues::default())
+            };
+            let info = TextLayoutInfo::new("    a".into(), options, true);
+            let line = TextState::new(&info).fill_line(1000.0, true).unwrap();
+            assert_eq!(line.range, 0..5);
+            let last_glyph = line
+                .fragments
+                .iter()
+                .flat_map(|fragment| fragment.glyphs.iter())
+                .last()
+                .unwrap();
+            assert_eq!(last_glyph.offset.x, 4.0 * 16.0 * test_font::SPACE_WIDTH);
+            assert_eq!(info.whitespace_markers(line.range).len(), 4);
+        });
+    }
+
+    #[test]
+    fn caret_offsets_skip_the_inside_of_clusters() {
+        illicit::child_env!(FontContext => test_font::font_context()).enter(|| {
+            let options = TextOptions::new(&ComputedValues::default());
+            // The two halves of the flag are 4 bytes each and missing
+            // from the test font, so the flag spans 8..24px.
+            let text = "a\u{1F1E9}\u{1F1EA}b";
+            let info = TextLayoutInfo::new(text.into(), options, true);
+            assert_eq!(info.offset_at_x(0..10, 15.0), 1);
+            assert_eq!(info.offset_at_x(0..10, 17.0), 9);
+            assert_eq!(info.offset_at_x(0..10, 30.0), 10);
+        });
+    }
+
+    #[test]
+    fn ink_covers_the_glyph_boxes() {
+        illicit::child_env!(FontContext => test_font::font_context()).enter(|| {
+            let options = TextOptions::new(&ComputedValues::default());
+            let ink = |text: &str| {
+                let info = TextLayoutInfo::new(text.into(), options, true);
+                let line = TextState::new(&info).fill_line(100.0, true).unwrap();
+                assert_eq!(line.height, 16.0);
+                ink_bounds(&line.fragments, options.size)
+            };
+            // Each box is 50..450 units across and 700 tall on the
+            // baseline, 12.8px down the 16px line.
+            let boxes = ink("ab").unwrap();
+            let close = |a: f32, b: f32| (a - b).abs() < 1e-4;
+            assert!(close(boxes.min_x(), 0.8) && close(boxes.max_x(), 15.2));
+            assert!(close(boxes.min_y(), 1.6) && close(boxes.max_y(), 12.8));
+            assert!(close(boxes.size.height, 11.2));
+            assert!(ink("  ").is_none());
+            assert!(ink_bounds(&[], options.size).is_none());
+        });
+    }
+}
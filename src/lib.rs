@@ -32,7 +32,7 @@
 pub use moxie_native_style::define_style;
 
 pub mod dom;
-mod layout;
+pub mod layout;
 #[doc(hidden)]
 pub mod moxie;
 pub mod prelude;
@@ -41,5 +41,7 @@ mod runtime;
 pub mod style;
 mod util;
 
+#[cfg(feature = "raster")]
+pub use render::raster;
 pub use runtime::Runtime;
 pub use util::color::Color;
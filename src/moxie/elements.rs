@@ -2,6 +2,7 @@ use crate::dom::element::{Attribute, Element, Event, HasAttribute, HasEvent};
 use crate::dom::Node;
 use crate::util::event_handler::EventHandler;
 use moxie::*;
+use std::rc::Rc;
 
 /// Builder pattern for creating a DOM node, typically used from the
 /// mox! macro.
@@ -86,19 +87,32 @@ where
     }
 }
 
-impl<Parent> IntoChildren<Parent> for String
+impl<Parent> IntoChildren<Parent> for Rc<str>
 where
     Parent: Element,
-    Parent::Child: From<String>,
+    Parent::Child: From<Rc<str>>,
 {
-    type Item = String;
-    type IntoIter = std::iter::Once<String>;
+    type Item = Rc<str>;
+    type IntoIter = std::iter::Once<Rc<str>>;
 
     fn into_children(self) -> Self::IntoIter {
         std::iter::once(self)
     }
 }
 
+impl<Parent> IntoChildren<Parent> for String
+where
+    Parent: Element,
+    Parent::Child: From<Rc<str>>,
+{
+    type Item = Rc<str>;
+    type IntoIter = std::iter::Once<Rc<str>>;
+
+    fn into_children(self) -> Self::IntoIter {
+        std::iter::once(self.into())
+    }
+}
+
 impl<Elt> Builder<Elt>
 where
     Elt: Element,
@@ -212,6 +226,14 @@ macro_rules! button {
     };
 }
 
+/// Flexible gap which pushes its siblings apart.
+#[macro_export]
+macro_rules! spacer {
+    ($with_elem:expr) => {
+        $crate::moxie::Builder::<$crate::dom::Spacer>::create($with_elem)
+    };
+}
+
 /// Container for inline text.
 #[macro_export]
 macro_rules! span {
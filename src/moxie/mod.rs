@@ -17,6 +17,8 @@ mod elements;
 pub use attributes::*;
 pub use elements::Builder;
 
+use std::rc::Rc;
+
 /// Used by the mox! macro for free-standing text, which is then passed
 /// to `Builder::add_content`.
 #[doc(hidden)]
@@ -28,6 +30,6 @@ macro_rules! text {
 }
 
 /// Text node
-pub fn text(s: impl ToString) -> String {
-    s.to_string()
+pub fn text(s: impl ToString) -> Rc<str> {
+    s.to_string().into()
 }
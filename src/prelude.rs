@@ -1,5 +1,5 @@
 // For naming the type result of mox!()
-pub use crate::dom::{events::*, App, Button, Node, Span, View, Window};
+pub use crate::dom::{events::*, App, Button, Node, Spacer, Span, View, Window};
 // Required for attributes to work
 pub use crate::moxie::*;
 // For easily defining styles
@@ -7,6 +7,6 @@ pub use crate::style::{Direction, Display, Style, Value};
 pub use crate::Color;
 pub use moxie_native_style::define_style;
 // Required for mox to work
-pub use crate::{app, button, span, text, view, window};
+pub use crate::{app, button, spacer, span, text, view, window};
 // Re-export important moxie pieces
 pub use moxie::{__memo_state_impl, memo, mox, state, Key};
@@ -1,10 +1,12 @@
 use crate::dom::input::InputEvent;
 use crate::dom::{Node, Window};
 use crate::layout::{
-    LayoutEngine, LayoutText, LayoutTreeNode, LogicalPixel, LogicalSideOffsets, RenderData,
+    pixel_grid, FontContext, LayoutEngine, LayoutText, LayoutTreeNode, LogicalPixel,
+    LogicalSideOffsets, RenderData, Synthesis, MISSING_GLYPH_ADVANCE,
 };
 use crate::style::StyleEngine;
 use crate::util::equal_rc::EqualRc;
+use crate::Color;
 use gleam::gl;
 use skribo::FontRef;
 use std::collections::HashMap;
@@ -13,10 +15,12 @@ use std::sync::mpsc;
 use webrender::{
     api::{
         units::Au, units::DeviceIntRect, units::DevicePixel, units::LayoutPixel,
-        units::LayoutSideOffsets, BorderDetails, BorderRadius, BorderSide, BorderStyle, ClipMode,
-        ColorF, CommonItemProperties, ComplexClipRegion, DisplayListBuilder, DocumentId, Epoch,
-        FontInstanceKey, FontKey, GlyphInstance, NormalBorder, PipelineId, PrimitiveFlags,
-        RenderApi, RenderNotifier, SpaceAndClipInfo, SpatialId, Transaction,
+        units::LayoutSideOffsets, units::LayoutTransform, BorderDetails, BorderRadius, BorderSide,
+        BorderStyle, ClipMode, CommonItemProperties, ComplexClipRegion, DisplayListBuilder,
+        DocumentId, Epoch, FontInstanceFlags, FontInstanceKey, FontInstanceOptions, FontKey,
+        GlyphInstance, NormalBorder, PipelineId, PrimitiveFlags, PropertyBinding,
+        ReferenceFrameKind, RenderApi, RenderNotifier, SpaceAndClipInfo, SyntheticItalics,
+        Transaction, TransformStyle,
     },
     euclid::{point2, size2, Point2D, Rect, Scale, Size2D},
     Renderer, RendererOptions,
@@ -61,19 +65,29 @@ pub struct Context {
     client_size: Size2D<i32, DevicePixel>,
     dpi_scale: f32,
     fonts: HashMap<String, FontKey>,
-    font_instances: HashMap<(FontKey, usize), FontInstanceKey>,
+    font_instances: HashMap<(FontKey, usize, Synthesis), FontInstanceKey>,
 }
 
 fn convert_offsets(input: LogicalSideOffsets) -> LayoutSideOffsets {
     LayoutSideOffsets::new(input.top, input.right, input.bottom, input.left)
 }
 
+fn convert_radius(input: &crate::style::BorderRadius) -> BorderRadius {
+    BorderRadius {
+        top_left: input.top_left * Scale::new(1.0),
+        top_right: input.top_right * Scale::new(1.0),
+        bottom_left: input.bottom_left * Scale::new(1.0),
+        bottom_right: input.bottom_right * Scale::new(1.0),
+    }
+}
+
 impl Context {
     pub fn new(
         gl: Rc<dyn gl::Gl>,
         parent_window: &WinitWindow,
         events_proxy: EventLoopProxy<()>,
         window: Node<Window>,
+        fonts: FontContext,
     ) -> Context {
         let (tx, rx) = mpsc::channel();
         let notifier = Box::new(Notifier { events_proxy, tx });
@@ -83,11 +97,24 @@ impl Context {
         let client_size =
             Size2D::<i32, DevicePixel>::new(inner_size.width as i32, inner_size.height as i32);
 
+        // The window is laid out once up front so that the renderer clears
+        // to its background rather than flashing white before the first
+        // frame. Later changes to the background are drawn by `render`.
+        let content_size = client_size.to_f32() / Scale::new(dpi_scale) * Scale::new(1.0);
+        let mut style_engine = StyleEngine::new();
+        style_engine.set_text_size_rounding(Some(dpi_scale));
+        style_engine.update(window.clone(), content_size);
+        let mut layout_engine = LayoutEngine::with_context(fonts);
+        layout_engine.set_pixel_snapping(Some(dpi_scale));
+        let clear_color = layout_engine
+            .layout(window.clone(), content_size)
+            .clear_color();
+
         let (renderer, sender) = Renderer::new(
             gl,
             notifier.clone(),
             RendererOptions {
-                clear_color: Some(ColorF::new(1.0, 1.0, 1.0, 1.0)),
+                clear_color: Some(clear_color.into()),
                 device_pixel_ratio: dpi_scale,
                 ..Default::default()
             },
@@ -104,8 +131,8 @@ impl Context {
             rx,
             renderer,
             window,
-            layout_engine: LayoutEngine::new(),
-            style_engine: StyleEngine::new(),
+            layout_engine,
+            style_engine,
             client_size,
             dpi_scale,
             fonts: HashMap::new(),
@@ -121,6 +148,10 @@ impl Context {
 
     pub fn resize(&mut self, size: PhysicalSize, dpi_scale: f32) {
         self.client_size = size2(size.width as i32, size.height as i32);
+        // Lays everything out again if the pixel grid changed, with text
+        // sizes rounded to it on the next styling.
+        self.layout_engine.set_pixel_snapping(Some(dpi_scale));
+        self.style_engine.set_text_size_rounding(Some(dpi_scale));
         self.dpi_scale = dpi_scale;
     }
 
@@ -141,21 +172,29 @@ impl Context {
         &mut self,
         key: FontKey,
         size: usize,
+        synthesis: Synthesis,
         txn: &mut Transaction,
     ) -> FontInstanceKey {
-        if let Some(&instance) = self.font_instances.get(&(key, size)) {
+        if let Some(&instance) = self.font_instances.get(&(key, size, synthesis)) {
             return instance;
         }
         let instance = self.api.generate_font_instance_key();
+        let mut options = FontInstanceOptions::default();
+        if synthesis.bold {
+            options.flags |= FontInstanceFlags::SYNTHETIC_BOLD;
+        }
+        if synthesis.italic {
+            options.synthetic_italics = SyntheticItalics::enabled();
+        }
         txn.add_font_instance(
             instance,
             key,
             Au::from_f64_px(size as f64),
-            None,
+            Some(options),
             None,
             vec![],
         );
-        self.font_instances.insert((key, size), instance);
+        self.font_instances.insert((key, size, synthesis), instance);
 
         instance
     }
@@ -165,17 +204,36 @@ impl Context {
         pipeline_id: PipelineId,
         builder: &mut DisplayListBuilder,
         transaction: &mut Transaction,
+        space_and_clip: SpaceAndClipInfo,
         position: Point2D<f32, LogicalPixel>,
         layout: &EqualRc<LayoutTreeNode>,
     ) {
-        let rect = Rect::new(position, layout.size) * Scale::new(1.0);
-
-        let space_and_clip = SpaceAndClipInfo::root_scroll(pipeline_id);
+        let rect = pixel_grid::round_rect(Rect::new(position, layout.size), self.dpi_scale)
+            * Scale::new(1.0);
 
         match layout.render {
             RenderData::Node(ref node) => {
                 let values = node.computed_values().get().unwrap();
 
+                // Transformed nodes get a reference frame of their own, so
+                // the node and everything below it are drawn through it.
+                let transform = layout.transform_at(position);
+                let space_and_clip = match transform {
+                    Some(t) => SpaceAndClipInfo {
+                        spatial_id: builder.push_reference_frame(
+                            point2(0.0, 0.0),
+                            space_and_clip.spatial_id,
+                            TransformStyle::Flat,
+                            PropertyBinding::Value(LayoutTransform::row_major_2d(
+                                t.m11, t.m12, t.m21, t.m22, t.m31, t.m32,
+                            )),
+                            ReferenceFrameKind::Transform,
+                        ),
+                        clip_id: space_and_clip.clip_id,
+                    },
+                    None => space_and_clip,
+                };
+
                 if values.border_color.alpha > 0
                     && values.border_thickness != LogicalSideOffsets::zero()
                 {
@@ -193,68 +251,125 @@ impl Context {
                             right: side,
                             top: side,
                             bottom: side,
-                            radius: BorderRadius::uniform(values.border_radius.get()),
+                            radius: convert_radius(&layout.border_radius),
                             do_aa: true,
                         }),
                     )
                 }
 
-                if values.background_color.alpha > 0 {
+                let background = match values.placeholder {
+                    Some(placeholder) if layout.children.is_empty() => placeholder,
+                    _ => values.background_color,
+                };
+                if background.alpha > 0 {
                     let rect = rect.inner_rect(convert_offsets(values.border_thickness));
-                    let item_props = if values.border_radius.get() > 0.0 {
-                        let region = ComplexClipRegion::new(
-                            rect,
-                            BorderRadius::uniform(values.border_radius.get()),
-                            ClipMode::Clip,
-                        );
-                        let clip = builder.define_clip(
-                            &SpaceAndClipInfo::root_scroll(pipeline_id),
-                            rect,
-                            vec![region],
-                            None,
-                        );
+                    // The background fills the inside of the border, whose
+                    // corners are rounded less by the border's thickness.
+                    let radius = layout.border_radius.inner(values.border_thickness);
+                    let item_props = if !radius.is_zero() {
+                        let region =
+                            ComplexClipRegion::new(rect, convert_radius(&radius), ClipMode::Clip);
+                        let clip = builder.define_clip(&space_and_clip, rect, vec![region], None);
                         CommonItemProperties::new(
                             rect,
                             SpaceAndClipInfo {
-                                spatial_id: SpatialId::root_scroll_node(pipeline_id),
+                                spatial_id: space_and_clip.spatial_id,
                                 clip_id: clip,
                             },
                         )
                     } else {
                         CommonItemProperties::new(rect, space_and_clip)
                     };
-                    builder.push_rect(&item_props, values.background_color.into());
+                    builder.push_rect(&item_props, background.into());
                 }
 
+                let child_space_and_clip = if let Some(ref clip) = layout.clip {
+                    let clip_rect = clip.rect.translate(position.to_vector());
+                    let clip_rect =
+                        pixel_grid::round_rect(clip_rect, self.dpi_scale) * Scale::new(1.0);
+                    let regions = if !clip.radius.is_zero() {
+                        vec![ComplexClipRegion::new(
+                            clip_rect,
+                            convert_radius(&clip.radius),
+                            ClipMode::Clip,
+                        )]
+                    } else {
+                        vec![]
+                    };
+                    SpaceAndClipInfo {
+                        spatial_id: space_and_clip.spatial_id,
+                        clip_id: builder.define_clip(&space_and_clip, clip_rect, regions, None),
+                    }
+                } else {
+                    space_and_clip
+                };
+
                 for layout in &layout.children {
                     self.render_child(
                         pipeline_id,
                         builder,
                         transaction,
+                        child_space_and_clip,
                         position + layout.position.to_vector(),
                         &layout.layout,
                     );
                 }
+
+                if transform.is_some() {
+                    builder.pop_reference_frame();
+                }
             }
             RenderData::Text {
                 text:
                     LayoutText {
                         ref fragments,
                         size,
+                        baseline,
+                        style,
+                        ref whitespace,
+                        ref decorations,
+                        ..
                     },
                 ref parent,
             } => {
                 let values = parent.computed_values().get().unwrap();
-                let color = values.text_color;
+                let color = style.text_color.unwrap_or(values.text_color);
                 builder.push_simple_stacking_context(
                     point2(0.0, 0.0),
                     space_and_clip.spatial_id,
                     PrimitiveFlags::IS_BACKFACE_VISIBLE,
                 );
                 for fragment in fragments {
+                    for glyph in fragment.glyphs.iter().filter(|glyph| glyph.missing) {
+                        // Outline a box for each missing glyph, sitting on the baseline.
+                        let advance = size * MISSING_GLYPH_ADVANCE;
+                        let origin = position + glyph.offset.to_vector();
+                        let rect = Rect::new(
+                            point2(origin.x + advance * 0.1, origin.y - size * 0.7),
+                            size2(advance * 0.8, size * 0.7),
+                        ) * Scale::new(1.0);
+                        let side = BorderSide {
+                            style: BorderStyle::Solid,
+                            color: color.into(),
+                        };
+                        builder.push_border(
+                            &CommonItemProperties::new(rect, space_and_clip),
+                            rect,
+                            LayoutSideOffsets::new_all_same(1.0),
+                            BorderDetails::Normal(NormalBorder {
+                                left: side,
+                                right: side,
+                                top: side,
+                                bottom: side,
+                                radius: BorderRadius::zero(),
+                                do_aa: true,
+                            }),
+                        );
+                    }
                     let glyphs = fragment
                         .glyphs
                         .iter()
+                        .filter(|glyph| !glyph.missing)
                         .map(|glyph| {
                             let pos = position + glyph.offset.to_vector();
                             GlyphInstance {
@@ -264,7 +379,12 @@ impl Context {
                         })
                         .collect::<Vec<_>>();
                     let font_key = self.get_font(&fragment.font, transaction);
-                    let key = self.get_font_instance(font_key, size as usize, transaction);
+                    let key = self.get_font_instance(
+                        font_key,
+                        size as usize,
+                        fragment.synthesis,
+                        transaction,
+                    );
                     builder.push_text(
                         &CommonItemProperties::new(rect, space_and_clip),
                         rect,
@@ -274,6 +394,26 @@ impl Context {
                         None,
                     );
                 }
+                let marker_color = Color {
+                    alpha: color.alpha / 2,
+                    ..color
+                };
+                for marker in whitespace {
+                    let rect = marker.mark(size, baseline).translate(position.to_vector())
+                        * Scale::new(1.0);
+                    builder.push_rect(
+                        &CommonItemProperties::new(rect, space_and_clip),
+                        marker_color.into(),
+                    );
+                }
+                for segment in decorations {
+                    let rect = segment.line(size, baseline).translate(position.to_vector())
+                        * Scale::new(1.0);
+                    builder.push_rect(
+                        &CommonItemProperties::new(rect, space_and_clip),
+                        color.into(),
+                    );
+                }
                 builder.pop_stacking_context();
             }
         }
@@ -296,11 +436,17 @@ impl Context {
             .layout_engine
             .layout(self.window.clone(), content_size * Scale::new(1.0));
 
+        let root_rect = Rect::new(Point2D::zero(), content_size);
+        builder.push_rect(
+            &CommonItemProperties::new(root_rect, SpaceAndClipInfo::root_scroll(pipeline_id)),
+            root_layout.clear_color().into(),
+        );
         for layout in &root_layout.children {
             self.render_child(
                 pipeline_id,
                 &mut builder,
                 &mut transaction,
+                SpaceAndClipInfo::root_scroll(pipeline_id),
                 layout.position,
                 &layout.layout,
             );
@@ -321,40 +467,6 @@ impl Context {
         let _ = self.renderer.flush_pipeline_info();
     }
 
-    pub fn process_child(
-        &self,
-        event: &InputEvent,
-        position: Point2D<f32, LogicalPixel>,
-        layout: &EqualRc<LayoutTreeNode>,
-    ) -> bool {
-        let rect = Rect::new(position, layout.size);
-
-        if let RenderData::Node(ref node) = layout.render {
-            for layout in &layout.children {
-                if self.process_child(
-                    event,
-                    position + layout.position.to_vector(),
-                    &layout.layout,
-                ) {
-                    return true;
-                }
-            }
-
-            let do_process = match event.get_position() {
-                Some((x, y)) => rect.contains(point2(x, y)),
-                None => true,
-            };
-
-            if do_process {
-                if node.process(event) {
-                    return true;
-                }
-            }
-        }
-
-        false
-    }
-
     pub fn process(&mut self, event: &InputEvent) -> bool {
         let client_size = self.client_size;
         let dpi_scale = Scale::new(self.dpi_scale);
@@ -367,9 +479,12 @@ impl Context {
             .layout_engine
             .layout(self.window.clone(), content_size * Scale::new(1.0));
 
+        let point = event.get_position().map(|(x, y)| point2(x, y));
         for layout in &root_layout.children {
-            if self.process_child(event, layout.position, &layout.layout) {
-                return true;
+            for node in layout.layout.event_targets(layout.position, point) {
+                if node.process(event) {
+                    return true;
+                }
             }
         }
 
@@ -2,5 +2,7 @@
 //! and processing user input queries against it.
 
 pub mod context;
+#[cfg(feature = "raster")]
+pub mod raster;
 
 pub use context::Context;
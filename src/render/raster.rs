@@ -0,0 +1,400 @@
+//! A software rasterizer for layout trees, for comparing renderings
+//! against golden images in tests without a GPU. It draws what the
+//! display list would, minus the parts webrender adds on top: corners
+//! are square, synthesized bold and italic are left out, and transformed
+//! nodes are drawn untransformed.
+
+use crate::layout::{
+    pixel_grid, LayoutText, LayoutTreeNode, LogicalPoint, LogicalRect, RenderData,
+    MISSING_GLYPH_ADVANCE,
+};
+use crate::style::ComputedValues;
+use crate::Color;
+use euclid::{point2, size2, Rect};
+use font_kit::canvas::{Canvas, Format, RasterizationOptions};
+use font_kit::hinting::HintingOptions;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+/// An opaque RGBA image, 8 bits per channel, in rows from the top.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+type DeviceRect = Rect<i32, ()>;
+
+impl Image {
+    fn new(width: u32, height: u32, clear: Color) -> Image {
+        let pixel = [clear.red, clear.green, clear.blue, 255];
+        Image {
+            width,
+            height,
+            pixels: pixel
+                .iter()
+                .cloned()
+                .cycle()
+                .take((width * height * 4) as usize)
+                .collect(),
+        }
+    }
+
+    pub fn load_png(path: &Path) -> io::Result<Image> {
+        let decoder = png::Decoder::new(File::open(path)?);
+        let (info, mut reader) = decoder.read_info().map_err(to_io_error)?;
+        if info.color_type != png::ColorType::RGBA || info.bit_depth != png::BitDepth::Eight {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "golden images must be 8 bit RGBA",
+            ));
+        }
+        let mut pixels = vec![0; info.buffer_size()];
+        reader.next_frame(&mut pixels).map_err(to_io_error)?;
+        Ok(Image {
+            width: info.width,
+            height: info.height,
+            pixels,
+        })
+    }
+
+    pub fn save_png(&self, path: &Path) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(File::create(path)?, self.width, self.height);
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        Ok(())
+    }
+
+    /// Whether both images have the same size and no channel of any
+    /// pixel differs by more than `tolerance`, which leaves room for
+    /// the differences in antialiasing between font backends.
+    pub fn matches(&self, other: &Image, tolerance: u8) -> bool {
+        self.width == other.width
+            && self.height == other.height
+            && self
+                .pixels
+                .iter()
+                .zip(&other.pixels)
+                .all(|(a, b)| (i16::from(*a) - i16::from(*b)).abs() <= i16::from(tolerance))
+    }
+
+    /// Blends `color` into the pixel at `x, y` with `coverage` out of
+    /// 255, ignoring pixels outside of the image.
+    fn blend(&mut self, x: i32, y: i32, color: Color, coverage: u8) {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return;
+        }
+        let alpha = u32::from(color.alpha) * u32::from(coverage) / 255;
+        let start = ((y as u32 * self.width + x as u32) * 4) as usize;
+        let pixel = &mut self.pixels[start..start + 3];
+        for (channel, source) in pixel.iter_mut().zip(&[color.red, color.green, color.blue]) {
+            let blended = u32::from(*source) * alpha + u32::from(*channel) * (255 - alpha);
+            *channel = (blended / 255) as u8;
+        }
+    }
+
+    /// Blends `color` into `rect` by the coverage `mask` gives each of
+    /// its pixels, within `clip`.
+    fn blend_mask(
+        &mut self,
+        rect: DeviceRect,
+        clip: DeviceRect,
+        color: Color,
+        mask: impl Fn(i32, i32) -> u8,
+    ) {
+        if let Some(visible) = rect.intersection(&clip) {
+            for y in visible.min_y()..visible.max_y() {
+                for x in visible.min_x()..visible.max_x() {
+                    let coverage = mask(x - rect.min_x(), y - rect.min_y());
+                    if coverage > 0 {
+                        self.blend(x, y, color, coverage);
+                    }
+                }
+            }
+        }
+    }
+
+    fn fill(&mut self, rect: DeviceRect, clip: DeviceRect, color: Color) {
+        if color.alpha == 0 {
+            return;
+        }
+        self.blend_mask(rect, clip, color, |_, _| 255);
+    }
+}
+
+fn to_io_error(error: png::DecodingError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
+
+/// Snaps a rect in logical pixels to device pixels the same way the
+/// renderer does.
+fn to_device(rect: LogicalRect, scale: f32) -> DeviceRect {
+    let rect = pixel_grid::round_rect(rect, scale);
+    Rect::new(
+        point2(
+            (rect.min_x() * scale).round() as i32,
+            (rect.min_y() * scale).round() as i32,
+        ),
+        size2(
+            (rect.size.width * scale).round() as i32,
+            (rect.size.height * scale).round() as i32,
+        ),
+    )
+}
+
+/// Draws the borders of `rect`, each as a solid band along its edge.
+fn fill_border(
+    image: &mut Image,
+    rect: DeviceRect,
+    clip: DeviceRect,
+    values: &ComputedValues,
+    scale: f32,
+) {
+    let thickness = values.border_thickness;
+    let device = |length: f32| (length * scale).round() as i32;
+    let (left, right) = (device(thickness.left), device(thickness.right));
+    let (top, bottom) = (device(thickness.top), device(thickness.bottom));
+    let color = values.border_color;
+    let (x, y, width, height) = (
+        rect.min_x(),
+        rect.min_y(),
+        rect.size.width,
+        rect.size.height,
+    );
+    image.fill(Rect::new(point2(x, y), size2(width, top)), clip, color);
+    image.fill(
+        Rect::new(point2(x, y + height - bottom), size2(width, bottom)),
+        clip,
+        color,
+    );
+    let middle = height - top - bottom;
+    image.fill(
+        Rect::new(point2(x, y + top), size2(left, middle)),
+        clip,
+        color,
+    );
+    image.fill(
+        Rect::new(point2(x + width - right, y + top), size2(right, middle)),
+        clip,
+        color,
+    );
+}
+
+fn draw_text(
+    image: &mut Image,
+    text: &LayoutText,
+    position: LogicalPoint,
+    clip: DeviceRect,
+    color: Color,
+    scale: f32,
+) {
+    let point_size = text.size * scale;
+    let marker_color = Color {
+        alpha: color.alpha / 2,
+        ..color
+    };
+    for marker in &text.whitespace {
+        let rect = marker.mark(text.size, text.baseline);
+        image.fill(
+            to_device(rect.translate(position.to_vector()), scale),
+            clip,
+            marker_color,
+        );
+    }
+    for fragment in &text.fragments {
+        for glyph in &fragment.glyphs {
+            let origin = position + glyph.offset.to_vector();
+            if glyph.missing {
+                // The same box the renderer outlines, filled in.
+                let advance = text.size * MISSING_GLYPH_ADVANCE;
+                let rect = LogicalRect::new(
+                    point2(origin.x + advance * 0.1, origin.y - text.size * 0.7),
+                    size2(advance * 0.8, text.size * 0.7),
+                );
+                image.fill(to_device(rect, scale), clip, color);
+                continue;
+            }
+            let font = &fragment.font.font;
+            let bounds = match font.raster_bounds(
+                glyph.index,
+                point_size,
+                &point2(0.0, 0.0),
+                HintingOptions::None,
+                RasterizationOptions::GrayscaleAa,
+            ) {
+                Ok(bounds) if bounds.size.width > 0 && bounds.size.height > 0 => bounds,
+                _ => continue,
+            };
+            let mut canvas = Canvas::new(&bounds.size.to_u32(), Format::A8);
+            let canvas_origin = point2(-bounds.origin.x as f32, -bounds.origin.y as f32);
+            if font
+                .rasterize_glyph(
+                    &mut canvas,
+                    glyph.index,
+                    point_size,
+                    &canvas_origin,
+                    HintingOptions::None,
+                    RasterizationOptions::GrayscaleAa,
+                )
+                .is_err()
+            {
+                continue;
+            }
+            let rect = Rect::new(
+                point2(
+                    (origin.x * scale).round() as i32 + bounds.origin.x,
+                    (origin.y * scale).round() as i32 + bounds.origin.y,
+                ),
+                size2(bounds.size.width, bounds.size.height),
+            );
+            image.blend_mask(rect, clip, color, |x, y| {
+                canvas.pixels[y as usize * canvas.stride + x as usize]
+            });
+        }
+    }
+    for segment in &text.decorations {
+        let rect = segment.line(text.size, text.baseline);
+        image.fill(
+            to_device(rect.translate(position.to_vector()), scale),
+            clip,
+            color,
+        );
+    }
+}
+
+/// Paints `layout` onto an image of its size at `scale` device pixels
+/// per logical pixel, over an opaque `clear` color.
+pub fn rasterize(layout: &LayoutTreeNode, scale: f32, clear: Color) -> Image {
+    let bounds = to_device(LogicalRect::new(point2(0.0, 0.0), layout.size), scale);
+    let mut image = Image::new(bounds.size.width as u32, bounds.size.height as u32, clear);
+    for node in layout.flatten() {
+        let rect = to_device(node.rect, scale);
+        let clip = node.clip.map_or(bounds, |clip| to_device(clip, scale));
+        match node.layout.render {
+            RenderData::Node(ref dom_node) => {
+                let values = match dom_node.computed_values().get() {
+                    Some(values) => values,
+                    None => continue,
+                };
+                if values.border_color.alpha > 0 {
+                    fill_border(&mut image, rect, clip, &values, scale);
+                }
+                let background = match values.placeholder {
+                    Some(placeholder) if node.layout.children.is_empty() => placeholder,
+                    _ => values.background_color,
+                };
+                let inner = node.rect.inner_rect(values.border_thickness);
+                image.fill(to_device(inner, scale), clip, background);
+            }
+            RenderData::Text {
+                ref text,
+                ref parent,
+            } => {
+                let color = text.style.text_color.unwrap_or_else(|| {
+                    parent
+                        .computed_values()
+                        .get()
+                        .map_or(Color::black(), |values| values.text_color)
+                });
+                draw_text(&mut image, text, node.rect.origin, clip, color, scale);
+            }
+        }
+    }
+    image
+}
+
+#[cfg(test)]
+mod test {
+    use super::{rasterize, DeviceRect, Image};
+    use crate::dom::element::Element;
+    use crate::dom::{Node, Span, View, Window};
+    use crate::layout::{test_font, LayoutEngine, LogicalLength};
+    use crate::style::{BlockValues, ComputedValues, DisplayType};
+    use crate::Color;
+    use euclid::{point2, size2, SideOffsets2D};
+    use std::path::Path;
+    use std::rc::Rc;
+
+    #[test]
+    fn tolerance_allows_small_differences() {
+        let image = Image::new(2, 1, Color::white());
+        let mut other = image.clone();
+        other.blend(1, 0, Color::black(), 3);
+        assert!(image.matches(&other, 3));
+        assert!(!image.matches(&other, 2));
+        assert!(!image.matches(&Image::new(1, 2, Color::white()), 255));
+    }
+
+    /// Compares a label set in the test font with the image it should
+    /// make. Every glyph of the test font is a box, so at 20px, where its
+    /// edges fall on whole pixels, the expected image is drawn from the
+    /// font's geometry instead of being kept as a file. The rendering is
+    /// saved to `target/golden/label.png` to be looked at when it differs.
+    #[test]
+    fn label_matches_expected_image() {
+        let text: Rc<str> = "Golden label".into();
+        let span = Node::new(Span::default(), vec![text.clone().into()]);
+        span.computed_values().set(Some(ComputedValues {
+            text_size: LogicalLength::new(20.0),
+            text_color: Color::black(),
+            ..Span::default().create_computed_values()
+        }));
+        let background = Color::new(230, 230, 230, 255);
+        let border = Color::new(40, 40, 40, 255);
+        let view = Node::new(View::default(), vec![span.into()]);
+        view.computed_values().set(Some(ComputedValues {
+            display: DisplayType::Block(BlockValues {
+                width: Some(LogicalLength::new(120.0)),
+                padding: SideOffsets2D::new_all_same(4.0),
+                ..Default::default()
+            }),
+            background_color: background,
+            border_color: border,
+            border_thickness: SideOffsets2D::new_all_same(1.0),
+            ..Default::default()
+        }));
+        let window = Node::new(Window::default(), vec![view]);
+        window
+            .computed_values()
+            .set(Some(Window::default().create_computed_values()));
+        let layout = LayoutEngine::with_context(test_font::font_context())
+            .layout(window, size2(140.0, 40.0));
+        let image = rasterize(&layout, 1.0, Color::white());
+
+        // The view is 120px wide and one 20px line plus its padding and
+        // border tall, with the text starting at 5, 5.
+        let mut expected = Image::new(140, 40, Color::white());
+        let all = DeviceRect::new(point2(0, 0), size2(140, 40));
+        expected.fill(DeviceRect::new(point2(0, 0), size2(120, 30)), all, border);
+        expected.fill(
+            DeviceRect::new(point2(1, 1), size2(118, 28)),
+            all,
+            background,
+        );
+        // Each box is 50..450 units across and 0..700 up from the
+        // baseline, 16px down the line.
+        let mut x = 5;
+        for c in text.chars() {
+            if c == ' ' {
+                x += 5;
+                continue;
+            }
+            let glyph = DeviceRect::new(point2(x + 1, 7), size2(8, 14));
+            expected.fill(glyph, all, Color::black());
+            x += 10;
+        }
+
+        let matches = image.matches(&expected, 16);
+        if !matches {
+            let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+            let output = root.join("target/golden/label.png");
+            std::fs::create_dir_all(output.parent().unwrap()).unwrap();
+            image.save_png(&output).unwrap();
+        }
+        assert!(matches);
+    }
+}
@@ -1,5 +1,6 @@
 use crate::dom::devtools::DevToolsRegistry;
 use crate::dom::{App, Node};
+use crate::layout::FontContext;
 use moxie::embed::Runtime as MoxieRuntime;
 use std::collections::HashMap;
 use std::iter;
@@ -17,6 +18,8 @@ pub struct Runtime {
     windows: HashMap<WindowId, window::Window>,
     window_ids: Vec<WindowId>,
     proxy: Option<EventLoopProxy<()>>,
+    /// Shared by every window, so fonts are only loaded once.
+    fonts: Option<FontContext>,
 }
 
 impl Runtime {
@@ -36,6 +39,7 @@ impl Runtime {
             windows: HashMap::new(),
             window_ids: vec![],
             proxy: None,
+            fonts: None,
         }
     }
 
@@ -83,10 +87,12 @@ impl Runtime {
                     self.window_ids.push(window_id);
                 }
                 (Some(dom_window), None) => {
+                    let fonts = self.fonts.get_or_insert_with(FontContext::new).clone();
                     let window = window::Window::new(
                         dom_window.clone(),
                         event_loop,
                         self.proxy.as_ref().unwrap().clone(),
+                        fonts,
                     );
                     let id = window.window_id();
                     self.windows.insert(id, window);
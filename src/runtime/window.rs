@@ -1,5 +1,6 @@
 use crate::dom::input;
 use crate::dom::{Node, Window as DomWindow};
+use crate::layout::FontContext;
 use crate::render::Context;
 use gleam::gl;
 use glutin::{ContextBuilder, ContextWrapper, PossiblyCurrent};
@@ -23,6 +24,7 @@ impl Window {
         dom_window: Node<DomWindow>,
         event_loop: &EventLoopWindowTarget<()>,
         proxy: EventLoopProxy<()>,
+        fonts: FontContext,
     ) -> Window {
         let window_builder = WindowBuilder::new()
             .with_title(&dom_window.element().title[..])
@@ -49,7 +51,7 @@ impl Window {
             glutin::Api::WebGl => unimplemented!(),
         };
 
-        let mut context = Context::new(gl, gl_context.window(), proxy, dom_window);
+        let mut context = Context::new(gl, gl_context.window(), proxy, dom_window, fonts);
         context.render();
         gl_context.swap_buffers().unwrap();
 
@@ -1,6 +1,12 @@
-use super::{ComputedValues, Direction, DisplayType};
-use crate::layout::{LogicalLength, LogicalSize};
+use super::{
+    Alignment, BlockValues, BorderRadius, BoxSizing, ChildOverflow, ComputedValues,
+    ControlCharacters, Direction, DisplayType, EllipsisPosition, FontStyle, FontSynthesis,
+    MissingGlyphs, Overflow, OverflowWrap, Position, StyleOptions, TextAlign, TextDirection,
+    TextPositioning, WhiteSpace, WordBreakStrategy,
+};
+use crate::layout::{CustomLayoutRef, LogicalLength, LogicalSize, LogicalTransform};
 use crate::Color;
+use euclid::Angle;
 use std::borrow::Cow;
 
 /// Represents a position or size that can be specified in multiple
@@ -11,17 +17,21 @@ pub struct Value {
     pub ems: f32,
     pub view_width: f32,
     pub view_height: f32,
+    /// Fraction of the containing block's width, or of the element's own
+    /// box for `transform_origin`. This can only be resolved during
+    /// layout, so only `padding` and `transform_origin` accept it.
+    pub percent: f32,
 }
 
 struct ValueContext {
     pixels_per_em: f32,
     viewport: LogicalSize,
+    zoom: f32,
 }
 
 impl Value {
     fn resolve(&self, ctx: &ValueContext) -> LogicalLength {
-        let pixels = self.pixels
-            + self.ems * ctx.pixels_per_em
+        let pixels = (self.pixels + self.ems * ctx.pixels_per_em) * ctx.zoom
             + self.view_width * ctx.viewport.width
             + self.view_height * ctx.viewport.height;
         LogicalLength::new(pixels)
@@ -35,6 +45,9 @@ pub enum Display {
     Block,
     /// Lay out elements with text wrapping.
     Inline,
+    /// Place every child at the same origin, layered on top of each
+    /// other in DOM order. Useful for overlays and badges.
+    Stack,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -49,29 +62,139 @@ pub struct SideOffsets {
 pub struct CommonAttributes {
     pub display: Option<Display>,
     pub direction: Option<Direction>,
+    /// Sets both `overflow_x` and `overflow_y`, which take precedence
+    /// over it when they are set as well.
+    pub overflow: Option<Overflow>,
+    pub overflow_x: Option<Overflow>,
+    pub overflow_y: Option<Overflow>,
+    pub position: Option<Position>,
+    /// Sets both `content_align_x` and `content_align_y`, which take
+    /// precedence over it when they are set as well.
+    pub place_content: Option<Alignment>,
+    pub content_align_x: Option<Alignment>,
+    pub content_align_y: Option<Alignment>,
+    pub align_items: Option<Alignment>,
     pub text_size: Option<Value>,
     pub text_color: Option<Color>,
+    pub overflow_wrap: Option<OverflowWrap>,
+    pub word_break: Option<WordBreakStrategy>,
+    pub min_line_height: Option<Value>,
+    pub baseline_grid: Option<Value>,
+    pub gap: Option<Value>,
+    pub paragraph_spacing: Option<Value>,
+    pub baseline_spacing: Option<Value>,
+    pub order: Option<i32>,
+    /// Clockwise rotation in degrees, which sets `transform`.
+    pub rotate: Option<f32>,
+    pub transform_origin_x: Option<Value>,
+    pub transform_origin_y: Option<Value>,
+    pub max_lines: Option<u32>,
+    pub child_overflow: Option<ChildOverflow>,
+    pub rows: Option<u32>,
+    pub aspect_ratio: Option<f32>,
+    pub custom_layout: Option<CustomLayoutRef>,
+    pub layout_boundary: Option<bool>,
+    pub overflow_expand: Option<bool>,
+    pub fill: Option<bool>,
+    pub box_sizing: Option<BoxSizing>,
     pub font_family: Option<Cow<'static, str>>,
+    pub font_stack: Option<&'static [&'static str]>,
+    pub font_fallback: Option<bool>,
+    pub collapse_whitespace: Option<bool>,
+    pub show_whitespace: Option<bool>,
     pub font_weight: Option<u32>,
+    pub font_style: Option<FontStyle>,
+    pub font_synthesis: Option<FontSynthesis>,
+    pub control_characters: Option<ControlCharacters>,
+    pub missing_glyphs: Option<MissingGlyphs>,
+    pub text_positioning: Option<TextPositioning>,
+    pub white_space: Option<WhiteSpace>,
+    pub text_direction: Option<TextDirection>,
+    pub text_align: Option<TextAlign>,
+    pub text_align_last: Option<TextAlign>,
+    pub ellipsis: Option<&'static str>,
+    pub ellipsis_position: Option<EllipsisPosition>,
     pub background_color: Option<Color>,
+    pub placeholder: Option<Color>,
+    /// Square, since targets are meant to fit a fingertip either way.
+    pub min_touch_target: Option<Value>,
     pub border_radius: Option<Value>,
+    /// The vertical radius of every corner, when it differs from the
+    /// horizontal one set by `border_radius`.
+    pub border_radius_vertical: Option<Value>,
     pub border_thickness: SideOffsets,
     pub border_color: Option<Color>,
     pub padding: SideOffsets,
     pub margin: SideOffsets,
+    /// Override `padding` and `margin` on the side lines start or end.
+    pub padding_inline_start: Option<Value>,
+    pub padding_inline_end: Option<Value>,
+    pub margin_inline_start: Option<Value>,
+    pub margin_inline_end: Option<Value>,
+    pub inset: SideOffsets,
     pub width: Option<Value>,
     pub height: Option<Value>,
+    pub min_width: Option<Value>,
+    pub min_height: Option<Value>,
+    pub max_width: Option<Value>,
+    pub max_height: Option<Value>,
 }
 
 pub const DEFAULT_ATTRIBUTES: CommonAttributes = CommonAttributes {
     display: None,
     direction: None,
+    overflow: None,
+    overflow_x: None,
+    overflow_y: None,
+    position: None,
+    place_content: None,
+    content_align_x: None,
+    content_align_y: None,
+    align_items: None,
     text_size: None,
     text_color: None,
+    overflow_wrap: None,
+    word_break: None,
+    min_line_height: None,
+    baseline_grid: None,
+    gap: None,
+    paragraph_spacing: None,
+    baseline_spacing: None,
+    order: None,
+    rotate: None,
+    transform_origin_x: None,
+    transform_origin_y: None,
+    max_lines: None,
+    child_overflow: None,
+    rows: None,
+    aspect_ratio: None,
+    custom_layout: None,
+    layout_boundary: None,
+    overflow_expand: None,
+    fill: None,
+    box_sizing: None,
     font_family: None,
+    font_stack: None,
+    font_fallback: None,
+    collapse_whitespace: None,
+    show_whitespace: None,
     font_weight: None,
+    font_style: None,
+    font_synthesis: None,
+    control_characters: None,
+    missing_glyphs: None,
+    text_positioning: None,
+    white_space: None,
+    text_direction: None,
+    text_align: None,
+    text_align_last: None,
+    ellipsis: None,
+    ellipsis_position: None,
     background_color: None,
+    placeholder: None,
+    min_touch_target: None,
     border_radius: None,
+    border_radius_vertical: None,
     border_thickness: SideOffsets {
         left: None,
         right: None,
@@ -91,8 +214,22 @@ pub const DEFAULT_ATTRIBUTES: CommonAttributes = CommonAttributes {
         top: None,
         bottom: None,
     },
+    padding_inline_start: None,
+    padding_inline_end: None,
+    margin_inline_start: None,
+    margin_inline_end: None,
+    inset: SideOffsets {
+        left: None,
+        right: None,
+        top: None,
+        bottom: None,
+    },
     width: None,
     height: None,
+    min_width: None,
+    min_height: None,
+    max_width: None,
+    max_height: None,
 };
 
 impl Default for CommonAttributes {
@@ -102,16 +239,23 @@ impl Default for CommonAttributes {
 }
 
 impl CommonAttributes {
-    #[illicit::from_env(viewport_size: &LogicalSize)]
+    #[illicit::from_env(viewport_size: &LogicalSize, options: &StyleOptions)]
     pub(super) fn apply(&self, values: &mut ComputedValues) {
         let ctx = ValueContext {
-            pixels_per_em: 16.0, // todo
+            pixels_per_em: options.base_text_size.get(),
             viewport: *viewport_size,
+            zoom: options.zoom,
         };
         if let Some(display) = self.display {
             match display {
                 Display::Block => values.display = DisplayType::Block(Default::default()),
                 Display::Inline => values.display = DisplayType::Inline(Default::default()),
+                Display::Stack => {
+                    values.display = DisplayType::Block(BlockValues {
+                        stacked: true,
+                        ..Default::default()
+                    })
+                }
             }
         }
         if let Some(direction) = self.direction {
@@ -119,27 +263,79 @@ impl CommonAttributes {
                 block.direction = direction;
             }
         }
+        if let Some(overflow) = self.overflow {
+            if let DisplayType::Block(ref mut block) = values.display {
+                block.overflow_x = overflow;
+                block.overflow_y = overflow;
+            }
+        }
+        if let Some(overflow) = self.overflow_x {
+            if let DisplayType::Block(ref mut block) = values.display {
+                block.overflow_x = overflow;
+            }
+        }
+        if let Some(overflow) = self.overflow_y {
+            if let DisplayType::Block(ref mut block) = values.display {
+                block.overflow_y = overflow;
+            }
+        }
+        if let Some(position) = self.position {
+            if let DisplayType::Block(ref mut block) = values.display {
+                block.position = position;
+            }
+        }
+        if let Some(align) = self.place_content {
+            if let DisplayType::Block(ref mut block) = values.display {
+                block.content_align_x = align;
+                block.content_align_y = align;
+            }
+        }
+        if let Some(align) = self.content_align_x {
+            if let DisplayType::Block(ref mut block) = values.display {
+                block.content_align_x = align;
+            }
+        }
+        if let Some(align) = self.content_align_y {
+            if let DisplayType::Block(ref mut block) = values.display {
+                block.content_align_y = align;
+            }
+        }
+        if let Some(align) = self.align_items {
+            if let DisplayType::Block(ref mut block) = values.display {
+                block.align_items = align;
+            }
+        }
         if let Some(ref text_size) = self.text_size {
             values.text_size = text_size.resolve(&ctx);
         }
+        if let Some(ref min_line_height) = self.min_line_height {
+            values.min_line_height = Some(min_line_height.resolve(&ctx));
+        }
+        if let Some(ref baseline_grid) = self.baseline_grid {
+            values.baseline_grid = Some(baseline_grid.resolve(&ctx));
+        }
         if let Some(ref padding) = self.padding.left {
             if let DisplayType::Block(ref mut block) = values.display {
                 block.padding.left = padding.resolve(&ctx).get();
+                block.padding_percent.left = padding.percent;
             }
         }
         if let Some(ref padding) = self.padding.right {
             if let DisplayType::Block(ref mut block) = values.display {
                 block.padding.right = padding.resolve(&ctx).get();
+                block.padding_percent.right = padding.percent;
             }
         }
         if let Some(ref padding) = self.padding.top {
             if let DisplayType::Block(ref mut block) = values.display {
                 block.padding.top = padding.resolve(&ctx).get();
+                block.padding_percent.top = padding.percent;
             }
         }
         if let Some(ref padding) = self.padding.bottom {
             if let DisplayType::Block(ref mut block) = values.display {
                 block.padding.bottom = padding.resolve(&ctx).get();
+                block.padding_percent.bottom = padding.percent;
             }
         }
         if let Some(ref margin) = self.margin.left {
@@ -162,6 +358,45 @@ impl CommonAttributes {
                 block.margin.bottom = margin.resolve(&ctx).get();
             }
         }
+        if let Some(text_direction) = self.text_direction {
+            values.text_direction = text_direction;
+        }
+        let direction = values.text_direction;
+        if let DisplayType::Block(ref mut block) = values.display {
+            let paddings = [
+                (&self.padding_inline_start, true),
+                (&self.padding_inline_end, false),
+            ];
+            for &(padding, start) in &paddings {
+                if let Some(padding) = padding {
+                    *direction.inline_side(&mut block.padding, start) = padding.resolve(&ctx).get();
+                    *direction.inline_side(&mut block.padding_percent, start) = padding.percent;
+                }
+            }
+            let margins = [
+                (&self.margin_inline_start, true),
+                (&self.margin_inline_end, false),
+            ];
+            for &(margin, start) in &margins {
+                if let Some(margin) = margin {
+                    *direction.inline_side(&mut block.margin, start) = margin.resolve(&ctx).get();
+                }
+            }
+        }
+        if let DisplayType::Block(ref mut block) = values.display {
+            if let Some(ref inset) = self.inset.left {
+                block.inset.left = Some(inset.resolve(&ctx));
+            }
+            if let Some(ref inset) = self.inset.right {
+                block.inset.right = Some(inset.resolve(&ctx));
+            }
+            if let Some(ref inset) = self.inset.top {
+                block.inset.top = Some(inset.resolve(&ctx));
+            }
+            if let Some(ref inset) = self.inset.bottom {
+                block.inset.bottom = Some(inset.resolve(&ctx));
+            }
+        }
         if let Some(ref width) = self.width {
             if let DisplayType::Block(ref mut block) = values.display {
                 block.width = Some(width.resolve(&ctx));
@@ -172,8 +407,55 @@ impl CommonAttributes {
                 block.height = Some(height.resolve(&ctx));
             }
         }
+        if let Some(ref min_width) = self.min_width {
+            if let DisplayType::Block(ref mut block) = values.display {
+                block.min_width = Some(min_width.resolve(&ctx));
+            }
+        }
+        if let Some(ref min_height) = self.min_height {
+            if let DisplayType::Block(ref mut block) = values.display {
+                block.min_height = Some(min_height.resolve(&ctx));
+            }
+        }
+        if let Some(ref max_width) = self.max_width {
+            if let DisplayType::Block(ref mut block) = values.display {
+                block.max_width = Some(max_width.resolve(&ctx));
+            }
+        }
+        if let Some(ref max_height) = self.max_height {
+            if let DisplayType::Block(ref mut block) = values.display {
+                block.max_height = Some(max_height.resolve(&ctx));
+            }
+        }
+        if let Some(ref gap) = self.gap {
+            if let DisplayType::Block(ref mut block) = values.display {
+                block.gap = gap.resolve(&ctx);
+            }
+        }
+        if let Some(ref paragraph_spacing) = self.paragraph_spacing {
+            if let DisplayType::Block(ref mut block) = values.display {
+                block.paragraph_spacing = paragraph_spacing.resolve(&ctx);
+            }
+        }
+        if let Some(ref baseline_spacing) = self.baseline_spacing {
+            if let DisplayType::Block(ref mut block) = values.display {
+                block.baseline_spacing = Some(baseline_spacing.resolve(&ctx));
+            }
+        }
         if let Some(ref border_radius) = self.border_radius {
-            values.border_radius = border_radius.resolve(&ctx);
+            values.border_radius = BorderRadius::uniform(border_radius.resolve(&ctx).get());
+        }
+        if let Some(ref border_radius_vertical) = self.border_radius_vertical {
+            let y = border_radius_vertical.resolve(&ctx).get();
+            let radius = &mut values.border_radius;
+            for corner in &mut [
+                &mut radius.top_left,
+                &mut radius.top_right,
+                &mut radius.bottom_right,
+                &mut radius.bottom_left,
+            ] {
+                corner.height = y;
+            }
         }
         if let Some(ref border_thickness) = self.border_thickness.left {
             values.border_thickness.left = border_thickness.resolve(&ctx).get();
@@ -190,11 +472,250 @@ impl CommonAttributes {
         if let Some(text_color) = self.text_color {
             values.text_color = text_color;
         }
+        if let Some(overflow_wrap) = self.overflow_wrap {
+            values.overflow_wrap = overflow_wrap;
+        }
+        if let Some(word_break) = self.word_break {
+            values.word_break = word_break;
+        }
+        if let Some(font_weight) = self.font_weight {
+            values.font_weight = font_weight;
+        }
+        if let Some(font_style) = self.font_style {
+            values.font_style = font_style;
+        }
+        if let Some(font_synthesis) = self.font_synthesis {
+            values.font_synthesis = font_synthesis;
+        }
+        if let Some(control_characters) = self.control_characters {
+            values.control_characters = control_characters;
+        }
+        if let Some(missing_glyphs) = self.missing_glyphs {
+            values.missing_glyphs = missing_glyphs;
+        }
+        if let Some(text_positioning) = self.text_positioning {
+            values.text_positioning = text_positioning;
+        }
+        if let Some(white_space) = self.white_space {
+            values.white_space = white_space;
+        }
+        if let Some(font_stack) = self.font_stack {
+            values.font_stack = Some(font_stack);
+        }
+        if let Some(font_fallback) = self.font_fallback {
+            values.font_fallback = font_fallback;
+        }
+        if let Some(collapse_whitespace) = self.collapse_whitespace {
+            values.collapse_whitespace = collapse_whitespace;
+        }
+        if let Some(show_whitespace) = self.show_whitespace {
+            values.show_whitespace = show_whitespace;
+        }
+        if let Some(text_align) = self.text_align {
+            values.text_align = text_align;
+        }
+        if let Some(text_align_last) = self.text_align_last {
+            values.text_align_last = Some(text_align_last);
+        }
+        if let Some(ellipsis) = self.ellipsis {
+            values.ellipsis = Some(ellipsis);
+        }
+        if let Some(ellipsis_position) = self.ellipsis_position {
+            values.ellipsis_position = ellipsis_position;
+        }
+        if let Some(order) = self.order {
+            values.order = order;
+        }
+        if let Some(rotate) = self.rotate {
+            values.transform = LogicalTransform::create_rotation(Angle::degrees(rotate));
+        }
+        if let Some(ref origin_x) = self.transform_origin_x {
+            let origin = &mut values.transform_origin;
+            origin.offset.x = origin_x.resolve(&ctx).get();
+            origin.fraction.0 = origin_x.percent;
+        }
+        if let Some(ref origin_y) = self.transform_origin_y {
+            let origin = &mut values.transform_origin;
+            origin.offset.y = origin_y.resolve(&ctx).get();
+            origin.fraction.1 = origin_y.percent;
+        }
+        if let Some(max_lines) = self.max_lines {
+            if let DisplayType::Inline(ref mut inline) = values.display {
+                inline.max_lines = Some(max_lines);
+            }
+        }
+        if let Some(child_overflow) = self.child_overflow {
+            if let DisplayType::Inline(ref mut inline) = values.display {
+                inline.child_overflow = child_overflow;
+            }
+        }
+        if let Some(overflow_expand) = self.overflow_expand {
+            if let DisplayType::Block(ref mut block) = values.display {
+                block.overflow_expand = overflow_expand;
+            }
+        }
+        if let Some(fill) = self.fill {
+            if let DisplayType::Block(ref mut block) = values.display {
+                block.fill = fill;
+            }
+        }
+        if let Some(box_sizing) = self.box_sizing {
+            if let DisplayType::Block(ref mut block) = values.display {
+                block.box_sizing = box_sizing;
+            }
+        }
+        if let Some(rows) = self.rows {
+            if let DisplayType::Block(ref mut block) = values.display {
+                block.rows = Some(rows);
+            }
+        }
+        if let Some(custom_layout) = self.custom_layout {
+            if let DisplayType::Block(ref mut block) = values.display {
+                block.custom_layout = Some(custom_layout);
+            }
+        }
+        if let Some(layout_boundary) = self.layout_boundary {
+            if let DisplayType::Block(ref mut block) = values.display {
+                block.layout_boundary = layout_boundary;
+            }
+        }
+        if let Some(aspect_ratio) = self.aspect_ratio {
+            if let DisplayType::Block(ref mut block) = values.display {
+                block.aspect_ratio = Some(aspect_ratio);
+            }
+        }
         if let Some(border_color) = self.border_color {
             values.border_color = border_color;
         }
         if let Some(background_color) = self.background_color {
             values.background_color = background_color;
         }
+        if let Some(placeholder) = self.placeholder {
+            values.placeholder = Some(placeholder);
+        }
+        if let Some(ref min_touch_target) = self.min_touch_target {
+            let side = min_touch_target.resolve(&ctx).get();
+            values.min_touch_target = Some(LogicalSize::new(side, side));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CommonAttributes, Value, ValueContext, DEFAULT_ATTRIBUTES};
+    use crate::layout::{LogicalLength, LogicalSize};
+    use crate::style::{Alignment, ComputedValues, DisplayType, StyleOptions, TextDirection};
+    use euclid::{size2, vec2};
+
+    fn context(zoom: f32) -> ValueContext {
+        ValueContext {
+            pixels_per_em: 16.0,
+            viewport: size2(800.0, 600.0),
+            zoom,
+        }
+    }
+
+    #[test]
+    fn zoom_scales_absolute_units() {
+        let value = Value {
+            pixels: 100.0,
+            ems: 1.0,
+            ..Default::default()
+        };
+        assert_eq!(value.resolve(&context(1.0)).get(), 116.0);
+        assert_eq!(value.resolve(&context(2.0)).get(), 232.0);
+    }
+
+    #[test]
+    fn zoom_ignores_viewport_units() {
+        let value = Value {
+            view_width: 0.5,
+            ..Default::default()
+        };
+        assert_eq!(value.resolve(&context(2.0)).get(), 400.0);
+    }
+
+    #[test]
+    fn inline_start_follows_text_direction() {
+        let padding_at = |text_direction| {
+            let attributes = CommonAttributes {
+                text_direction: Some(text_direction),
+                padding_inline_start: Some(Value {
+                    pixels: 10.0,
+                    ..Default::default()
+                }),
+                ..DEFAULT_ATTRIBUTES
+            };
+            let mut values = ComputedValues::default();
+            illicit::child_env!(
+                LogicalSize => size2(800.0, 600.0),
+                StyleOptions => StyleOptions {
+                    base_text_size: LogicalLength::new(16.0),
+                    zoom: 1.0,
+                    text_size_scale: None,
+                }
+            )
+            .enter(|| attributes.apply(&mut values));
+            match values.display {
+                DisplayType::Block(block) => (block.padding.left, block.padding.right),
+                DisplayType::Inline(_) => unreachable!(),
+            }
+        };
+        assert_eq!(padding_at(TextDirection::Ltr), (10.0, 0.0));
+        assert_eq!(padding_at(TextDirection::Rtl), (0.0, 10.0));
+    }
+
+    #[test]
+    fn transform_origin_percent_sets_the_fraction() {
+        let attributes = CommonAttributes {
+            transform_origin_x: Some(Value {
+                percent: 0.25,
+                pixels: 4.0,
+                ..Default::default()
+            }),
+            transform_origin_y: Some(Value {
+                percent: 1.0,
+                ..Default::default()
+            }),
+            ..DEFAULT_ATTRIBUTES
+        };
+        let mut values = ComputedValues::default();
+        illicit::child_env!(
+            LogicalSize => size2(800.0, 600.0),
+            StyleOptions => StyleOptions {
+                base_text_size: LogicalLength::new(16.0),
+                zoom: 1.0,
+                text_size_scale: None,
+            }
+        )
+        .enter(|| attributes.apply(&mut values));
+        assert_eq!(values.transform_origin.fraction, (0.25, 1.0));
+        assert_eq!(values.transform_origin.offset, vec2(4.0, 0.0));
+    }
+
+    #[test]
+    fn place_content_sets_both_axes() {
+        let attributes = CommonAttributes {
+            place_content: Some(Alignment::Center),
+            content_align_y: Some(Alignment::End),
+            ..DEFAULT_ATTRIBUTES
+        };
+        let mut values = ComputedValues::default();
+        illicit::child_env!(
+            LogicalSize => size2(800.0, 600.0),
+            StyleOptions => StyleOptions {
+                base_text_size: LogicalLength::new(16.0),
+                zoom: 1.0,
+                text_size_scale: None,
+            }
+        )
+        .enter(|| attributes.apply(&mut values));
+        match values.display {
+            DisplayType::Block(block) => {
+                assert_eq!(block.content_align_x, Alignment::Center);
+                assert_eq!(block.content_align_y, Alignment::End);
+            }
+            DisplayType::Inline(_) => unreachable!(),
+        }
     }
 }
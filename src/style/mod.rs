@@ -1,10 +1,15 @@
 use crate::dom::{element::DynamicNode, node::NodeRef, Node, Window};
-use crate::layout::{LogicalLength, LogicalSideOffsets, LogicalSize};
+use crate::layout::{
+    CustomLayoutRef, LogicalLength, LogicalSideOffsets, LogicalSize, LogicalTransform,
+    LogicalVector,
+};
 use crate::Color;
+use euclid::{size2, SideOffsets2D, UnknownUnit};
 use moxie::embed::Runtime;
 
 mod attributes;
 
+pub use crate::util::word_break_iter::WordBreakStrategy;
 pub use attributes::*;
 
 /// Specifies which direction layout should be performed in.
@@ -14,34 +19,426 @@ pub enum Direction {
     Horizontal,
 }
 
+/// Where to place content along an axis when there is extra space.
+#[derive(Clone, PartialEq, Copy, Debug)]
+pub enum Alignment {
+    Start,
+    Center,
+    End,
+}
+
+/// Specifies what happens to children that extend outside of an
+/// element's box.
+#[derive(Clone, PartialEq, Copy, Debug)]
+pub enum Overflow {
+    /// Children are drawn in full, even outside of the box.
+    Visible,
+    /// Children are clipped to the padding box, following the border
+    /// radius.
+    Hidden,
+    /// Clipped like `Hidden`, with the content that doesn't fit left to
+    /// be scrolled to, see `LayoutTreeNode::scrollable_extent`.
+    Scroll,
+}
+
+/// The radii of the four corners of a border. Each corner has a
+/// horizontal and a vertical radius, as the width and height of its
+/// size, so that corners can be elliptical like CSS
+/// `border-radius: 10px / 20px`.
+#[derive(Clone, PartialEq, Copy, Debug)]
+pub struct BorderRadius {
+    pub top_left: LogicalSize,
+    pub top_right: LogicalSize,
+    pub bottom_right: LogicalSize,
+    pub bottom_left: LogicalSize,
+}
+
+impl BorderRadius {
+    /// Circular corners of the same radius.
+    pub fn uniform(radius: f32) -> BorderRadius {
+        BorderRadius::elliptical(radius, radius)
+    }
+
+    /// Corners that are all the same ellipse.
+    pub fn elliptical(x: f32, y: f32) -> BorderRadius {
+        let corner = size2(x, y);
+        BorderRadius {
+            top_left: corner,
+            top_right: corner,
+            bottom_right: corner,
+            bottom_left: corner,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        [
+            self.top_left,
+            self.top_right,
+            self.bottom_right,
+            self.bottom_left,
+        ]
+        .iter()
+        .all(|corner| corner.width <= 0.0 || corner.height <= 0.0)
+    }
+
+    /// The radii a box of `size` is drawn with. Where the two radii
+    /// along an edge add up to more than its length, every radius is
+    /// scaled down by the same factor until they fit, the way CSS
+    /// resolves overlapping corners, which keeps each corner's shape.
+    /// Negative radii count as zero.
+    pub fn clamped(&self, size: LogicalSize) -> BorderRadius {
+        let corner = |corner: LogicalSize| size2(corner.width.max(0.0), corner.height.max(0.0));
+        let (top_left, top_right) = (corner(self.top_left), corner(self.top_right));
+        let (bottom_right, bottom_left) = (corner(self.bottom_right), corner(self.bottom_left));
+        let fit = |length: f32, first: f32, second: f32| {
+            if first + second > length {
+                length.max(0.0) / (first + second)
+            } else {
+                1.0
+            }
+        };
+        let scale = [
+            fit(size.width, top_left.width, top_right.width),
+            fit(size.width, bottom_left.width, bottom_right.width),
+            fit(size.height, top_left.height, bottom_left.height),
+            fit(size.height, top_right.height, bottom_right.height),
+        ]
+        .iter()
+        .cloned()
+        .fold(1.0, f32::min);
+        BorderRadius {
+            top_left: top_left * scale,
+            top_right: top_right * scale,
+            bottom_right: bottom_right * scale,
+            bottom_left: bottom_left * scale,
+        }
+    }
+
+    /// The radii of the inner edge of a border of `thickness` with these
+    /// radii, which the background and the clip of the padding box
+    /// follow. Each corner shrinks by the sides that meet at it, down to
+    /// a square corner.
+    pub fn inner(&self, thickness: LogicalSideOffsets) -> BorderRadius {
+        let corner = |corner: LogicalSize, x: f32, y: f32| {
+            size2((corner.width - x).max(0.0), (corner.height - y).max(0.0))
+        };
+        BorderRadius {
+            top_left: corner(self.top_left, thickness.left, thickness.top),
+            top_right: corner(self.top_right, thickness.right, thickness.top),
+            bottom_right: corner(self.bottom_right, thickness.right, thickness.bottom),
+            bottom_left: corner(self.bottom_left, thickness.left, thickness.bottom),
+        }
+    }
+}
+
+impl Default for BorderRadius {
+    fn default() -> BorderRadius {
+        BorderRadius::uniform(0.0)
+    }
+}
+
+/// What inline content does with a box that is wider than the whole
+/// line, like an image in a narrow column.
+#[derive(Clone, PartialEq, Copy, Debug)]
+pub enum ChildOverflow {
+    /// The box runs past the edge of the line.
+    Visible,
+    /// The box is cut off at the edge of the line.
+    Clip,
+    /// The box is laid out again as wide as the line, so that e.g. an
+    /// image with an `aspect_ratio` scales down to fit.
+    Shrink,
+}
+
+impl Default for ChildOverflow {
+    fn default() -> Self {
+        ChildOverflow::Visible
+    }
+}
+
+/// Whether text is drawn upright or slanted.
+#[derive(Clone, PartialEq, Copy, Debug)]
+pub enum FontStyle {
+    Normal,
+    Italic,
+}
+
+/// Controls what happens when the font has no face with the requested
+/// weight or style, mirroring CSS `font-synthesis`.
+#[derive(Clone, PartialEq, Copy, Debug)]
+pub enum FontSynthesis {
+    /// Fake the missing bold or italic from the regular face.
+    Auto,
+    /// Use the regular face as is.
+    None,
+}
+
+/// What to do with control characters (other than tabs and line
+/// breaks) in text, which fonts have no glyphs for.
+#[derive(Clone, PartialEq, Copy, Debug)]
+pub enum ControlCharacters {
+    /// Leave them out of the layout, so they take up no space.
+    Strip,
+    /// Shape them like any other character, which usually draws a box.
+    Keep,
+}
+
+/// How glyphs that no available font has are laid out.
+#[derive(Clone, PartialEq, Copy, Debug)]
+pub enum MissingGlyphs {
+    /// Use the font's own missing glyph, whatever its width is.
+    Font,
+    /// Substitute a fixed-width box, so line widths are predictable.
+    Placeholder,
+}
+
+/// Whether glyphs are placed at fractional pixel positions.
+#[derive(Clone, PartialEq, Copy, Debug)]
+pub enum TextPositioning {
+    /// Keep the positions the shaper computed, for subpixel antialiasing.
+    Subpixel,
+    /// Round every glyph advance to whole pixels, which keeps small
+    /// grayscale antialiased text crisp at the cost of slightly
+    /// different widths.
+    IntegerSnap,
+}
+
+/// Whether text wraps onto new lines when it reaches the edge of its
+/// container.
+#[derive(Clone, PartialEq, Copy, Debug)]
+pub enum WhiteSpace {
+    Normal,
+    /// The text stays on one line and runs past the edge, where a block
+    /// with hidden overflow cuts it off.
+    NoWrap,
+}
+
+/// The direction inline content flows in.
+#[derive(Clone, PartialEq, Copy, Debug)]
+pub enum TextDirection {
+    Ltr,
+    Rtl,
+}
+
+impl TextDirection {
+    /// The side of `offsets` where lines start, or end if not `start`.
+    pub fn inline_side<T>(self, offsets: &mut SideOffsets2D<f32, T>, start: bool) -> &mut f32 {
+        match (self, start) {
+            (TextDirection::Ltr, true) | (TextDirection::Rtl, false) => &mut offsets.left,
+            (TextDirection::Ltr, false) | (TextDirection::Rtl, true) => &mut offsets.right,
+        }
+    }
+}
+
+/// How the lines of text in an inline box are placed horizontally.
+#[derive(Clone, PartialEq, Copy, Debug)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+    /// Widen the spaces of each line so that it fills the whole width.
+    Justify,
+}
+
+/// Which part of a line of text is replaced by the `ellipsis` when the
+/// line is cut short.
+#[derive(Clone, PartialEq, Copy, Debug)]
+pub enum EllipsisPosition {
+    Start,
+    /// Keeps both ends of the text, e.g. for file names, where the
+    /// extension matters.
+    Middle,
+    End,
+}
+
+/// What a block's `width` and `height` (and their limits) measure.
+#[derive(Clone, PartialEq, Copy, Debug)]
+pub enum BoxSizing {
+    /// Only the content, with padding and border added around it.
+    ContentBox,
+    /// The whole box including padding and border, which the content
+    /// shrinks to make room for.
+    BorderBox,
+}
+
+/// Specifies how a block is positioned relative to its parent.
+#[derive(Clone, PartialEq, Copy, Debug)]
+pub enum Position {
+    /// Placed by the parent, in order with its siblings.
+    Static,
+    /// Taken out of the parent's flow and placed against the window
+    /// using `inset`, above all other content.
+    Fixed,
+}
+
+/// Distances from each edge of the window for `Position::Fixed`. Sides
+/// left unset place the box against the opposite side, or the top-left
+/// corner when neither side is set.
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub struct Inset {
+    pub left: Option<LogicalLength>,
+    pub right: Option<LogicalLength>,
+    pub top: Option<LogicalLength>,
+    pub bottom: Option<LogicalLength>,
+}
+
+/// The point `transform` turns an element around, measured from the
+/// top left corner of its box as a fixed offset plus a fraction of the
+/// box's width and height. The default is the center of the box.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct TransformOrigin {
+    pub offset: LogicalVector,
+    pub fraction: (f32, f32),
+}
+
+impl TransformOrigin {
+    /// The origin in a box of `size`, from its top left corner.
+    pub fn resolve(&self, size: LogicalSize) -> LogicalVector {
+        self.offset
+            + LogicalVector::new(self.fraction.0 * size.width, self.fraction.1 * size.height)
+    }
+}
+
+impl Default for TransformOrigin {
+    fn default() -> Self {
+        TransformOrigin {
+            offset: LogicalVector::zero(),
+            fraction: (0.5, 0.5),
+        }
+    }
+}
+
+/// Specifies what happens to a word that is too long to fit on a line
+/// by itself.
+#[derive(Clone, PartialEq, Copy, Debug)]
+pub enum OverflowWrap {
+    /// The word overflows the line.
+    Normal,
+    /// The word is broken between characters to fit.
+    BreakWord,
+}
+
 #[derive(Default, PartialEq, Clone, Copy, Debug)]
-pub struct InlineValues {}
+pub struct InlineValues {
+    /// Lines past this count are dropped, and the node is marked as
+    /// truncated.
+    pub max_lines: Option<u32>,
+    pub child_overflow: ChildOverflow,
+}
 
 #[derive(PartialEq, Clone, Copy, Debug)]
 pub struct BlockValues {
     pub direction: Direction,
+    /// When set, children are layered on top of each other instead of
+    /// being placed in a list, see `Display::Stack`.
+    pub stacked: bool,
+    /// When set, the box takes a share of the space left over in a parent
+    /// with a fixed size along its direction, as a gap after the box
+    /// that pushes the siblings after it along. The box keeps its own
+    /// size. This is what `Spacer` uses to push its siblings apart.
+    pub grow: bool,
+    /// When set, the box takes up the space its siblings leave in a
+    /// parent with a fixed size along its direction, and its content is
+    /// laid out at that size. Unlike `grow`, the box itself gets bigger.
+    pub fill: bool,
+    pub position: Position,
+    pub inset: Inset,
+    /// Where the children are placed as a whole when the box has a
+    /// fixed size larger than its content.
+    pub content_align_x: Alignment,
+    pub content_align_y: Alignment,
+    /// Where each child of a list is placed across its direction, within
+    /// the content box, e.g. `End` for rows ending at the right edge of
+    /// a vertical list. Children too large for the box start at its
+    /// start. Stacks and custom layouts place their children themselves.
+    pub align_items: Alignment,
+    /// What happens to children that extend past the left or right of
+    /// the box.
+    pub overflow_x: Overflow,
+    /// What happens to children that extend past the top or bottom of
+    /// the box.
+    pub overflow_y: Overflow,
     pub margin: LogicalSideOffsets,
     pub padding: LogicalSideOffsets,
+    /// Fractions of the containing block's width, added to `padding`
+    /// during layout. Like CSS, every side uses the width.
+    pub padding_percent: SideOffsets2D<f32, UnknownUnit>,
     pub width: Option<LogicalLength>,
     pub height: Option<LogicalLength>,
     pub min_width: Option<LogicalLength>,
     pub min_height: Option<LogicalLength>,
     pub max_width: Option<LogicalLength>,
     pub max_height: Option<LogicalLength>,
+    /// Sizes the box to hold exactly this many lines of text in its
+    /// font, no matter what it contains. Overrides `height`.
+    pub rows: Option<u32>,
+    /// Width divided by height. Fills in whichever of `width` and
+    /// `height` isn't set from the other, or both from the width of the
+    /// parent, so that the box can be sized before its content arrives.
+    pub aspect_ratio: Option<f32>,
+    /// Makes `width` and `height` a minimum instead of a fixed size, so
+    /// that content larger than them grows the box instead of
+    /// overflowing it.
+    pub overflow_expand: bool,
+    pub box_sizing: BoxSizing,
+    /// Space between neighbouring children along the direction of a
+    /// list. It goes between children as they are placed, after `order`
+    /// has been applied, so the first and last child on screen never
+    /// get any on their outer side.
+    pub gap: LogicalLength,
+    /// Space between consecutive paragraphs of a vertical list, on top
+    /// of their margins. Text and inline children are paragraphs, and
+    /// empty ones are passed over, so they don't double the spacing.
+    pub paragraph_spacing: LogicalLength,
+    /// Distance from the last baseline of a child of a vertical list to
+    /// the first baseline of the next one, in place of their gap and
+    /// paragraph spacing, so that text sits on a regular rhythm. Only
+    /// applies between children that both have text in them.
+    pub baseline_spacing: Option<LogicalLength>,
+    /// Places the children instead of the list or stack layout.
+    pub custom_layout: Option<CustomLayoutRef>,
+    /// Keeps the layout of the box and that of its parent apart, for a
+    /// widget that updates often on its own: as long as the box keeps
+    /// its size, changes inside of it don't lay out its parent again,
+    /// and measuring it doesn't look inside. Only takes effect when both
+    /// `width` and `height` are set, without `overflow_expand`, so that
+    /// the size can't depend on the content. The parent doesn't see the
+    /// baselines of the text inside.
+    pub layout_boundary: bool,
 }
 
 impl Default for BlockValues {
     fn default() -> Self {
         BlockValues {
             direction: Direction::Vertical,
+            stacked: false,
+            grow: false,
+            fill: false,
+            position: Position::Static,
+            inset: Inset::default(),
+            content_align_x: Alignment::Start,
+            content_align_y: Alignment::Start,
+            align_items: Alignment::Start,
+            overflow_x: Overflow::Visible,
+            overflow_y: Overflow::Visible,
             margin: LogicalSideOffsets::new_all_same(0.0),
             padding: LogicalSideOffsets::new_all_same(0.0),
+            padding_percent: SideOffsets2D::new_all_same(0.0),
             width: None,
             height: None,
             min_width: None,
             min_height: None,
             max_width: None,
             max_height: None,
+            rows: None,
+            aspect_ratio: None,
+            overflow_expand: false,
+            box_sizing: BoxSizing::BorderBox,
+            gap: LogicalLength::new(0.0),
+            paragraph_spacing: LogicalLength::new(0.0),
+            baseline_spacing: None,
+            custom_layout: None,
+            layout_boundary: false,
         }
     }
 }
@@ -57,8 +454,67 @@ pub struct ComputedValues {
     pub display: DisplayType,
     pub text_size: LogicalLength,
     pub text_color: Color,
+    pub overflow_wrap: OverflowWrap,
+    pub word_break: WordBreakStrategy,
+    /// Weight of the text, from 100 to 900 where 400 is regular and 700
+    /// is bold.
+    pub font_weight: u32,
+    pub font_style: FontStyle,
+    pub font_synthesis: FontSynthesis,
+    pub control_characters: ControlCharacters,
+    pub missing_glyphs: MissingGlyphs,
+    pub text_positioning: TextPositioning,
+    pub white_space: WhiteSpace,
+    pub text_direction: TextDirection,
+    /// Font families to try in order, which may be generic names like
+    /// `serif` or `monospace`.
+    pub font_stack: Option<&'static [&'static str]>,
+    /// Whether characters the primary font lacks are looked up in the
+    /// rest of the font stack. When off they're drawn as the primary
+    /// font's missing glyph, so measurements don't depend on which
+    /// other fonts happen to be installed.
+    pub font_fallback: bool,
+    /// Whether runs of spaces, tabs and line breaks are collapsed into a
+    /// single space, as they are in most UI text. Code and other
+    /// preformatted text keep each one.
+    pub collapse_whitespace: bool,
+    /// Whether lines of text mark where their spaces and tabs are, for
+    /// editors that make whitespace visible. The markers are drawn over
+    /// the text and take up no room of their own.
+    pub show_whitespace: bool,
+    pub text_align: TextAlign,
+    /// Alignment of the last line of an inline box, which is usually too
+    /// short to be justified. When unset, this is the same as
+    /// `text_align`, except that justified text ends on a left aligned
+    /// line.
+    pub text_align_last: Option<TextAlign>,
+    /// Drawn in place of the text that is cut off, either by a word that
+    /// doesn't fit or by `max_lines`. Text is only cut when this is set.
+    pub ellipsis: Option<&'static str>,
+    pub ellipsis_position: EllipsisPosition,
+    /// Lines of text shorter than this are padded to it, with the text
+    /// centered vertically.
+    pub min_line_height: Option<LogicalLength>,
+    /// When set, the baseline of every line of text is moved down to the
+    /// next multiple of this spacing, measured from the top of its box,
+    /// so that text in neighbouring columns lines up.
+    pub baseline_grid: Option<LogicalLength>,
+    /// Changes the position of the element among its siblings when
+    /// laid out, without changing the DOM. Lower values come first.
+    pub order: i32,
+    /// Moves the element where it's drawn and hit, around
+    /// `transform_origin`, without changing its layout.
+    pub transform: LogicalTransform,
+    pub transform_origin: TransformOrigin,
     pub background_color: Color,
-    pub border_radius: LogicalLength,
+    /// Painted over the background while the element has no laid out
+    /// content, e.g. for an image that is still loading.
+    pub placeholder: Option<Color>,
+    /// The smallest area that takes pointer input for the element,
+    /// centered on its box, for controls too small to hit with a
+    /// finger. Only hit testing sees it; the box is drawn as laid out.
+    pub min_touch_target: Option<LogicalSize>,
+    pub border_radius: BorderRadius,
     pub border_thickness: LogicalSideOffsets,
     pub border_color: Color,
 }
@@ -69,8 +525,33 @@ impl Default for ComputedValues {
             display: DisplayType::Block(BlockValues::default()),
             text_size: LogicalLength::new(16.0),
             text_color: Color::black(),
+            overflow_wrap: OverflowWrap::Normal,
+            word_break: WordBreakStrategy::Normal,
+            font_weight: 400,
+            font_style: FontStyle::Normal,
+            font_synthesis: FontSynthesis::Auto,
+            control_characters: ControlCharacters::Strip,
+            missing_glyphs: MissingGlyphs::Font,
+            text_positioning: TextPositioning::Subpixel,
+            white_space: WhiteSpace::Normal,
+            text_direction: TextDirection::Ltr,
+            font_stack: None,
+            font_fallback: true,
+            collapse_whitespace: true,
+            show_whitespace: false,
+            text_align: TextAlign::Left,
+            text_align_last: None,
+            ellipsis: None,
+            ellipsis_position: EllipsisPosition::End,
+            min_line_height: None,
+            baseline_grid: None,
+            order: 0,
+            transform: LogicalTransform::identity(),
+            transform_origin: TransformOrigin::default(),
             background_color: Color::clear(),
-            border_radius: LogicalLength::new(0.0),
+            placeholder: None,
+            min_touch_target: None,
+            border_radius: BorderRadius::default(),
             border_thickness: LogicalSideOffsets::new_all_same(0.0),
             border_color: Color::clear(),
         }
@@ -121,24 +602,94 @@ impl PartialEq for Style {
     }
 }
 
+/// Engine-wide settings that affect how values are resolved during
+/// styling.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct StyleOptions {
+    pub base_text_size: LogicalLength,
+    pub zoom: f32,
+    /// Device pixels per logical pixel that text sizes are rounded to,
+    /// see `StyleEngine::set_text_size_rounding`.
+    pub text_size_scale: Option<f32>,
+}
+
 /// Used to annotate the node tree with computed values from styling.
 pub struct StyleEngine {
     runtime: Runtime<fn()>,
+    options: StyleOptions,
 }
 
 impl StyleEngine {
     pub fn new() -> StyleEngine {
         StyleEngine {
             runtime: Runtime::new(StyleEngine::run_styling),
+            options: StyleOptions {
+                base_text_size: LogicalLength::new(16.0),
+                zoom: 1.0,
+                text_size_scale: None,
+            },
         }
     }
 
-    fn update_style(node: NodeRef, parent: Option<&ComputedValues>) {
+    /// Sets the text size used by the root of the DOM, which is also
+    /// the size that `em` units are resolved against. Takes effect on
+    /// the next call to `update`.
+    pub fn set_base_text_size(&mut self, size: LogicalLength) {
+        self.options.base_text_size = size;
+    }
+
+    /// Sets a user-facing zoom factor which scales every absolute
+    /// length (pixels and ems), including text, while viewport units
+    /// keep tracking the window size. Unlike the DPI scale this changes
+    /// the logical layout, so text is shaped at the zoomed size.
+    pub fn set_zoom(&mut self, zoom: f32) {
+        self.options.zoom = zoom;
+    }
+
+    /// Rounds every text size to a whole number of device pixels at
+    /// `scale` device pixels per logical pixel, e.g. 15.3 to 15 at a
+    /// scale of 1, so that hinted glyphs stay crisp on low DPI displays.
+    /// Text is both measured and drawn at the rounded size. `None` keeps
+    /// sizes as they are.
+    pub fn set_text_size_rounding(&mut self, scale: Option<f32>) {
+        self.options.text_size_scale = scale.filter(|scale| *scale > 0.0);
+    }
+
+    /// Styles `node` and the nodes below it, returning whether any of
+    /// their computed values changed. `text_size` is the size inherited
+    /// from the parent before it was rounded, so that only the size each
+    /// node ends up with is rounded, once.
+    #[illicit::from_env(options: &StyleOptions)]
+    fn update_style(
+        node: NodeRef,
+        parent: Option<&ComputedValues>,
+        text_size: LogicalLength,
+    ) -> bool {
         let mut computed = node.create_computed_values();
 
+        computed.text_size = text_size;
         if let Some(parent) = parent {
-            computed.text_size = parent.text_size;
             computed.text_color = parent.text_color;
+            computed.overflow_wrap = parent.overflow_wrap;
+            computed.word_break = parent.word_break;
+            computed.font_weight = parent.font_weight;
+            computed.font_style = parent.font_style;
+            computed.font_synthesis = parent.font_synthesis;
+            computed.control_characters = parent.control_characters;
+            computed.missing_glyphs = parent.missing_glyphs;
+            computed.text_positioning = parent.text_positioning;
+            computed.white_space = parent.white_space;
+            computed.text_direction = parent.text_direction;
+            computed.font_stack = parent.font_stack;
+            computed.font_fallback = parent.font_fallback;
+            computed.collapse_whitespace = parent.collapse_whitespace;
+            computed.show_whitespace = parent.show_whitespace;
+            computed.text_align = parent.text_align;
+            computed.text_align_last = parent.text_align_last;
+            computed.ellipsis = parent.ellipsis;
+            computed.ellipsis_position = parent.ellipsis_position;
+            computed.min_line_height = parent.min_line_height;
+            computed.baseline_grid = parent.baseline_grid;
         }
 
         let style = node.style();
@@ -150,27 +701,93 @@ impl StyleEngine {
                 }
             }
         }
+        let text_size = computed.text_size;
+        if let Some(scale) = options.text_size_scale {
+            let size = (computed.text_size.get() * scale).round() / scale;
+            // Text too small to cover a device pixel keeps its size.
+            if size > 0.0 {
+                computed.text_size = LogicalLength::new(size);
+            }
+        }
 
-        node.computed_values().set(Some(computed));
+        let mut changed = node.computed_values().replace(Some(computed)) != Some(computed);
 
         for child in node.children() {
-            if let DynamicNode::Node(node) = child {
-                Self::update_style(node, Some(&computed));
+            if let DynamicNode::Node(node) = child.get_node() {
+                changed |= Self::update_style(node, Some(&computed), text_size);
             }
         }
+        node.style_changed().set(changed);
+        changed
     }
 
-    #[illicit::from_env(node: &Node<Window>)]
+    #[illicit::from_env(node: &Node<Window>, options: &StyleOptions)]
     fn run_styling() {
-        Self::update_style(node.into(), None);
+        Self::update_style(node.into(), None, options.base_text_size * options.zoom);
     }
 
     /// Update the node tree with computed values.
     pub fn update(&mut self, node: Node<Window>, size: LogicalSize) {
         illicit::child_env!(
             Node<Window> => node,
-            LogicalSize => size
+            LogicalSize => size,
+            StyleOptions => self.options
         )
         .enter(|| topo::call!(self.runtime.run_once()))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{BorderRadius, StyleEngine};
+    use crate::dom::{Node, View, Window};
+    use crate::layout::LogicalLength;
+    use euclid::size2;
+
+    #[test]
+    fn overlapping_radii_scale_down_together() {
+        let radius = BorderRadius {
+            top_left: size2(30.0, 15.0),
+            top_right: size2(50.0, 5.0),
+            bottom_right: size2(10.0, 30.0),
+            bottom_left: size2(0.0, 0.0),
+        };
+        // The top edge is overlapped the most, by twice its length, so
+        // every radius is halved, including those that already fit.
+        let clamped = radius.clamped(size2(40.0, 20.0));
+        assert_eq!(
+            clamped,
+            BorderRadius {
+                top_left: size2(15.0, 7.5),
+                top_right: size2(25.0, 2.5),
+                bottom_right: size2(5.0, 15.0),
+                bottom_left: size2(0.0, 0.0),
+            }
+        );
+        assert_eq!(clamped.top_left.width + clamped.top_right.width, 40.0);
+        // Radii that fit are kept.
+        assert_eq!(clamped.clamped(size2(40.0, 20.0)), clamped);
+        assert!(BorderRadius::uniform(-4.0)
+            .clamped(size2(40.0, 20.0))
+            .is_zero());
+    }
+
+    #[test]
+    fn text_size_rounds_to_device_pixels() {
+        let text_size = |scale| {
+            let child = Node::new(View::default(), vec![]);
+            let window = Node::new(Window::default(), vec![child.clone().into()]);
+            let mut engine = StyleEngine::new();
+            engine.set_base_text_size(LogicalLength::new(15.3));
+            engine.set_text_size_rounding(scale);
+            engine.update(window.clone(), size2(100.0, 100.0));
+            let size = window.computed_values().get().unwrap().text_size.get();
+            // Children inherit the size, rounded the same way.
+            assert_eq!(child.computed_values().get().unwrap().text_size.get(), size);
+            size
+        };
+        assert_eq!(text_size(None), 15.3);
+        assert_eq!(text_size(Some(1.0)), 15.0);
+        assert_eq!(text_size(Some(2.0)), 15.5);
+    }
+}
@@ -0,0 +1,277 @@
+use std::any::{Any, TypeId};
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+struct Entry {
+    used: bool,
+    /// When the entry was last looked up, for finding the least
+    /// recently used one once the cache is full.
+    last_used: u64,
+    value: Box<dyn Any>,
+}
+
+/// Counters for tuning the capacity of a `KeyedCache`, kept since it
+/// was created.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Entries dropped to stay within the capacity. Those dropped by a
+    /// sweep or by clearing the cache aren't counted.
+    pub evictions: u64,
+}
+
+/// Memoizes values by a key chosen by the caller instead of by call
+/// site like `moxie::memo!`, so that a result can be found again after
+/// its call site has moved, e.g. because a sibling was inserted before
+/// it. Entries that went unused since the last `sweep` are dropped by
+/// the next one, and with a capacity set the least recently used ones
+/// are dropped as soon as there are too many.
+pub struct KeyedCache<K> {
+    entries: RefCell<HashMap<(K, TypeId), Entry>>,
+    /// The key of every entry by its `last_used`, oldest first, so that
+    /// eviction doesn't have to scan the entries.
+    recency: RefCell<BTreeMap<u64, (K, TypeId)>>,
+    capacity: Cell<Option<usize>>,
+    /// Counts lookups, to order entries by when they were last used.
+    clock: Cell<u64>,
+    stats: Cell<CacheStats>,
+}
+
+impl<K> KeyedCache<K>
+where
+    K: Clone + Eq + Hash,
+{
+    pub fn new() -> Self {
+        KeyedCache {
+            entries: RefCell::new(HashMap::new()),
+            recency: RefCell::new(BTreeMap::new()),
+            capacity: Cell::new(None),
+            clock: Cell::new(0),
+            stats: Cell::new(CacheStats::default()),
+        }
+    }
+
+    /// Limits the number of entries, evicting the least recently used
+    /// ones right away if there are already more. `None` lets the cache
+    /// grow until the next `sweep`.
+    pub fn set_capacity(&self, capacity: Option<usize>) {
+        self.capacity.set(capacity);
+        self.evict();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats.get()
+    }
+
+    fn tick(&self) -> u64 {
+        let now = self.clock.get() + 1;
+        self.clock.set(now);
+        now
+    }
+
+    fn count(&self, update: impl FnOnce(&mut CacheStats)) {
+        let mut stats = self.stats.get();
+        update(&mut stats);
+        self.stats.set(stats);
+    }
+
+    /// Drops least recently used entries until the cache is within its
+    /// capacity.
+    fn evict(&self) {
+        let capacity = match self.capacity.get() {
+            Some(capacity) => capacity,
+            None => return,
+        };
+        let mut entries = self.entries.borrow_mut();
+        let mut recency = self.recency.borrow_mut();
+        while entries.len() > capacity {
+            let oldest = match recency.keys().next() {
+                Some(&oldest) => oldest,
+                None => break,
+            };
+            let slot = recency.remove(&oldest).unwrap();
+            entries.remove(&slot);
+            self.count(|stats| stats.evictions += 1);
+        }
+    }
+
+    /// Returns the value stored for `key` if it was created from an
+    /// equal `arg`, otherwise creates and stores a new one with `init`.
+    pub fn memo<Arg, Out>(&self, key: K, arg: Arg, init: impl FnOnce(&Arg) -> Out) -> Out
+    where
+        Arg: PartialEq + 'static,
+        Out: Clone + 'static,
+    {
+        let slot = (key, TypeId::of::<(Arg, Out)>());
+        if let Some(entry) = self.entries.borrow_mut().get_mut(&slot) {
+            if let Some((stored, out)) = entry.value.downcast_ref::<(Arg, Out)>() {
+                if *stored == arg {
+                    let now = self.tick();
+                    let mut recency = self.recency.borrow_mut();
+                    recency.remove(&entry.last_used);
+                    recency.insert(now, slot.clone());
+                    entry.used = true;
+                    entry.last_used = now;
+                    self.count(|stats| stats.hits += 1);
+                    return out.clone();
+                }
+            }
+        }
+        self.count(|stats| stats.misses += 1);
+        // `init` may memoize values of its own, so the entries must not
+        // be borrowed while it runs.
+        let out = init(&arg);
+        let now = self.tick();
+        self.recency.borrow_mut().insert(now, slot.clone());
+        let replaced = self.entries.borrow_mut().insert(
+            slot,
+            Entry {
+                used: true,
+                last_used: now,
+                value: Box::new((arg, out.clone())),
+            },
+        );
+        if let Some(replaced) = replaced {
+            self.recency.borrow_mut().remove(&replaced.last_used);
+        }
+        self.evict();
+        out
+    }
+
+    /// Drops the value `memo` stored for `key` with an argument of type
+    /// `Arg`, for when something the argument doesn't capture changed.
+    pub fn forget<Arg, Out>(&self, key: K)
+    where
+        Arg: 'static,
+        Out: 'static,
+    {
+        let slot = (key, TypeId::of::<(Arg, Out)>());
+        if let Some(entry) = self.entries.borrow_mut().remove(&slot) {
+            self.recency.borrow_mut().remove(&entry.last_used);
+        }
+    }
+
+    /// Drops the entries that weren't used since the previous sweep.
+    pub fn sweep(&self) {
+        let mut recency = self.recency.borrow_mut();
+        self.entries.borrow_mut().retain(|_, entry| {
+            let keep = entry.used;
+            if !keep {
+                recency.remove(&entry.last_used);
+            }
+            entry.used = false;
+            keep
+        });
+    }
+
+    /// Drops every entry, keeping the capacity and the stats.
+    pub fn clear(&self) {
+        self.entries.borrow_mut().clear();
+        self.recency.borrow_mut().clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.borrow().is_empty()
+    }
+}
+
+impl<K> Default for KeyedCache<K>
+where
+    K: Clone + Eq + Hash,
+{
+    fn default() -> Self {
+        KeyedCache::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CacheStats, KeyedCache};
+    use std::cell::Cell;
+
+    #[test]
+    fn reuses_value_for_same_key_and_arg() {
+        let cache = KeyedCache::new();
+        let calls = Cell::new(0);
+        let init = |arg: &u32| {
+            calls.set(calls.get() + 1);
+            arg * 2
+        };
+        assert_eq!(cache.memo("a", 1, init), 2);
+        assert_eq!(cache.memo("b", 1, init), 2);
+        assert_eq!(cache.memo("a", 1, init), 2);
+        assert_eq!(calls.get(), 2);
+        assert_eq!(cache.memo("a", 2, init), 4);
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[test]
+    fn sweep_drops_unused_entries() {
+        let cache = KeyedCache::new();
+        cache.memo("a", 1, |arg| *arg);
+        cache.memo("b", 1, |arg| *arg);
+        cache.sweep();
+        assert_eq!(cache.len(), 2);
+        cache.memo("a", 1, |arg| *arg);
+        cache.sweep();
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn capacity_evicts_least_recently_used() {
+        let cache = KeyedCache::new();
+        cache.set_capacity(Some(2));
+        let calls = Cell::new(0);
+        let init = |arg: &u32| {
+            calls.set(calls.get() + 1);
+            *arg
+        };
+        cache.memo("a", 1, init);
+        cache.memo("b", 1, init);
+        cache.memo("a", 1, init);
+        // "b" is the least recently used, so it makes room for "c".
+        cache.memo("c", 1, init);
+        assert_eq!(cache.len(), 2);
+        cache.memo("a", 1, init);
+        assert_eq!(calls.get(), 3);
+        cache.memo("b", 1, init);
+        assert_eq!(calls.get(), 4);
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 2,
+                misses: 4,
+                evictions: 2,
+            }
+        );
+
+        cache.set_capacity(Some(1));
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.stats().evictions, 3);
+    }
+
+    #[test]
+    fn eviction_skips_entries_already_dropped() {
+        let cache = KeyedCache::new();
+        cache.memo("a", 1, |arg| *arg);
+        cache.memo("b", 1, |arg| *arg);
+        cache.memo("c", 1, |arg| *arg);
+        cache.forget::<u32, u32>("a");
+        cache.sweep();
+        cache.memo("c", 1, |arg| *arg);
+        cache.sweep();
+        // Only "c" is left, and it's replaced before it's evicted.
+        cache.memo("c", 2, |arg| *arg);
+        cache.memo("d", 1, |arg| *arg);
+        cache.set_capacity(Some(1));
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.memo("d", 1, |_| 0), 1);
+        assert_eq!(cache.stats().evictions, 1);
+    }
+}
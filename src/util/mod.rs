@@ -1,4 +1,5 @@
 pub mod color;
 pub mod equal_rc;
 pub mod event_handler;
+pub mod keyed_cache;
 pub mod word_break_iter;
@@ -1,13 +1,167 @@
 use std::iter::Peekable;
 use std::str::CharIndices;
 
+/// Decides where words may be broken, mirroring CSS `word-break`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WordBreakStrategy {
+    /// Break at whitespace, and around CJK characters since spaces are
+    /// uncommon in those scripts.
+    Normal,
+    /// Only break at whitespace.
+    KeepAll,
+    /// Allow a break between any two characters.
+    BreakAll,
+}
+
+impl WordBreakStrategy {
+    fn allows_break(self, prev: char, next: char) -> bool {
+        match self {
+            WordBreakStrategy::Normal => is_cjk(prev) || is_cjk(next),
+            WordBreakStrategy::KeepAll => false,
+            WordBreakStrategy::BreakAll => true,
+        }
+    }
+}
+
+/// Marks a break opportunity in text that otherwise has none, like a
+/// long URL. It takes up no space.
+pub const ZERO_WIDTH_SPACE: char = '\u{200B}';
+
+fn is_cjk(ch: char) -> bool {
+    match ch as u32 {
+        0x3000..=0x303F // CJK symbols and punctuation
+        | 0x3040..=0x30FF // Hiragana and Katakana
+        | 0x3400..=0x4DBF // CJK unified ideographs extension A
+        | 0x4E00..=0x9FFF // CJK unified ideographs
+        | 0xAC00..=0xD7AF // Hangul syllables
+        | 0xF900..=0xFAFF // CJK compatibility ideographs
+        | 0xFF00..=0xFFEF => true, // Halfwidth and fullwidth forms
+        _ => false,
+    }
+}
+
+const ZERO_WIDTH_JOINER: char = '\u{200D}';
+
+fn is_regional_indicator(ch: char) -> bool {
+    (0x1F1E6..=0x1F1FF).contains(&(ch as u32))
+}
+
+/// The vowel signs, viramas and other marks that the Brahmic scripts
+/// from Devanagari to Malayalam attach to the consonant before them.
+/// Those blocks share one layout, so the marks sit at the same offsets
+/// in each of them.
+fn is_indic_mark(ch: char) -> bool {
+    match ch as u32 {
+        0x0900..=0x0D7F => match ch as u32 & 0x7F {
+            0x01..=0x03 | 0x3A..=0x3C | 0x3E..=0x4D | 0x51..=0x57 | 0x62..=0x63 => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// The parts Hangul syllables are built from: leading consonants,
+/// vowels and trailing consonants, and the precomposed syllables with
+/// and without a trailing consonant.
+#[derive(Clone, Copy, PartialEq)]
+enum Jamo {
+    Leading,
+    Vowel,
+    Trailing,
+    Syllable,
+    ClosedSyllable,
+}
+
+fn jamo(ch: char) -> Option<Jamo> {
+    match ch as u32 {
+        0x1100..=0x115F | 0xA960..=0xA97F => Some(Jamo::Leading),
+        0x1160..=0x11A7 | 0xD7B0..=0xD7C6 => Some(Jamo::Vowel),
+        0x11A8..=0x11FF | 0xD7CB..=0xD7FB => Some(Jamo::Trailing),
+        code @ 0xAC00..=0xD7A3 if (code - 0xAC00) % 28 == 0 => Some(Jamo::Syllable),
+        0xAC00..=0xD7A3 => Some(Jamo::ClosedSyllable),
+        _ => None,
+    }
+}
+
+/// Whether `next` belongs to the same user-perceived character as
+/// `prev`, so that no break may go between them whatever the strategy:
+/// combining marks, like accents and the vowel signs of Indic scripts,
+/// and variation selectors belong to the character before them, emoji
+/// modifiers and tags to the emoji, and a zero width joiner holds
+/// together the characters on either side of it, as in the family
+/// emoji. Hangul jamo join into syllables, and regional indicators pair
+/// up into flags, which takes knowing whether `prev` is the first of a
+/// pair, given as `open_flag`. This is a rough cut of Unicode's
+/// extended grapheme clusters, covering what breaking inside a word
+/// runs into.
+pub fn continues_cluster(prev: char, next: char, open_flag: bool) -> bool {
+    if prev == ZERO_WIDTH_JOINER || next == ZERO_WIDTH_JOINER {
+        return true;
+    }
+    if open_flag && is_regional_indicator(prev) && is_regional_indicator(next) {
+        return true;
+    }
+    if let (Some(prev), Some(next)) = (jamo(prev), jamo(next)) {
+        return match prev {
+            Jamo::Leading => next != Jamo::Trailing,
+            Jamo::Vowel | Jamo::Syllable => next == Jamo::Vowel || next == Jamo::Trailing,
+            Jamo::Trailing | Jamo::ClosedSyllable => next == Jamo::Trailing,
+        };
+    }
+    if is_indic_mark(next) {
+        return true;
+    }
+    match next as u32 {
+        0x0300..=0x036F // Combining diacritical marks
+        | 0x1AB0..=0x1AFF // Combining diacritical marks extended
+        | 0x1DC0..=0x1DFF // Combining diacritical marks supplement
+        | 0x20D0..=0x20FF // Combining marks for symbols
+        | 0xFE00..=0xFE0F // Variation selectors
+        | 0xFE20..=0xFE2F // Combining half marks
+        | 0x1F3FB..=0x1F3FF // Emoji skin tone modifiers
+        | 0xE0020..=0xE007F // Tags
+        | 0xE0100..=0xE01EF => true, // Variation selectors supplement
+        _ => false,
+    }
+}
+
+/// Whether a flag is left open after `ch`, i.e. `ch` is a regional
+/// indicator that didn't complete one left open before it.
+fn opens_flag(ch: char, open_flag: bool) -> bool {
+    is_regional_indicator(ch) && !open_flag
+}
+
+/// The byte offsets in `text` where a character starts that doesn't
+/// continue the one before it, see `continues_cluster`. The start of
+/// the text isn't included.
+pub fn cluster_starts(text: &str) -> impl Iterator<Item = usize> + '_ {
+    let mut prev = None;
+    let mut open_flag = false;
+    text.char_indices().filter_map(move |(index, ch)| {
+        let starts = prev.map_or(false, |prev| !continues_cluster(prev, ch, open_flag));
+        prev = Some(ch);
+        open_flag = opens_flag(ch, open_flag);
+        if starts {
+            Some(index)
+        } else {
+            None
+        }
+    })
+}
+
 /// An iterator over word breaks. It leaves whitespace in, which
 /// distinguishes it from `str::split_whitespace()`, leaving it at the
-/// start of each item produced. This will also eventually insert break
-/// points between CJK characters, as spaces are not common.
+/// start of each item produced. Where else words are broken depends on
+/// the `WordBreakStrategy`, but a break is always allowed after a
+/// `ZERO_WIDTH_SPACE`, which stays at the end of the word before it.
+/// Characters that make up a single one on screen, like an emoji joined
+/// from several, are never broken apart.
 pub struct WordBreakIterator<'a> {
     string: &'a str,
     iter: Peekable<CharIndices<'a>>,
+    strategy: WordBreakStrategy,
+    soft_breaks: &'a [usize],
+    soft_break_base: usize,
 }
 
 impl<'a> Iterator for WordBreakIterator<'a> {
@@ -16,6 +170,8 @@ impl<'a> Iterator for WordBreakIterator<'a> {
     fn next(&mut self) -> Option<&'a str> {
         let mut first_index = None;
         let mut seen_non_ws = false;
+        let mut prev = None;
+        let mut open_flag = false;
         loop {
             let result = self.iter.peek();
             let index = if let Some(&(index, _)) = result {
@@ -31,7 +187,16 @@ impl<'a> Iterator for WordBreakIterator<'a> {
             } else {
                 true
             };
-            if seen_non_ws && is_whitespace_or_end {
+            let can_break = match (prev, result) {
+                (Some(ZERO_WIDTH_SPACE), Some(_)) => true,
+                (Some(prev), Some(&(_, ch))) if !continues_cluster(prev, ch, open_flag) => {
+                    let offset = self.soft_break_base + index;
+                    self.strategy.allows_break(prev, ch)
+                        || self.soft_breaks.binary_search(&offset).is_ok()
+                }
+                _ => false,
+            };
+            if seen_non_ws && (is_whitespace_or_end || can_break) {
                 return Some(&self.string[first_index.unwrap()..index]);
             }
             if !is_whitespace_or_end {
@@ -46,23 +211,44 @@ impl<'a> Iterator for WordBreakIterator<'a> {
                 }
             }
 
+            prev = result.map(|&(_, ch)| ch);
+            open_flag = prev.map_or(false, |ch| opens_flag(ch, open_flag));
             self.iter.next();
         }
     }
 }
 
 impl<'a> WordBreakIterator<'a> {
+    /// Creates an iterator which only breaks at whitespace.
     pub fn new(string: &'a str) -> WordBreakIterator<'a> {
+        Self::with_strategy(string, WordBreakStrategy::KeepAll)
+    }
+
+    pub fn with_strategy(string: &'a str, strategy: WordBreakStrategy) -> WordBreakIterator<'a> {
         WordBreakIterator {
             string,
             iter: string.char_indices().peekable(),
+            strategy,
+            soft_breaks: &[],
+            soft_break_base: 0,
         }
     }
+
+    /// Also allows breaks before the given sorted offsets, for text that
+    /// had its zero width spaces taken out. The offsets are into a text
+    /// that this iterator's string starts `base` bytes into.
+    pub fn with_soft_breaks(mut self, breaks: &'a [usize], base: usize) -> WordBreakIterator<'a> {
+        self.soft_breaks = breaks;
+        self.soft_break_base = base;
+        self
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use super::WordBreakIterator;
+    use super::{cluster_starts, WordBreakIterator, WordBreakStrategy};
+
+    const URL: &str = "example.com/\u{200B}some/\u{200B}path";
 
     #[test]
     fn test_iter() {
@@ -95,4 +281,112 @@ mod test {
         assert!(expect.len() == result.len());
         assert!(expect[0] == result[0]);
     }
+
+    const MIXED: &str = "ab 漢字cd";
+
+    #[test]
+    fn strategy_normal() {
+        let result =
+            WordBreakIterator::with_strategy(MIXED, WordBreakStrategy::Normal).collect::<Vec<_>>();
+        assert_eq!(result, vec!["ab", " 漢", "字", "cd"]);
+    }
+
+    #[test]
+    fn strategy_keep_all() {
+        let result =
+            WordBreakIterator::with_strategy(MIXED, WordBreakStrategy::KeepAll).collect::<Vec<_>>();
+        assert_eq!(result, vec!["ab", " 漢字cd"]);
+    }
+
+    #[test]
+    fn strategy_break_all() {
+        let result = WordBreakIterator::with_strategy(MIXED, WordBreakStrategy::BreakAll)
+            .collect::<Vec<_>>();
+        assert_eq!(result, vec!["a", "b", " 漢", "字", "c", "d"]);
+    }
+
+    const FAMILY: &str = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+
+    #[test]
+    fn break_all_keeps_clusters_whole() {
+        let text = format!("a{}e\u{301}", FAMILY);
+        let result = WordBreakIterator::with_strategy(&text, WordBreakStrategy::BreakAll)
+            .collect::<Vec<_>>();
+        assert_eq!(result, vec!["a", FAMILY, "e\u{301}"]);
+
+        let flags = "\u{1F1E9}\u{1F1EA}";
+        let result = WordBreakIterator::with_strategy(flags, WordBreakStrategy::BreakAll)
+            .collect::<Vec<_>>();
+        assert_eq!(result, vec![flags]);
+    }
+
+    #[test]
+    fn regional_indicators_pair_up_into_flags() {
+        // Germany, then France, then a lone indicator.
+        let text = "\u{1F1E9}\u{1F1EA}\u{1F1EB}\u{1F1F7}\u{1F1EE}";
+        let result =
+            WordBreakIterator::with_strategy(text, WordBreakStrategy::BreakAll).collect::<Vec<_>>();
+        assert_eq!(
+            result,
+            vec!["\u{1F1E9}\u{1F1EA}", "\u{1F1EB}\u{1F1F7}", "\u{1F1EE}"]
+        );
+        assert_eq!(cluster_starts(text).collect::<Vec<_>>(), vec![8, 16]);
+    }
+
+    #[test]
+    fn indic_vowel_signs_stay_on_their_consonant() {
+        // Hindi "किताब", with the vowel signs of the first two consonants.
+        let text = "\u{915}\u{93F}\u{924}\u{93E}\u{92C}";
+        let result =
+            WordBreakIterator::with_strategy(text, WordBreakStrategy::BreakAll).collect::<Vec<_>>();
+        assert_eq!(result, vec!["\u{915}\u{93F}", "\u{924}\u{93E}", "\u{92C}"]);
+        // Tamil "கோ" with its two-part vowel sign, and a virama.
+        assert_eq!(cluster_starts("\u{B95}\u{BCB}").count(), 0);
+        assert_eq!(cluster_starts("\u{B95}\u{BCD}\u{BB7}").count(), 1);
+    }
+
+    #[test]
+    fn hangul_jamo_join_into_syllables() {
+        // "한" spelled out in jamo, then "글" precomposed with a trailing
+        // jamo added.
+        let text = "\u{1112}\u{1161}\u{11AB}\u{AE00}\u{11AF}";
+        let result =
+            WordBreakIterator::with_strategy(text, WordBreakStrategy::BreakAll).collect::<Vec<_>>();
+        assert_eq!(result, vec!["\u{1112}\u{1161}\u{11AB}", "\u{AE00}\u{11AF}"]);
+        // A syllable without a trailing consonant takes a trailing jamo,
+        // but two syllables stay apart.
+        assert_eq!(cluster_starts("\u{AC00}\u{11A8}").count(), 0);
+        assert_eq!(
+            cluster_starts("\u{AC00}\u{AC00}").collect::<Vec<_>>(),
+            vec![3]
+        );
+    }
+
+    #[test]
+    fn clusters_start_after_joined_emoji() {
+        let text = format!("{}x", FAMILY);
+        let starts: Vec<_> = cluster_starts(&text).collect();
+        assert_eq!(starts, vec![FAMILY.len()]);
+        assert_eq!(cluster_starts("abc").collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    fn breaks_after_zero_width_space() {
+        let result = WordBreakIterator::new(URL).collect::<Vec<_>>();
+        assert_eq!(
+            result,
+            vec!["example.com/\u{200B}", "some/\u{200B}", "path"]
+        );
+    }
+
+    #[test]
+    fn breaks_at_soft_breaks() {
+        // The URL with its zero width spaces removed, starting at byte 4.
+        let text = "foo example.com/some/path";
+        let breaks = [16, 21];
+        let result = WordBreakIterator::new(&text[4..])
+            .with_soft_breaks(&breaks, 4)
+            .collect::<Vec<_>>();
+        assert_eq!(result, vec!["example.com/", "some/", "path"]);
+    }
 }
@@ -8,8 +8,8 @@ use {
     syn::spanned::Spanned,
     syn::token,
     syn::{
-        braced, parenthesized, parse_macro_input, Attribute as SynAttribute, Ident, Lit, LitInt,
-        Token, Visibility,
+        braced, bracketed, parenthesized, parse_macro_input, Attribute as SynAttribute, Ident, Lit,
+        LitBool, LitInt, LitStr, Path, Token, Visibility,
     },
 };
 
@@ -60,15 +60,27 @@ enum LengthItem {
     Ems(f32),
     ViewWidth(f32),
     ViewHeight(f32),
+    Percent(f32),
 }
 
 impl Parse for LengthItem {
     fn parse(input: ParseStream) -> Result<Self> {
-        let value = match input.parse::<Lit>()? {
-            Lit::Int(int) => int.base10_parse::<f32>()?,
-            Lit::Float(float) => float.base10_parse::<f32>()?,
-            _ => unimplemented!(),
+        let sign = if input.peek(Token![-]) {
+            input.parse::<Token![-]>()?;
+            -1.0
+        } else {
+            1.0
         };
+        let value = sign
+            * match input.parse::<Lit>()? {
+                Lit::Int(int) => int.base10_parse::<f32>()?,
+                Lit::Float(float) => float.base10_parse::<f32>()?,
+                _ => unimplemented!(),
+            };
+        if input.peek(Token![%]) {
+            input.parse::<Token![%]>()?;
+            return Ok(LengthItem::Percent(value));
+        }
         let ident = input.parse::<Ident>()?;
         match &ident.to_string()[..] {
             "px" => Ok(LengthItem::Pixels(value)),
@@ -77,7 +89,7 @@ impl Parse for LengthItem {
             "vh" => Ok(LengthItem::ViewHeight(value)),
             _ => Err(Error::new(
                 ident.span(),
-                "Expected one of px, em, vw, or vh",
+                "Expected one of px, em, vw, vh, or %",
             )),
         }
     }
@@ -96,6 +108,7 @@ struct LengthValues {
     ems: f32,
     view_width: f32,
     view_height: f32,
+    percent: f32,
 }
 
 impl Length {
@@ -114,6 +127,17 @@ impl Length {
         }
     }
 
+    /// Whether any term of the length is a percentage.
+    fn has_percent(&self) -> bool {
+        match self {
+            Length::Const(LengthItem::Percent(_)) => true,
+            Length::Const(_) => false,
+            Length::Add(left, right) | Length::Sub(left, right) => {
+                left.has_percent() || right.has_percent()
+            }
+        }
+    }
+
     fn eval(&self) -> LengthValues {
         match self {
             Length::Const(LengthItem::Pixels(value)) => LengthValues {
@@ -132,6 +156,10 @@ impl Length {
                 view_height: *value / 100.0,
                 ..Default::default()
             },
+            Length::Const(LengthItem::Percent(value)) => LengthValues {
+                percent: *value / 100.0,
+                ..Default::default()
+            },
             Length::Add(left, right) => {
                 let left = left.eval();
                 let right = right.eval();
@@ -140,6 +168,7 @@ impl Length {
                     ems: left.ems + right.ems,
                     view_width: left.view_width + right.view_width,
                     view_height: left.view_height + right.view_height,
+                    percent: left.percent + right.percent,
                 }
             }
             Length::Sub(left, right) => {
@@ -150,6 +179,7 @@ impl Length {
                     ems: left.ems - right.ems,
                     view_width: left.view_width - right.view_width,
                     view_height: left.view_height - right.view_height,
+                    percent: left.percent - right.percent,
                 }
             }
         }
@@ -169,12 +199,14 @@ impl ToTokens for Length {
             ems,
             view_width,
             view_height,
+            percent,
         } = self.eval();
         tokens.extend(quote!(::moxie_native::style::Value {
             pixels: #pixels,
             ems: #ems,
             view_width: #view_width,
             view_height: #view_height,
+            percent: #percent,
         }));
     }
 }
@@ -301,6 +333,14 @@ impl Parse for SideOffsets {
     }
 }
 
+impl SideOffsets {
+    fn has_percent(&self) -> bool {
+        [&self.left, &self.right, &self.top, &self.bottom]
+            .iter()
+            .any(|side| side.as_ref().map_or(false, Length::has_percent))
+    }
+}
+
 struct OptionLength(Option<Length>);
 
 impl ToTokens for OptionLength {
@@ -336,22 +376,82 @@ impl ToTokens for SideOffsets {
     }
 }
 
+struct Integer(i32);
+
+impl Parse for Integer {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let sign = if input.peek(Token![-]) {
+            input.parse::<Token![-]>()?;
+            -1
+        } else {
+            1
+        };
+        Ok(Integer(
+            sign * input.parse::<LitInt>()?.base10_parse::<i32>()?,
+        ))
+    }
+}
+
+/// A width to height ratio, given as one number or as two separated by
+/// a slash, e.g. `16 / 9`.
+struct Ratio(f32);
+
+fn parse_number(input: ParseStream) -> Result<f32> {
+    match input.parse::<Lit>()? {
+        Lit::Int(int) => int.base10_parse::<f32>(),
+        Lit::Float(float) => float.base10_parse::<f32>(),
+        lit => Err(Error::new(lit.span(), "Expected a number")),
+    }
+}
+
+impl Parse for Ratio {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let width = parse_number(input)?;
+        if input.peek(Token![/]) {
+            input.parse::<Token![/]>()?;
+            let height = parse_number(input)?;
+            return Ok(Ratio(width / height));
+        }
+        Ok(Ratio(width))
+    }
+}
+
 enum Value {
+    Bool(bool),
+    Integer(Integer),
+    Ratio(Ratio),
+    Number(f32),
     Length(Length),
     Color(Color),
     SideOffsets(SideOffsets),
     Enum(Ident, Ident),
+    Text(LitStr),
+    Strings(Vec<LitStr>),
+    CustomLayout(Path),
 }
 
 impl ToTokens for Value {
     fn to_tokens(&self, tokens: &mut TokenStream) {
         match self {
+            Value::Bool(value) => tokens.extend(quote!(Some(#value))),
+            Value::Integer(Integer(value)) => {
+                // Unsuffixed so that the literal takes on the attribute's type.
+                let value = proc_macro2::Literal::i32_unsuffixed(*value);
+                tokens.extend(quote!(Some(#value)))
+            }
+            Value::Ratio(Ratio(value)) => tokens.extend(quote!(Some(#value))),
+            Value::Number(value) => tokens.extend(quote!(Some(#value))),
             Value::Length(value) => tokens.extend(quote!(Some(#value))),
             Value::Color(value) => tokens.extend(quote!(Some(#value))),
             Value::SideOffsets(value) => value.to_tokens(tokens),
             Value::Enum(enum_ty, variant) => {
                 tokens.extend(quote!(Some(::moxie_native::style::#enum_ty::#variant)))
             }
+            Value::Text(value) => tokens.extend(quote!(Some(#value))),
+            Value::Strings(values) => tokens.extend(quote!(Some(&[#(#values),*]))),
+            Value::CustomLayout(path) => tokens.extend(quote!(Some(
+                ::moxie_native::layout::CustomLayoutRef(&#path)
+            ))),
         }
     }
 }
@@ -383,19 +483,76 @@ impl Enum {
 }
 
 enum AttributeType {
+    Bool,
+    Integer,
+    Ratio,
+    /// A plain number, like an angle in degrees.
+    Number,
     Length,
     Color,
     SideOffsets,
     Enum(Enum),
+    Text,
+    /// A list of strings in brackets, e.g. `["Fira Code", "monospace"]`.
+    Strings,
+    /// The path of a static that implements `CustomLayout`.
+    CustomLayout,
     Unknown,
 }
 
 impl AttributeType {
+    /// Percentages are resolved during layout, which only knows what to
+    /// resolve them against for these attributes.
+    fn accepts_percent(name: &str) -> bool {
+        match name {
+            "padding"
+            | "padding_inline_start"
+            | "padding_inline_end"
+            | "transform_origin_x"
+            | "transform_origin_y" => true,
+            _ => false,
+        }
+    }
+
     fn from_name(name: &str) -> AttributeType {
         match name {
-            "padding" | "margin" | "border_thickness" => AttributeType::SideOffsets,
-            "width" | "height" | "text_size" | "border_radius" => AttributeType::Length,
-            "text_color" | "background_color" | "border_color" => AttributeType::Color,
+            "padding" | "margin" | "border_thickness" | "inset" => AttributeType::SideOffsets,
+            "overflow_expand"
+            | "fill"
+            | "font_fallback"
+            | "collapse_whitespace"
+            | "show_whitespace"
+            | "layout_boundary" => AttributeType::Bool,
+            "font_stack" => AttributeType::Strings,
+            "custom_layout" => AttributeType::CustomLayout,
+            "ellipsis" => AttributeType::Text,
+            "order" | "max_lines" | "rows" | "font_weight" => AttributeType::Integer,
+            "width"
+            | "height"
+            | "min_width"
+            | "min_height"
+            | "max_width"
+            | "max_height"
+            | "text_size"
+            | "border_radius"
+            | "border_radius_vertical"
+            | "min_line_height"
+            | "baseline_grid"
+            | "gap"
+            | "paragraph_spacing"
+            | "baseline_spacing"
+            | "min_touch_target"
+            | "padding_inline_start"
+            | "padding_inline_end"
+            | "margin_inline_start"
+            | "margin_inline_end"
+            | "transform_origin_x"
+            | "transform_origin_y" => AttributeType::Length,
+            "text_color" | "background_color" | "border_color" | "placeholder" => {
+                AttributeType::Color
+            }
+            "aspect_ratio" => AttributeType::Ratio,
+            "rotate" => AttributeType::Number,
             "direction" => AttributeType::Enum(Enum {
                 name: "Direction",
                 variants: &[
@@ -409,6 +566,244 @@ impl AttributeType {
                     },
                 ],
             }),
+            "place_content" | "content_align_x" | "content_align_y" | "align_items" => {
+                AttributeType::Enum(Enum {
+                    name: "Alignment",
+                    variants: &[
+                        EnumItem {
+                            short_name: "start",
+                            canonical_name: "Start",
+                        },
+                        EnumItem {
+                            short_name: "center",
+                            canonical_name: "Center",
+                        },
+                        EnumItem {
+                            short_name: "end",
+                            canonical_name: "End",
+                        },
+                    ],
+                })
+            }
+            "font_style" => AttributeType::Enum(Enum {
+                name: "FontStyle",
+                variants: &[
+                    EnumItem {
+                        short_name: "normal",
+                        canonical_name: "Normal",
+                    },
+                    EnumItem {
+                        short_name: "italic",
+                        canonical_name: "Italic",
+                    },
+                ],
+            }),
+            "font_synthesis" => AttributeType::Enum(Enum {
+                name: "FontSynthesis",
+                variants: &[
+                    EnumItem {
+                        short_name: "auto",
+                        canonical_name: "Auto",
+                    },
+                    EnumItem {
+                        short_name: "none",
+                        canonical_name: "None",
+                    },
+                ],
+            }),
+            "control_characters" => AttributeType::Enum(Enum {
+                name: "ControlCharacters",
+                variants: &[
+                    EnumItem {
+                        short_name: "strip",
+                        canonical_name: "Strip",
+                    },
+                    EnumItem {
+                        short_name: "keep",
+                        canonical_name: "Keep",
+                    },
+                ],
+            }),
+            "missing_glyphs" => AttributeType::Enum(Enum {
+                name: "MissingGlyphs",
+                variants: &[
+                    EnumItem {
+                        short_name: "font",
+                        canonical_name: "Font",
+                    },
+                    EnumItem {
+                        short_name: "placeholder",
+                        canonical_name: "Placeholder",
+                    },
+                ],
+            }),
+            "text_positioning" => AttributeType::Enum(Enum {
+                name: "TextPositioning",
+                variants: &[
+                    EnumItem {
+                        short_name: "subpixel",
+                        canonical_name: "Subpixel",
+                    },
+                    EnumItem {
+                        short_name: "integer_snap",
+                        canonical_name: "IntegerSnap",
+                    },
+                ],
+            }),
+            "white_space" => AttributeType::Enum(Enum {
+                name: "WhiteSpace",
+                variants: &[
+                    EnumItem {
+                        short_name: "normal",
+                        canonical_name: "Normal",
+                    },
+                    EnumItem {
+                        short_name: "nowrap",
+                        canonical_name: "NoWrap",
+                    },
+                ],
+            }),
+            "text_align" | "text_align_last" => AttributeType::Enum(Enum {
+                name: "TextAlign",
+                variants: &[
+                    EnumItem {
+                        short_name: "left",
+                        canonical_name: "Left",
+                    },
+                    EnumItem {
+                        short_name: "center",
+                        canonical_name: "Center",
+                    },
+                    EnumItem {
+                        short_name: "right",
+                        canonical_name: "Right",
+                    },
+                    EnumItem {
+                        short_name: "justify",
+                        canonical_name: "Justify",
+                    },
+                ],
+            }),
+            "ellipsis_position" => AttributeType::Enum(Enum {
+                name: "EllipsisPosition",
+                variants: &[
+                    EnumItem {
+                        short_name: "start",
+                        canonical_name: "Start",
+                    },
+                    EnumItem {
+                        short_name: "middle",
+                        canonical_name: "Middle",
+                    },
+                    EnumItem {
+                        short_name: "end",
+                        canonical_name: "End",
+                    },
+                ],
+            }),
+            "text_direction" => AttributeType::Enum(Enum {
+                name: "TextDirection",
+                variants: &[
+                    EnumItem {
+                        short_name: "ltr",
+                        canonical_name: "Ltr",
+                    },
+                    EnumItem {
+                        short_name: "rtl",
+                        canonical_name: "Rtl",
+                    },
+                ],
+            }),
+            "box_sizing" => AttributeType::Enum(Enum {
+                name: "BoxSizing",
+                variants: &[
+                    EnumItem {
+                        short_name: "content_box",
+                        canonical_name: "ContentBox",
+                    },
+                    EnumItem {
+                        short_name: "border_box",
+                        canonical_name: "BorderBox",
+                    },
+                ],
+            }),
+            "position" => AttributeType::Enum(Enum {
+                name: "Position",
+                variants: &[
+                    EnumItem {
+                        short_name: "static",
+                        canonical_name: "Static",
+                    },
+                    EnumItem {
+                        short_name: "fixed",
+                        canonical_name: "Fixed",
+                    },
+                ],
+            }),
+            "overflow" | "overflow_x" | "overflow_y" => AttributeType::Enum(Enum {
+                name: "Overflow",
+                variants: &[
+                    EnumItem {
+                        short_name: "visible",
+                        canonical_name: "Visible",
+                    },
+                    EnumItem {
+                        short_name: "hidden",
+                        canonical_name: "Hidden",
+                    },
+                    EnumItem {
+                        short_name: "scroll",
+                        canonical_name: "Scroll",
+                    },
+                ],
+            }),
+            "child_overflow" => AttributeType::Enum(Enum {
+                name: "ChildOverflow",
+                variants: &[
+                    EnumItem {
+                        short_name: "visible",
+                        canonical_name: "Visible",
+                    },
+                    EnumItem {
+                        short_name: "clip",
+                        canonical_name: "Clip",
+                    },
+                    EnumItem {
+                        short_name: "shrink",
+                        canonical_name: "Shrink",
+                    },
+                ],
+            }),
+            "overflow_wrap" => AttributeType::Enum(Enum {
+                name: "OverflowWrap",
+                variants: &[
+                    EnumItem {
+                        short_name: "normal",
+                        canonical_name: "Normal",
+                    },
+                    EnumItem {
+                        short_name: "break_word",
+                        canonical_name: "BreakWord",
+                    },
+                ],
+            }),
+            "word_break" => AttributeType::Enum(Enum {
+                name: "WordBreakStrategy",
+                variants: &[
+                    EnumItem {
+                        short_name: "normal",
+                        canonical_name: "Normal",
+                    },
+                    EnumItem {
+                        short_name: "keep_all",
+                        canonical_name: "KeepAll",
+                    },
+                    EnumItem {
+                        short_name: "break_all",
+                        canonical_name: "BreakAll",
+                    },
+                ],
+            }),
             "display" => AttributeType::Enum(Enum {
                 name: "Display",
                 variants: &[
@@ -420,6 +815,10 @@ impl AttributeType {
                         short_name: "inline",
                         canonical_name: "Inline",
                     },
+                    EnumItem {
+                        short_name: "stack",
+                        canonical_name: "Stack",
+                    },
                 ],
             }),
             _ => AttributeType::Unknown,
@@ -432,9 +831,29 @@ impl Parse for Attribute {
         let name = input.parse::<Ident>()?;
         input.parse::<Token![:]>()?;
         let value = match AttributeType::from_name(name.to_string().as_ref()) {
+            AttributeType::Bool => Value::Bool(input.parse::<LitBool>()?.value),
+            AttributeType::Integer => Value::Integer(input.parse()?),
+            AttributeType::Ratio => Value::Ratio(input.parse()?),
+            AttributeType::Number => {
+                let sign = if input.peek(Token![-]) {
+                    input.parse::<Token![-]>()?;
+                    -1.0
+                } else {
+                    1.0
+                };
+                Value::Number(sign * parse_number(input)?)
+            }
             AttributeType::Length => Value::Length(input.parse()?),
             AttributeType::Color => Value::Color(input.parse()?),
             AttributeType::SideOffsets => Value::SideOffsets(input.parse()?),
+            AttributeType::Text => Value::Text(input.parse()?),
+            AttributeType::CustomLayout => Value::CustomLayout(input.parse()?),
+            AttributeType::Strings => {
+                let content;
+                bracketed!(content in input);
+                let values = Punctuated::<LitStr, Token![,]>::parse_terminated(&content)?;
+                Value::Strings(values.into_iter().collect())
+            }
             AttributeType::Enum(enum_ty) => {
                 let ident = input.parse::<Ident>()?;
                 if let Some(canonical) = enum_ty.lookup(&ident.to_string()[..]) {
@@ -459,6 +878,17 @@ impl Parse for Attribute {
             }
             AttributeType::Unknown => return Err(Error::new(name.span(), "Unknown attribute")),
         };
+        let percent = match &value {
+            Value::Length(length) => length.has_percent(),
+            Value::SideOffsets(offsets) => offsets.has_percent(),
+            _ => false,
+        };
+        if percent && !AttributeType::accepts_percent(name.to_string().as_ref()) {
+            return Err(Error::new(
+                name.span(),
+                "Percentages are only supported for padding and transform_origin",
+            ));
+        }
         Ok(Attribute { name, value })
     }
 }
@@ -608,8 +1038,34 @@ impl ToTokens for StyleList {
 
 #[cfg(test)]
 mod tests {
+    use super::{Attribute, Value};
+
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+
+    #[test]
+    fn percentages_only_parse_where_layout_resolves_them() {
+        let parse = |source| syn::parse_str::<Attribute>(source).is_ok();
+        assert!(parse("padding: 10% 0px 10% 0px"));
+        assert!(parse("padding_inline_start: 5% + 2px"));
+        assert!(parse("transform_origin_x: 0%"));
+        assert!(!parse("margin: 10% 0px 10% 0px"));
+        assert!(!parse("width: 50% - 4px"));
+        assert!(!parse("inset: 0px 10% 0px 0px"));
+        assert!(parse("width: 50vw - 4px"));
+    }
+
+    #[test]
+    fn transform_origin_percent_is_a_fraction() {
+        let attribute = syn::parse_str::<Attribute>("transform_origin_y: 25% + 4px").unwrap();
+        match attribute.value {
+            Value::Length(length) => {
+                let values = length.eval();
+                assert_eq!((values.percent, values.pixels), (0.25, 4.0));
+            }
+            _ => panic!("transform_origin_y should be a length"),
+        }
+    }
 }